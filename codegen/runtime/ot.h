@@ -0,0 +1,1304 @@
+/* Runtime of OT programs compiled to C, mirrors values and instructions of the OTVM */
+#ifndef OT_H
+#define OT_H
+
+#include <errno.h>
+#include <setjmp.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+
+#ifndef OT_NO_FFI
+#include <dlfcn.h>
+#endif
+
+/* numbers of `:ok` and `:err` are the same in every program */
+#define OT_ATOM_OK 0
+#define OT_ATOM_ERR 1
+
+/* kinds of errors, caught errors are `(pair kind message)` like in the VM */
+#define OT_ATOM_TYPE_ERROR 2
+#define OT_ATOM_ARITHMETIC_ERROR 3
+#define OT_ATOM_INDEX_ERROR 4
+#define OT_ATOM_ARITY_ERROR 5
+#define OT_ATOM_ASSERTION_ERROR 6
+#define OT_ATOM_STACK_OVERFLOW 7
+
+/* same order as variants of the VM value, values of different types are ordered by it */
+typedef enum {
+    OT_INT,
+    OT_ATOM,
+    OT_NIL,
+    OT_PAIR,
+    OT_ARRAY,
+    OT_STRING,
+    OT_LAMBDA,
+} ot_tag;
+
+typedef struct ot_object {
+    size_t refs;
+} ot_object;
+
+typedef struct {
+    ot_tag tag;
+    union {
+        int32_t i;
+        uint32_t atom;
+        ot_object *obj;
+    } as;
+} Value;
+
+typedef struct {
+    ot_object header;
+    Value left;
+    Value right;
+} ot_pair_t;
+
+typedef struct {
+    ot_object header;
+    size_t len;
+    Value *items;
+} ot_array_t;
+
+typedef struct {
+    ot_object header;
+    size_t len;
+    char data[]; /* null terminated */
+} ot_string_t;
+
+typedef struct {
+    ot_object header;
+    uint32_t first; /* index of first function */
+    uint8_t overloads; /* number of arity overloads */
+    size_t ctxc;
+    Value ctx[];
+} ot_lambda_t;
+
+#define OT_PAIR_OF(v) ((ot_pair_t *)(v).as.obj)
+#define OT_ARRAY_OF(v) ((ot_array_t *)(v).as.obj)
+#define OT_STRING_OF(v) ((ot_string_t *)(v).as.obj)
+#define OT_LAMBDA_OF(v) ((ot_lambda_t *)(v).as.obj)
+
+/* Values */
+
+static inline Value ot_int(int32_t i) {
+    Value v;
+    v.tag = OT_INT;
+    v.as.i = i;
+    return v;
+}
+
+static inline Value ot_atom(uint32_t atom) {
+    Value v;
+    v.tag = OT_ATOM;
+    v.as.atom = atom;
+    return v;
+}
+
+static inline Value ot_nil(void) {
+    Value v;
+    v.tag = OT_NIL;
+    v.as.i = 0;
+    return v;
+}
+
+static inline int ot_is_object(Value v) {
+    return v.tag >= OT_PAIR;
+}
+
+static inline void *ot_alloc(size_t size) {
+    void *ptr = malloc(size);
+    if (ptr == NULL) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    return ptr;
+}
+
+static inline Value ot_object_value(ot_tag tag, void *obj) {
+    Value v;
+    v.tag = tag;
+    v.as.obj = (ot_object *)obj;
+    v.as.obj->refs = 1;
+    return v;
+}
+
+static inline Value ot_retain(Value v) {
+    if (ot_is_object(v)) {
+        v.as.obj->refs++;
+    }
+    return v;
+}
+
+static inline void ot_release(Value v) {
+    size_t i;
+
+    if (!ot_is_object(v) || --v.as.obj->refs != 0) {
+        return;
+    }
+
+    switch (v.tag) {
+    case OT_PAIR:
+        ot_release(OT_PAIR_OF(v)->left);
+        ot_release(OT_PAIR_OF(v)->right);
+        break;
+    case OT_ARRAY:
+        for (i = 0; i < OT_ARRAY_OF(v)->len; i++) {
+            ot_release(OT_ARRAY_OF(v)->items[i]);
+        }
+        free(OT_ARRAY_OF(v)->items);
+        break;
+    case OT_LAMBDA:
+        for (i = 0; i < OT_LAMBDA_OF(v)->ctxc; i++) {
+            ot_release(OT_LAMBDA_OF(v)->ctx[i]);
+        }
+        break;
+    default:
+        break;
+    }
+
+    free(v.as.obj);
+}
+
+static inline Value ot_string(const char *data, size_t len) {
+    ot_string_t *s = (ot_string_t *)ot_alloc(sizeof(ot_string_t) + len + 1);
+    s->len = len;
+    memcpy(s->data, data, len);
+    s->data[len] = '\0';
+    return ot_object_value(OT_STRING, s);
+}
+
+/* takes ownership of both values */
+static inline Value ot_pair(Value left, Value right) {
+    ot_pair_t *p = (ot_pair_t *)ot_alloc(sizeof(ot_pair_t));
+    p->left = left;
+    p->right = right;
+    return ot_object_value(OT_PAIR, p);
+}
+
+/* array filled with nils */
+static inline Value ot_array(size_t len) {
+    size_t i;
+    ot_array_t *a = (ot_array_t *)ot_alloc(sizeof(ot_array_t));
+    a->len = len;
+    a->items = (Value *)ot_alloc(sizeof(Value) * (len == 0 ? 1 : len));
+    for (i = 0; i < len; i++) {
+        a->items[i] = ot_nil();
+    }
+    return ot_object_value(OT_ARRAY, a);
+}
+
+/* takes ownership of the items */
+static inline Value ot_array_from(const Value *items, size_t len) {
+    Value array = ot_array(len);
+    memcpy(OT_ARRAY_OF(array)->items, items, sizeof(Value) * len);
+    return array;
+}
+
+/* takes ownership of the context values */
+static inline Value ot_lambda(uint32_t first, uint8_t overloads, const Value *ctx, size_t ctxc) {
+    ot_lambda_t *l = (ot_lambda_t *)ot_alloc(sizeof(ot_lambda_t) + sizeof(Value) * ctxc);
+    l->first = first;
+    l->overloads = overloads;
+    l->ctxc = ctxc;
+    memcpy(l->ctx, ctx, sizeof(Value) * ctxc);
+    return ot_object_value(OT_LAMBDA, l);
+}
+
+static inline int ot_truthy(Value v) {
+    return !(v.tag == OT_NIL || (v.tag == OT_INT && v.as.i == 0));
+}
+
+static inline int ot_eq(Value a, Value b) {
+    size_t i;
+
+    if (a.tag != b.tag) {
+        return 0;
+    }
+
+    switch (a.tag) {
+    case OT_INT:
+        return a.as.i == b.as.i;
+    case OT_ATOM:
+        return a.as.atom == b.as.atom;
+    case OT_NIL:
+        return 1;
+    case OT_PAIR:
+        return ot_eq(OT_PAIR_OF(a)->left, OT_PAIR_OF(b)->left)
+            && ot_eq(OT_PAIR_OF(a)->right, OT_PAIR_OF(b)->right);
+    case OT_ARRAY:
+        if (OT_ARRAY_OF(a)->len != OT_ARRAY_OF(b)->len) {
+            return 0;
+        }
+        for (i = 0; i < OT_ARRAY_OF(a)->len; i++) {
+            if (!ot_eq(OT_ARRAY_OF(a)->items[i], OT_ARRAY_OF(b)->items[i])) {
+                return 0;
+            }
+        }
+        return 1;
+    case OT_STRING:
+        return OT_STRING_OF(a)->len == OT_STRING_OF(b)->len
+            && memcmp(OT_STRING_OF(a)->data, OT_STRING_OF(b)->data, OT_STRING_OF(a)->len) == 0;
+    case OT_LAMBDA:
+        if (OT_LAMBDA_OF(a)->first != OT_LAMBDA_OF(b)->first
+            || OT_LAMBDA_OF(a)->overloads != OT_LAMBDA_OF(b)->overloads
+            || OT_LAMBDA_OF(a)->ctxc != OT_LAMBDA_OF(b)->ctxc) {
+            return 0;
+        }
+        for (i = 0; i < OT_LAMBDA_OF(a)->ctxc; i++) {
+            if (!ot_eq(OT_LAMBDA_OF(a)->ctx[i], OT_LAMBDA_OF(b)->ctx[i])) {
+                return 0;
+            }
+        }
+        return 1;
+    }
+
+    return 0;
+}
+
+#define OT_CMP(a, b) ((a) < (b) ? -1 : (a) > (b) ? 1 : 0)
+
+static inline int ot_cmp_values(const Value *a, size_t alen, const Value *b, size_t blen);
+
+/* returns negative, zero or positive number like strcmp */
+static inline int ot_cmp(Value a, Value b) {
+    int c;
+
+    if (a.tag != b.tag) {
+        return OT_CMP(a.tag, b.tag);
+    }
+
+    switch (a.tag) {
+    case OT_INT:
+        return OT_CMP(a.as.i, b.as.i);
+    case OT_ATOM:
+        return OT_CMP(a.as.atom, b.as.atom);
+    case OT_NIL:
+        return 0;
+    case OT_PAIR:
+        c = ot_cmp(OT_PAIR_OF(a)->left, OT_PAIR_OF(b)->left);
+        return c != 0 ? c : ot_cmp(OT_PAIR_OF(a)->right, OT_PAIR_OF(b)->right);
+    case OT_ARRAY:
+        return ot_cmp_values(OT_ARRAY_OF(a)->items, OT_ARRAY_OF(a)->len,
+                             OT_ARRAY_OF(b)->items, OT_ARRAY_OF(b)->len);
+    case OT_STRING:
+        c = memcmp(OT_STRING_OF(a)->data, OT_STRING_OF(b)->data,
+                   OT_STRING_OF(a)->len < OT_STRING_OF(b)->len ? OT_STRING_OF(a)->len : OT_STRING_OF(b)->len);
+        return c != 0 ? c : OT_CMP(OT_STRING_OF(a)->len, OT_STRING_OF(b)->len);
+    case OT_LAMBDA:
+        if (OT_LAMBDA_OF(a)->first != OT_LAMBDA_OF(b)->first) {
+            return OT_CMP(OT_LAMBDA_OF(a)->first, OT_LAMBDA_OF(b)->first);
+        }
+        if (OT_LAMBDA_OF(a)->overloads != OT_LAMBDA_OF(b)->overloads) {
+            return OT_CMP(OT_LAMBDA_OF(a)->overloads, OT_LAMBDA_OF(b)->overloads);
+        }
+        return ot_cmp_values(OT_LAMBDA_OF(a)->ctx, OT_LAMBDA_OF(a)->ctxc,
+                             OT_LAMBDA_OF(b)->ctx, OT_LAMBDA_OF(b)->ctxc);
+    }
+
+    return 0;
+}
+
+static inline int ot_cmp_values(const Value *a, size_t alen, const Value *b, size_t blen) {
+    size_t i;
+    int c;
+
+    for (i = 0; i < alen && i < blen; i++) {
+        c = ot_cmp(a[i], b[i]);
+        if (c != 0) {
+            return c;
+        }
+    }
+    return OT_CMP(alen, blen);
+}
+
+/* Formatting */
+
+typedef struct {
+    char *data;
+    size_t len;
+    size_t cap;
+} ot_buf;
+
+static inline void ot_buf_push(ot_buf *buf, const char *s, size_t len) {
+    if (buf->len + len + 1 > buf->cap) {
+        buf->cap = (buf->len + len + 1) * 2;
+        buf->data = (char *)realloc(buf->data, buf->cap);
+        if (buf->data == NULL) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    memcpy(buf->data + buf->len, s, len);
+    buf->len += len;
+    buf->data[buf->len] = '\0';
+}
+
+static inline void ot_buf_str(ot_buf *buf, const char *s) {
+    ot_buf_push(buf, s, strlen(s));
+}
+
+static inline void ot_buf_uint(ot_buf *buf, const char *fmt, unsigned long n) {
+    char number[64];
+    snprintf(number, sizeof(number), fmt, n);
+    ot_buf_str(buf, number);
+}
+
+static inline void ot_buf_int(ot_buf *buf, int32_t i) {
+    char number[16];
+    snprintf(number, sizeof(number), "%d", (int)i);
+    ot_buf_str(buf, number);
+}
+
+static inline void ot_debug(ot_buf *buf, Value v);
+
+static inline void ot_debug_values(ot_buf *buf, const Value *values, size_t len) {
+    size_t i;
+
+    ot_buf_str(buf, "[");
+    for (i = 0; i < len; i++) {
+        if (i != 0) {
+            ot_buf_str(buf, ", ");
+        }
+        ot_debug(buf, values[i]);
+    }
+    ot_buf_str(buf, "]");
+}
+
+/* same output as the `Debug` formatting of VM values */
+static inline void ot_debug(ot_buf *buf, Value v) {
+    size_t i;
+
+    switch (v.tag) {
+    case OT_INT:
+        ot_buf_str(buf, "Int(");
+        ot_buf_int(buf, v.as.i);
+        ot_buf_str(buf, ")");
+        break;
+    case OT_ATOM:
+        ot_buf_uint(buf, "Atom(%lu)", v.as.atom);
+        break;
+    case OT_NIL:
+        ot_buf_str(buf, "Nil");
+        break;
+    case OT_PAIR:
+        ot_buf_str(buf, "Pair((");
+        ot_debug(buf, OT_PAIR_OF(v)->left);
+        ot_buf_str(buf, ", ");
+        ot_debug(buf, OT_PAIR_OF(v)->right);
+        ot_buf_str(buf, "))");
+        break;
+    case OT_ARRAY:
+        ot_buf_str(buf, "Array(RefCell { value: ");
+        ot_debug_values(buf, OT_ARRAY_OF(v)->items, OT_ARRAY_OF(v)->len);
+        ot_buf_str(buf, " })");
+        break;
+    case OT_STRING:
+        ot_buf_str(buf, "String(\"");
+        for (i = 0; i < OT_STRING_OF(v)->len; i++) {
+            unsigned char c = (unsigned char)OT_STRING_OF(v)->data[i];
+            switch (c) {
+            case '\t': ot_buf_str(buf, "\\t"); break;
+            case '\r': ot_buf_str(buf, "\\r"); break;
+            case '\n': ot_buf_str(buf, "\\n"); break;
+            case '\'': ot_buf_str(buf, "\\'"); break;
+            case '"': ot_buf_str(buf, "\\\""); break;
+            case '\\': ot_buf_str(buf, "\\\\"); break;
+            default:
+                if (c >= 0x20 && c < 0x7f) {
+                    ot_buf_push(buf, (const char *)&c, 1);
+                } else {
+                    ot_buf_uint(buf, "\\x%02lx", c);
+                }
+            }
+        }
+        ot_buf_str(buf, "\")");
+        break;
+    case OT_LAMBDA:
+        ot_buf_uint(buf, "Lambda((%lu, ", OT_LAMBDA_OF(v)->first);
+        ot_buf_uint(buf, "%lu, ", OT_LAMBDA_OF(v)->overloads);
+        ot_debug_values(buf, OT_LAMBDA_OF(v)->ctx, OT_LAMBDA_OF(v)->ctxc);
+        ot_buf_str(buf, "))");
+        break;
+    }
+}
+
+/* same output as the `Display` formatting of VM values */
+static inline void ot_display(ot_buf *buf, Value v) {
+    switch (v.tag) {
+    case OT_INT:
+        ot_buf_int(buf, v.as.i);
+        break;
+    case OT_ATOM:
+        ot_buf_uint(buf, "atom(%lu)", v.as.atom);
+        break;
+    case OT_NIL:
+        ot_buf_str(buf, "nil");
+        break;
+    case OT_PAIR:
+        ot_buf_str(buf, "{");
+        ot_display(buf, OT_PAIR_OF(v)->left);
+        ot_buf_str(buf, ";");
+        ot_display(buf, OT_PAIR_OF(v)->right);
+        ot_buf_str(buf, "}");
+        break;
+    case OT_ARRAY:
+        ot_debug_values(buf, OT_ARRAY_OF(v)->items, OT_ARRAY_OF(v)->len);
+        break;
+    case OT_STRING:
+        ot_buf_push(buf, OT_STRING_OF(v)->data, OT_STRING_OF(v)->len);
+        break;
+    case OT_LAMBDA:
+        ot_buf_str(buf, "lambda");
+        break;
+    }
+}
+
+/* Errors, printed the same way as VM errors, only the failed function is in the trace */
+
+/* `try` installs a handler in the frame of `ot_run` running it, thrown value is in `ot_thrown` */
+typedef struct ot_handler {
+    jmp_buf jump;
+    struct ot_handler *prev;
+} ot_handler;
+
+static ot_handler *ot_handlers = NULL;
+static Value ot_thrown;
+
+static inline void ot_report(const char *location, ot_buf *msg) {
+    fflush(stdout);
+    fprintf(stderr, "\x1b[31m\x1b[1mVM ERROR:\x1b[0m %s\n    at %s\n", msg->data, location);
+    exit(1);
+}
+
+/* values of unwound frames aren't released */
+static inline void ot_fail(uint32_t kind, const char *location, ot_buf *msg) {
+    if (ot_handlers != NULL) {
+        ot_thrown = ot_pair(ot_atom(kind), ot_string(msg->data, msg->len));
+        free(msg->data);
+        longjmp(ot_handlers->jump, 1);
+    }
+    ot_report(location, msg);
+}
+
+static inline void ot_error(const char *location, ot_buf *msg) {
+    ot_fail(OT_ATOM_TYPE_ERROR, location, msg);
+}
+
+static inline void ot_fail_str(uint32_t kind, const char *location, const char *msg) {
+    ot_buf buf = {NULL, 0, 0};
+    ot_buf_str(&buf, msg);
+    ot_fail(kind, location, &buf);
+}
+
+static inline void ot_error_str(const char *location, const char *msg) {
+    ot_fail_str(OT_ATOM_TYPE_ERROR, location, msg);
+}
+
+static inline void ot_fail_values(uint32_t kind, const char *location, const char *before, Value first,
+                                  const char *middle, Value second, const char *after, int debug) {
+    ot_buf buf = {NULL, 0, 0};
+    ot_buf_str(&buf, before);
+    if (debug) {
+        ot_debug(&buf, first);
+    } else {
+        ot_display(&buf, first);
+    }
+    ot_buf_str(&buf, middle);
+    if (debug) {
+        ot_debug(&buf, second);
+    } else {
+        ot_display(&buf, second);
+    }
+    ot_buf_str(&buf, after);
+    ot_fail(kind, location, &buf);
+}
+
+static inline void ot_error_values(const char *location, const char *before, Value first,
+                                   const char *middle, Value second, const char *after, int debug) {
+    ot_fail_values(OT_ATOM_TYPE_ERROR, location, before, first, middle, second, after, debug);
+}
+
+static inline void ot_error_value(const char *location, Value v, const char *after) {
+    ot_buf buf = {NULL, 0, 0};
+    ot_display(&buf, v);
+    ot_buf_str(&buf, after);
+    ot_error(location, &buf);
+}
+
+/* Instructions, arguments are consumed unless stated otherwise */
+
+/* integers wrap around on overflow */
+#define OT_WRAP(a, op, b) ((int32_t)(uint32_t)((uint32_t)(a) op (uint32_t)(b)))
+
+static inline Value ot_add(Value a, Value b, const char *location) {
+    if (a.tag != OT_INT || b.tag != OT_INT) {
+        ot_error_values(location, "Can't add ", a, " and ", b, "", 1);
+    }
+    return ot_int(OT_WRAP(a.as.i, +, b.as.i));
+}
+
+static inline Value ot_sub(Value a, Value b, const char *location) {
+    if (a.tag != OT_INT || b.tag != OT_INT) {
+        ot_error_values(location, "Can't sub ", a, " and ", b, "", 1);
+    }
+    return ot_int(OT_WRAP(a.as.i, -, b.as.i));
+}
+
+static inline Value ot_mul(Value a, Value b, const char *location) {
+    if (a.tag != OT_INT || b.tag != OT_INT) {
+        ot_error_values(location, "Can't mul ", a, " and ", b, "", 1);
+    }
+    return ot_int(OT_WRAP(a.as.i, *, b.as.i));
+}
+
+static inline Value ot_div(Value a, Value b, const char *location) {
+    if (a.tag != OT_INT || b.tag != OT_INT) {
+        ot_error_values(location, "Can't div ", a, " and ", b, "", 1);
+    }
+    if (b.as.i == 0) {
+        ot_fail_str(OT_ATOM_ARITHMETIC_ERROR, location, "Division by zero");
+    }
+    if (b.as.i == -1) {
+        return ot_int(OT_WRAP(0, -, a.as.i));
+    }
+    return ot_int(a.as.i / b.as.i);
+}
+
+static inline Value ot_print(Value v) {
+    ot_buf buf = {NULL, 0, 0};
+    ot_display(&buf, v);
+    if (buf.len != 0) {
+        fwrite(buf.data, 1, buf.len, stdout);
+    }
+    free(buf.data);
+    ot_release(v);
+    return ot_nil();
+}
+
+static inline Value ot_pair_left(Value p) {
+    Value v = ot_nil();
+    if (p.tag == OT_PAIR) {
+        v = ot_retain(OT_PAIR_OF(p)->left);
+    }
+    ot_release(p);
+    return v;
+}
+
+static inline Value ot_pair_right(Value p) {
+    Value v = ot_nil();
+    if (p.tag == OT_PAIR) {
+        v = ot_retain(OT_PAIR_OF(p)->right);
+    }
+    ot_release(p);
+    return v;
+}
+
+static inline Value ot_new_array(Value size, const char *location) {
+    if (size.tag != OT_INT) {
+        ot_error_value(location, size, " must be an integer");
+    }
+    if (size.as.i < 0) {
+        ot_fail_str(OT_ATOM_INDEX_ERROR, location, "array size can't be negative");
+    }
+    return ot_array((size_t)size.as.i);
+}
+
+static inline Value ot_array_get(Value array, Value index, const char *location) {
+    Value v = ot_nil();
+    if (array.tag != OT_ARRAY || index.tag != OT_INT) {
+        ot_error_values(location, "", array, " has to be array and ", index, " has to be integer", 0);
+    }
+    if (index.as.i >= 0 && (size_t)index.as.i < OT_ARRAY_OF(array)->len) {
+        v = ot_retain(OT_ARRAY_OF(array)->items[index.as.i]);
+    }
+    ot_release(array);
+    return v;
+}
+
+/* returns the array */
+static inline Value ot_array_set(Value array, Value index, Value value, const char *location) {
+    if (array.tag != OT_ARRAY || index.tag != OT_INT) {
+        ot_error_values(location, "", array, " has to be array and ", index, " has to be integer", 0);
+    }
+    if (index.as.i < 0 || (size_t)index.as.i >= OT_ARRAY_OF(array)->len) {
+        ot_fail_str(OT_ATOM_INDEX_ERROR, location, "index out of bounds");
+    }
+    ot_release(OT_ARRAY_OF(array)->items[index.as.i]);
+    OT_ARRAY_OF(array)->items[index.as.i] = value;
+    return array;
+}
+
+static inline Value ot_array_len(Value array, const char *location) {
+    int32_t len;
+    if (array.tag != OT_ARRAY) {
+        ot_error_value(location, array, " is not array");
+    }
+    len = (int32_t)OT_ARRAY_OF(array)->len;
+    ot_release(array);
+    return ot_int(len);
+}
+
+/* Strings, indexed by characters of their UTF-8 encoding like in the VM */
+
+static inline void ot_check_string(Value s, const char *location) {
+    if (s.tag != OT_STRING) {
+        ot_error_value(location, s, " is not a string");
+    }
+}
+
+static inline void ot_check_int(Value i, const char *location) {
+    if (i.tag != OT_INT) {
+        ot_error_value(location, i, " is not an integer");
+    }
+}
+
+/* number of characters in the first len bytes */
+static inline size_t ot_utf8_len(const char *data, size_t len) {
+    size_t i, chars = 0;
+    for (i = 0; i < len; i++) {
+        if (((unsigned char)data[i] & 0xc0) != 0x80) {
+            chars++;
+        }
+    }
+    return chars;
+}
+
+/* byte offset of the character at index, index can be the number of characters */
+static inline size_t ot_utf8_offset(const char *data, size_t len, size_t index) {
+    size_t i = 0;
+    while (index > 0 && i < len) {
+        i++;
+        while (i < len && ((unsigned char)data[i] & 0xc0) == 0x80) {
+            i++;
+        }
+        index--;
+    }
+    return i;
+}
+
+static inline Value ot_string_len(Value s, const char *location) {
+    int32_t len;
+    ot_check_string(s, location);
+    len = (int32_t)ot_utf8_len(OT_STRING_OF(s)->data, OT_STRING_OF(s)->len);
+    ot_release(s);
+    return ot_int(len);
+}
+
+static inline Value ot_string_concat(Value a, Value b, const char *location) {
+    Value v;
+    ot_string_t *s;
+    if (a.tag != OT_STRING || b.tag != OT_STRING) {
+        ot_error_values(location, "Can't concat ", a, " and ", b, "", 1);
+    }
+    s = (ot_string_t *)ot_alloc(sizeof(ot_string_t) + OT_STRING_OF(a)->len + OT_STRING_OF(b)->len + 1);
+    s->len = OT_STRING_OF(a)->len + OT_STRING_OF(b)->len;
+    memcpy(s->data, OT_STRING_OF(a)->data, OT_STRING_OF(a)->len);
+    memcpy(s->data + OT_STRING_OF(a)->len, OT_STRING_OF(b)->data, OT_STRING_OF(b)->len + 1);
+    v = ot_object_value(OT_STRING, s);
+    ot_release(a);
+    ot_release(b);
+    return v;
+}
+
+static inline Value ot_substring(Value s, Value start, Value end, const char *location) {
+    Value v;
+    size_t chars, from, to;
+    ot_buf buf = {NULL, 0, 0};
+
+    ot_check_string(s, location);
+    ot_check_int(start, location);
+    ot_check_int(end, location);
+
+    chars = ot_utf8_len(OT_STRING_OF(s)->data, OT_STRING_OF(s)->len);
+    if (start.as.i < 0 || end.as.i < start.as.i || (size_t)end.as.i > chars) {
+        ot_buf_str(&buf, "substring ");
+        ot_buf_int(&buf, start.as.i);
+        ot_buf_str(&buf, "..");
+        ot_buf_int(&buf, end.as.i);
+        ot_buf_uint(&buf, " out of bounds of string of length %lu", chars);
+        ot_fail(OT_ATOM_INDEX_ERROR, location, &buf);
+    }
+
+    from = ot_utf8_offset(OT_STRING_OF(s)->data, OT_STRING_OF(s)->len, (size_t)start.as.i);
+    to = ot_utf8_offset(OT_STRING_OF(s)->data, OT_STRING_OF(s)->len, (size_t)end.as.i);
+    v = ot_string(OT_STRING_OF(s)->data + from, to - from);
+    ot_release(s);
+    return v;
+}
+
+/* code point of the character at the index like `'a'`, nil when it is out of bounds */
+static inline Value ot_char_at(Value s, Value index, const char *location) {
+    Value v = ot_nil();
+    const unsigned char *c;
+    int32_t point;
+    size_t i, len;
+
+    ot_check_string(s, location);
+    ot_check_int(index, location);
+
+    len = OT_STRING_OF(s)->len;
+    if (index.as.i >= 0 && (size_t)index.as.i < ot_utf8_len(OT_STRING_OF(s)->data, len)) {
+        i = ot_utf8_offset(OT_STRING_OF(s)->data, len, (size_t)index.as.i);
+        c = (const unsigned char *)OT_STRING_OF(s)->data + i;
+        if (c[0] < 0x80) {
+            point = c[0];
+        } else if (c[0] < 0xe0) {
+            point = ((c[0] & 0x1f) << 6) | (c[1] & 0x3f);
+        } else if (c[0] < 0xf0) {
+            point = ((c[0] & 0x0f) << 12) | ((c[1] & 0x3f) << 6) | (c[2] & 0x3f);
+        } else {
+            point = ((c[0] & 0x07) << 18) | ((c[1] & 0x3f) << 12) | ((c[2] & 0x3f) << 6) | (c[3] & 0x3f);
+        }
+        v = ot_int(point);
+    }
+    ot_release(s);
+    return v;
+}
+
+/* string of one character with the code point, the null character can't be in strings */
+static inline Value ot_char_to_string(Value c, const char *location) {
+    char data[4];
+    int32_t point = c.as.i;
+
+    if (c.tag != OT_INT || point <= 0 || point > 0x10ffff || (point >= 0xd800 && point <= 0xdfff)) {
+        ot_error_value(location, c, " is not a character");
+    }
+
+    if (point < 0x80) {
+        data[0] = (char)point;
+        return ot_string(data, 1);
+    }
+    if (point < 0x800) {
+        data[0] = (char)(0xc0 | (point >> 6));
+        data[1] = (char)(0x80 | (point & 0x3f));
+        return ot_string(data, 2);
+    }
+    if (point < 0x10000) {
+        data[0] = (char)(0xe0 | (point >> 12));
+        data[1] = (char)(0x80 | ((point >> 6) & 0x3f));
+        data[2] = (char)(0x80 | (point & 0x3f));
+        return ot_string(data, 3);
+    }
+    data[0] = (char)(0xf0 | (point >> 18));
+    data[1] = (char)(0x80 | ((point >> 12) & 0x3f));
+    data[2] = (char)(0x80 | ((point >> 6) & 0x3f));
+    data[3] = (char)(0x80 | (point & 0x3f));
+    return ot_string(data, 4);
+}
+
+/* nil when the string isn't a decimal integer with an optional sign */
+static inline Value ot_string_to_int(Value s, const char *location) {
+    const char *c;
+    int negative = 0;
+    int64_t n = 0;
+
+    ot_check_string(s, location);
+    c = OT_STRING_OF(s)->data;
+    if (*c == '-' || *c == '+') {
+        negative = *c == '-';
+        c++;
+    }
+    if (*c == '\0') {
+        ot_release(s);
+        return ot_nil();
+    }
+    for (; *c != '\0'; c++) {
+        if (*c < '0' || *c > '9' || (n = n * 10 + (*c - '0')) > 2147483648LL) {
+            ot_release(s);
+            return ot_nil();
+        }
+    }
+    ot_release(s);
+    if (!negative && n > 2147483647LL) {
+        return ot_nil();
+    }
+    return ot_int((int32_t)(negative ? -n : n));
+}
+
+static inline Value ot_int_to_string(Value i, const char *location) {
+    char number[16];
+    ot_check_int(i, location);
+    snprintf(number, sizeof(number), "%d", (int)i.as.i);
+    return ot_string(number, strlen(number));
+}
+
+/* same text as `print` writes */
+static inline Value ot_to_string(Value v) {
+    Value s;
+    ot_buf buf = {NULL, 0, 0};
+    if (v.tag == OT_STRING) {
+        return v;
+    }
+    ot_display(&buf, v);
+    s = ot_string(buf.data == NULL ? "" : buf.data, buf.len);
+    free(buf.data);
+    ot_release(v);
+    return s;
+}
+
+/* list of parts between separators, empty separator splits the string into characters */
+static inline Value ot_string_split(Value s, Value separator, const char *location) {
+    Value list = ot_nil();
+    Value *tail = &list; /* items are appended to the end */
+    const char *data, *found;
+    size_t len, seplen, i, next;
+
+    ot_check_string(s, location);
+    ot_check_string(separator, location);
+    data = OT_STRING_OF(s)->data;
+    len = OT_STRING_OF(s)->len;
+    seplen = OT_STRING_OF(separator)->len;
+
+    if (seplen == 0) {
+        for (i = 0; i < len; i = next) {
+            next = i + ot_utf8_offset(data + i, len - i, 1);
+            *tail = ot_pair(ot_string(data + i, next - i), ot_nil());
+            tail = &OT_PAIR_OF(*tail)->right;
+        }
+    } else {
+        for (i = 0;; i = next + seplen) {
+            found = strstr(data + i, OT_STRING_OF(separator)->data);
+            next = found == NULL ? len : (size_t)(found - data);
+            *tail = ot_pair(ot_string(data + i, next - i), ot_nil());
+            tail = &OT_PAIR_OF(*tail)->right;
+            if (found == NULL) {
+                break;
+            }
+        }
+    }
+
+    ot_release(s);
+    ot_release(separator);
+    return list;
+}
+
+/* items of the list converted to strings with the separator between them */
+static inline Value ot_string_join(Value list, Value separator, const char *location) {
+    Value v, rest = list;
+    ot_buf buf = {NULL, 0, 0};
+
+    ot_check_string(separator, location);
+    while (rest.tag == OT_PAIR) {
+        if (rest.as.obj != list.as.obj) {
+            ot_buf_push(&buf, OT_STRING_OF(separator)->data, OT_STRING_OF(separator)->len);
+        }
+        ot_display(&buf, OT_PAIR_OF(rest)->left);
+        rest = OT_PAIR_OF(rest)->right;
+    }
+    if (rest.tag != OT_NIL) {
+        ot_error_value(location, rest, " is not a list");
+    }
+
+    v = ot_string(buf.data == NULL ? "" : buf.data, buf.len);
+    free(buf.data);
+    ot_release(list);
+    ot_release(separator);
+    return v;
+}
+
+/* index of the first character of the first occurrence, nil when there is none */
+static inline Value ot_string_index_of(Value s, Value part, const char *location) {
+    Value v = ot_nil();
+    const char *found;
+
+    ot_check_string(s, location);
+    ot_check_string(part, location);
+    found = strstr(OT_STRING_OF(s)->data, OT_STRING_OF(part)->data);
+    if (found != NULL) {
+        v = ot_int((int32_t)ot_utf8_len(OT_STRING_OF(s)->data, (size_t)(found - OT_STRING_OF(s)->data)));
+    }
+    ot_release(s);
+    ot_release(part);
+    return v;
+}
+
+/* only ASCII letters are changed */
+static inline Value ot_string_case(Value s, int upper, const char *location) {
+    Value v;
+    size_t i;
+    char *data;
+
+    ot_check_string(s, location);
+    v = ot_string(OT_STRING_OF(s)->data, OT_STRING_OF(s)->len);
+    data = OT_STRING_OF(v)->data;
+    for (i = 0; i < OT_STRING_OF(v)->len; i++) {
+        if (upper && data[i] >= 'a' && data[i] <= 'z') {
+            data[i] = (char)(data[i] - 'a' + 'A');
+        } else if (!upper && data[i] >= 'A' && data[i] <= 'Z') {
+            data[i] = (char)(data[i] - 'A' + 'a');
+        }
+    }
+    ot_release(s);
+    return v;
+}
+
+static inline Value ot_string_upper(Value s, const char *location) {
+    return ot_string_case(s, 1, location);
+}
+
+static inline Value ot_string_lower(Value s, const char *location) {
+    return ot_string_case(s, 0, location);
+}
+
+/* -1, 0 or 1 like the order of values in comparisons */
+static inline Value ot_compare(Value a, Value b) {
+    int c = ot_cmp(a, b);
+    ot_release(a);
+    ot_release(b);
+    return ot_int(c < 0 ? -1 : c > 0 ? 1 : 0);
+}
+
+/* uncaught `(pair kind message)` is reported like the error it was caught from */
+static inline Value ot_throw(Value v, const char *location) {
+    ot_buf buf = {NULL, 0, 0};
+    Value kind, message;
+
+    if (ot_handlers != NULL) {
+        ot_thrown = v;
+        longjmp(ot_handlers->jump, 1);
+    }
+
+    if (v.tag == OT_PAIR) {
+        kind = OT_PAIR_OF(v)->left;
+        message = OT_PAIR_OF(v)->right;
+        if (kind.tag == OT_ATOM && kind.as.atom >= OT_ATOM_TYPE_ERROR && kind.as.atom <= OT_ATOM_STACK_OVERFLOW
+            && message.tag == OT_STRING) {
+            ot_buf_push(&buf, OT_STRING_OF(message)->data, OT_STRING_OF(message)->len);
+            ot_report(location, &buf);
+        }
+    }
+    ot_buf_str(&buf, "uncaught ");
+    ot_display(&buf, v);
+    ot_report(location, &buf);
+    return ot_nil();
+}
+
+static inline Value ot_assert_eq(Value expected, Value actual, const char *location) {
+    if (!ot_eq(expected, actual)) {
+        ot_fail_values(OT_ATOM_ASSERTION_ERROR, location, "assertion failed: expected ", expected,
+                       ", found ", actual, "", 0);
+    }
+    ot_release(expected);
+    ot_release(actual);
+    return ot_nil();
+}
+
+/* Files and standard input, failures are returned as `(:err reason)` like in the VM */
+
+static inline Value ot_ok(Value v) {
+    return ot_pair(ot_atom(OT_ATOM_OK), v);
+}
+
+static inline Value ot_err(const char *reason) {
+    return ot_pair(ot_atom(OT_ATOM_ERR), ot_string(reason, strlen(reason)));
+}
+
+/* `(:ok contents)` of the buffer, which is freed */
+static inline Value ot_contents(ot_buf *buf) {
+    Value v;
+    if (buf->len != 0 && memchr(buf->data, '\0', buf->len) != NULL) {
+        v = ot_err("contains the null character");
+    } else {
+        v = ot_ok(ot_string(buf->data == NULL ? "" : buf->data, buf->len));
+    }
+    free(buf->data);
+    return v;
+}
+
+/* reads the rest of the stream into the buffer, returns 0 on failure */
+static inline int ot_read_stream(FILE *stream, ot_buf *buf) {
+    char chunk[4096];
+    size_t read;
+    while ((read = fread(chunk, 1, sizeof(chunk), stream)) != 0) {
+        ot_buf_push(buf, chunk, read);
+    }
+    return !ferror(stream);
+}
+
+static inline Value ot_read_file(Value path, const char *location) {
+    Value v;
+    FILE *file;
+    ot_buf buf = {NULL, 0, 0};
+
+    ot_check_string(path, location);
+    file = fopen(OT_STRING_OF(path)->data, "rb");
+    if (file == NULL) {
+        v = ot_err(strerror(errno));
+    } else if (!ot_read_stream(file, &buf)) {
+        v = ot_err(strerror(errno));
+        free(buf.data);
+    } else {
+        v = ot_contents(&buf);
+    }
+    if (file != NULL) {
+        fclose(file);
+    }
+    ot_release(path);
+    return v;
+}
+
+/* the file is created or its contents replaced unless append */
+static inline Value ot_write_to_file(Value path, Value s, int append, const char *location) {
+    Value v = ot_ok(ot_nil());
+    FILE *file;
+
+    ot_check_string(path, location);
+    ot_check_string(s, location);
+    file = fopen(OT_STRING_OF(path)->data, append ? "ab" : "wb");
+    if (file == NULL) {
+        ot_release(v);
+        v = ot_err(strerror(errno));
+    } else {
+        if (fwrite(OT_STRING_OF(s)->data, 1, OT_STRING_OF(s)->len, file) != OT_STRING_OF(s)->len) {
+            ot_release(v);
+            v = ot_err(strerror(errno));
+        }
+        if (fclose(file) != 0 && OT_PAIR_OF(v)->left.as.atom == OT_ATOM_OK) {
+            ot_release(v);
+            v = ot_err(strerror(errno));
+        }
+    }
+    ot_release(path);
+    ot_release(s);
+    return v;
+}
+
+static inline Value ot_write_file(Value path, Value s, const char *location) {
+    return ot_write_to_file(path, s, 0, location);
+}
+
+static inline Value ot_append_file(Value path, Value s, const char *location) {
+    return ot_write_to_file(path, s, 1, location);
+}
+
+/* line without the line break, `(:ok !)` at the end of the input */
+static inline Value ot_read_line(const char *location) {
+    int c;
+    ot_buf buf = {NULL, 0, 0};
+    (void)location;
+
+    /* prompts written by `print` have to be visible before waiting for the input */
+    fflush(stdout);
+    while ((c = getchar()) != EOF && c != '\n') {
+        char ch = (char)c;
+        ot_buf_push(&buf, &ch, 1);
+    }
+    if (c == EOF && ferror(stdin)) {
+        free(buf.data);
+        return ot_err(strerror(errno));
+    }
+    if (c == EOF && buf.len == 0) {
+        return ot_ok(ot_nil());
+    }
+    if (buf.len != 0 && buf.data[buf.len - 1] == '\r') {
+        buf.len--;
+    }
+    return ot_contents(&buf);
+}
+
+static inline Value ot_read_all_stdin(const char *location) {
+    ot_buf buf = {NULL, 0, 0};
+    (void)location;
+
+    fflush(stdout);
+    if (!ot_read_stream(stdin, &buf)) {
+        free(buf.data);
+        return ot_err(strerror(errno));
+    }
+    return ot_contents(&buf);
+}
+
+/* 1 when the file or directory exists, otherwise 0 */
+static inline Value ot_file_exists(Value path, const char *location) {
+    struct stat info;
+    int exists;
+
+    ot_check_string(path, location);
+    exists = stat(OT_STRING_OF(path)->data, &info) == 0;
+    ot_release(path);
+    return ot_int(exists);
+}
+
+/* Process */
+
+static int ot_process_argc;
+static char **ot_process_argv;
+
+/* arguments of the program are the arguments of the process without its name */
+static inline void ot_process_args(int argc, char **argv) {
+    ot_process_argc = argc - 1;
+    ot_process_argv = argv + 1;
+}
+
+static inline Value ot_program_args(const char *location) {
+    Value list = ot_nil();
+    int i;
+    (void)location;
+
+    for (i = ot_process_argc - 1; i >= 0; i--) {
+        list = ot_pair(ot_string(ot_process_argv[i], strlen(ot_process_argv[i])), list);
+    }
+    return list;
+}
+
+/* nil when the variable isn't set */
+static inline Value ot_env(Value name, const char *location) {
+    Value v = ot_nil();
+    const char *value = NULL;
+
+    ot_check_string(name, location);
+    if (OT_STRING_OF(name)->len != 0 && strchr(OT_STRING_OF(name)->data, '=') == NULL) {
+        value = getenv(OT_STRING_OF(name)->data);
+    }
+    if (value != NULL) {
+        v = ot_string(value, strlen(value));
+    }
+    ot_release(name);
+    return v;
+}
+
+static inline Value ot_exit(Value status, const char *location) {
+    ot_check_int(status, location);
+    fflush(stdout);
+    exit(status.as.i);
+    return ot_nil();
+}
+
+/* local isn't consumed */
+static inline Value ot_local_array_get(Value local, uint32_t index, const char *location) {
+    if (local.tag != OT_ARRAY) {
+        ot_error_value(location, local, " is not an array");
+    }
+    if (index >= OT_ARRAY_OF(local)->len) {
+        ot_fail_str(OT_ATOM_INDEX_ERROR, location, "index out of bounds");
+    }
+    return ot_retain(OT_ARRAY_OF(local)->items[index]);
+}
+
+/* Finds overload of the lambda accepting argc arguments, lambda isn't consumed */
+static inline uint32_t ot_lambda_target(Value lambda, uint8_t argc, const uint8_t *argcs, const char *location) {
+    uint32_t i;
+    ot_buf buf = {NULL, 0, 0};
+
+    if (lambda.tag != OT_LAMBDA) {
+        ot_error_value(location, lambda, " is not callable");
+    }
+
+    for (i = OT_LAMBDA_OF(lambda)->first; i < OT_LAMBDA_OF(lambda)->first + OT_LAMBDA_OF(lambda)->overloads; i++) {
+        if (argcs[i] == argc) {
+            return i;
+        }
+    }
+
+    ot_buf_uint(&buf, "lambda does not accept %lu arguments", argc);
+    ot_fail(OT_ATOM_ARITY_ERROR, location, &buf);
+    return 0;
+}
+
+/* context of the lambda passed as the first argument, lambda isn't consumed */
+static inline Value ot_lambda_context(Value lambda) {
+    size_t i;
+    Value array = ot_array(OT_LAMBDA_OF(lambda)->ctxc);
+    for (i = 0; i < OT_LAMBDA_OF(lambda)->ctxc; i++) {
+        OT_ARRAY_OF(array)->items[i] = ot_retain(OT_LAMBDA_OF(lambda)->ctx[i]);
+    }
+    return array;
+}
+
+/* Foreign functions */
+
+/* loads symbols of the group from the library at path, symbols are looked up by name */
+static inline Value ot_import(Value path, const char *const *names, void **symbols, size_t signaturec,
+                              const uint32_t *group, size_t len, const char *location) {
+#ifndef OT_NO_FFI
+    size_t i, j;
+    void *library;
+    void *symbol;
+
+    if (path.tag == OT_STRING) {
+        library = dlopen(OT_STRING_OF(path)->data, RTLD_NOW);
+        if (library == NULL) {
+            ot_error_str(location, dlerror());
+        }
+        for (i = 0; i < len; i++) {
+            symbol = dlsym(library, names[group[i]]);
+            if (symbol == NULL) {
+                ot_error_str(location, dlerror());
+            }
+            for (j = 0; j < signaturec; j++) {
+                if (strcmp(names[j], names[group[i]]) == 0) {
+                    symbols[j] = symbol;
+                }
+            }
+        }
+    }
+#else
+    (void)names;
+    (void)symbols;
+    (void)signaturec;
+    (void)group;
+    (void)len;
+    ot_error_str(location, "foreign functions are not supported");
+#endif
+    ot_release(path);
+    return ot_nil();
+}
+
+static inline int64_t ot_ffi(Value v, const char *location) {
+    switch (v.tag) {
+    case OT_INT:
+        return (int64_t)v.as.i;
+    case OT_STRING:
+        return (int64_t)(intptr_t)OT_STRING_OF(v)->data;
+    default:
+        ot_error_value(location, v, " can't be passed to foreign function");
+    }
+    return 0;
+}
+
+typedef int64_t (*ot_ffi0)(void);
+typedef int64_t (*ot_ffi1)(int64_t);
+typedef int64_t (*ot_ffi2)(int64_t, int64_t);
+
+/* calls symbol with the arguments, returned string is freed after being copied */
+static inline Value ot_call_dynamic(void *symbol, Value *args, size_t argc, int returns_string, const char *location) {
+    int64_t ret = 0;
+    size_t i;
+    Value v;
+
+    if (symbol == NULL) {
+        ot_error_str(location, "Unknown symbol");
+    }
+
+    switch (argc) {
+    case 0:
+        ret = ((ot_ffi0)symbol)();
+        break;
+    case 1:
+        ret = ((ot_ffi1)symbol)(ot_ffi(args[0], location));
+        break;
+    case 2:
+        ret = ((ot_ffi2)symbol)(ot_ffi(args[0], location), ot_ffi(args[1], location));
+        break;
+    default:
+        ot_error_str(location, "Can't call this symbol");
+    }
+
+    for (i = 0; i < argc; i++) {
+        ot_release(args[i]);
+    }
+
+    if (returns_string) {
+        char *s = (char *)(intptr_t)ret;
+        v = ot_string(s, strlen(s));
+        free(s);
+        return v;
+    }
+    return ot_int((int32_t)ret);
+}
+
+#endif
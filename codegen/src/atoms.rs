@@ -8,10 +8,27 @@ pub struct Atoms<'a> {
 
 impl<'a> Atoms<'a> {
     pub fn new() -> Atoms<'a> {
-        Atoms {
+        let mut atoms = Atoms {
             atoms: HashMap::new(),
             atomc: 0,
-        }
+        };
+        // builtins expect `:ok` and `:err` to be `vm_bytecode::ATOM_OK` and `ATOM_ERR`, errors
+        // raised by the VM are tagged with the following ones
+        [
+            "ok",
+            "err",
+            "type-error",
+            "arithmetic-error",
+            "index-error",
+            "arity-error",
+            "assertion-error",
+            "stack-overflow",
+        ]
+        .iter()
+        .for_each(|&name| {
+            atoms.translate(name);
+        });
+        return atoms;
     }
 
     pub fn translate(&mut self, name: &'a str) -> u32 {
@@ -22,4 +39,11 @@ impl<'a> Atoms<'a> {
             return c;
         })
     }
+
+    /// Names of atoms ordered by their numbers
+    pub fn names(&self) -> Vec<&'a str> {
+        let mut names = self.atoms.iter().collect::<Vec<_>>();
+        names.sort_by_key(|(_, &c)| c);
+        names.into_iter().map(|(&name, _)| name).collect()
+    }
 }
@@ -0,0 +1,80 @@
+use std::io::{BufRead, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use codegen::json::Json;
+use codegen::lsp::Server;
+
+fn main() {
+    let stdin = std::io::stdin();
+    let mut stdin = stdin.lock();
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut stdin) {
+        let message = match Json::parse(&body) {
+            Some(message) => message,
+            None => {
+                eprintln!("otls: invalid message `{}`", body);
+                continue;
+            }
+        };
+
+        if message.get("method").as_str() == Some("exit") {
+            std::process::exit(if server.is_shutdown() { 0 } else { 1 });
+        }
+
+        // a bug in the analysis must not take down the editor session
+        let responses =
+            catch_unwind(AssertUnwindSafe(|| server.handle(&message))).unwrap_or_else(|_| {
+                match message.get("id") {
+                    Json::Null => Vec::new(),
+                    id => vec![Json::object(vec![
+                        ("jsonrpc", "2.0".into()),
+                        ("id", id.clone()),
+                        (
+                            "error",
+                            Json::object(vec![
+                                ("code", Json::Number(-32603.0)),
+                                ("message", "Internal error".into()),
+                            ]),
+                        ),
+                    ])],
+                }
+            });
+
+        for response in responses {
+            let body = response.to_string();
+            let sent = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+                .and_then(|_| stdout.flush());
+            if sent.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Body of the next message, `None` at the end of input
+fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    return String::from_utf8(body).ok();
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use vm_bytecode::bundle::append;
+
+/// Writes executable made of the VM runtime and the program appended to it
+pub fn bundle(program: &[u8], out: &str, runtime: Option<&str>, jit: bool) {
+    let runtime = match runtime.map(PathBuf::from).or_else(find_runtime) {
+        Some(runtime) => runtime,
+        None => bundle_error(
+            "Can't find `otvm` runtime, use `--runtime <path>` or set `OTVM` environment variable",
+        ),
+    };
+
+    let runtime_bytes = std::fs::read(&runtime).unwrap_or_else(|e| {
+        bundle_error(&format!(
+            "Can't read runtime `{}`: {}",
+            runtime.display(),
+            e
+        ))
+    });
+
+    std::fs::write(out, append(&runtime_bytes, program, jit))
+        .unwrap_or_else(|e| bundle_error(&format!("Can't write `{}`: {}", out, e)));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(out, std::fs::Permissions::from_mode(0o755))
+            .unwrap_or_else(|e| bundle_error(&format!("Can't make `{}` executable: {}", out, e)));
+    }
+}
+
+// `OTVM` variable, then `otvm` next to the compiler and then in `PATH`
+fn find_runtime() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("OTVM") {
+        return Some(PathBuf::from(path));
+    }
+
+    let next_to_compiler = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("otvm")));
+
+    return next_to_compiler
+        .into_iter()
+        .chain(
+            std::env::var_os("PATH")
+                .iter()
+                .flat_map(std::env::split_paths)
+                .map(|dir| dir.join("otvm")),
+        )
+        .find(|path| Path::is_file(path));
+}
+
+fn bundle_error(msg: &str) -> ! {
+    eprintln!("\x1b[31m\x1b[1mBUNDLE ERROR:\x1b[0m {}", msg);
+    std::process::exit(1);
+}
@@ -0,0 +1,885 @@
+use std::fmt::Write;
+
+use parser::SymbolType;
+use vm_bytecode::Bytecode;
+
+use super::{compiler::Position, CompilerCtx, Constant};
+
+/// Runtime implementing values and instructions, generated code includes it as `ot.h`
+pub const RUNTIME_HEADER: &str = include_str!("../runtime/ot.h");
+
+struct Function {
+    index: u32,
+    code: Vec<Bytecode>,
+    argc: u8,
+    localc: u8,
+}
+
+struct Emitter<'a> {
+    out: String,
+
+    functions: &'a [Function],
+    debug: Option<&'a [(u32, String, Vec<(u32, Position<'a>)>)]>,
+
+    // unique ids of branches
+    branches: usize,
+    max_stack: usize,
+}
+
+// Branch opened by If or Try, depths are None when the code is unreachable
+struct Branch {
+    id: usize,
+    depth: Option<usize>,
+    then_depth: Option<usize>,
+    dead: bool,
+}
+
+/// Translates compiled program into C source, which includes the runtime header `ot.h`
+pub fn emit_c(ctx: CompilerCtx, debug_info: bool) -> String {
+    let start = ctx
+        .functions
+        .main_index()
+        .expect("Main function is not defined");
+
+    let functions = ctx
+        .functions
+        .get_all()
+        .into_iter()
+        .map(|(index, code, argc, localc)| Function {
+            index,
+            code,
+            argc,
+            localc: localc - argc,
+        })
+        .collect::<Vec<_>>();
+
+    let (sigs, groups) = ctx.signatures.get_signatures_and_groups();
+
+    let mut debug = ctx.debug;
+    debug.sort_by_key(|(index, _, _)| *index);
+
+    let mut emitter = Emitter {
+        out: String::new(),
+        functions: &functions,
+        debug: if debug_info { Some(&debug) } else { None },
+        branches: 0,
+        max_stack: 1,
+    };
+
+    for f in &functions {
+        emitter.function(f, &sigs);
+    }
+    // lambdas get their context as an extra argument
+    let max_locals = functions
+        .iter()
+        .map(|f| f.argc as usize + f.localc as usize + 1)
+        .max()
+        .unwrap_or(1);
+
+    let mut out = String::new();
+    let out = &mut out;
+    writeln!(out, "/* generated by otc */").unwrap();
+    writeln!(out, "#include \"ot.h\"\n").unwrap();
+    writeln!(out, "#define OT_MAX_STACK {}", emitter.max_stack).unwrap();
+    writeln!(out, "#define OT_MAX_LOCALS {}\n", max_locals).unwrap();
+
+    // function tables
+    writeln!(
+        out,
+        "static const uint8_t ot_argc[] = {{{}}};",
+        list(functions.iter().map(|f| f.argc))
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "static const uint8_t ot_localc[] = {{{}}};\n",
+        list(functions.iter().map(|f| f.localc))
+    )
+    .unwrap();
+
+    // constants
+    writeln!(
+        out,
+        "static Value ot_constants[{}];\n",
+        ctx.constants.len().max(1)
+    )
+    .unwrap();
+    writeln!(out, "static void ot_init_constants(void) {{").unwrap();
+    for (index, constant) in ctx.constants.iter().enumerate() {
+        match constant {
+            Constant::Int(i) => {
+                writeln!(out, "    ot_constants[{}] = ot_int({});", index, i).unwrap()
+            }
+            Constant::String(s) => writeln!(
+                out,
+                "    ot_constants[{}] = ot_string({}, {});",
+                index,
+                c_string(s),
+                s.len()
+            )
+            .unwrap(),
+        }
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    // foreign functions
+    writeln!(
+        out,
+        "static const char *const ot_symbol_names[] = {{{}}};",
+        list(sigs.iter().map(|(name, _, _, _)| c_string(name)).chain(
+            // array can't be empty
+            std::iter::once(String::from("NULL")).take(if sigs.is_empty() { 1 } else { 0 })
+        ))
+    )
+    .unwrap();
+    writeln!(out, "static void *ot_symbols[{}];", sigs.len().max(1)).unwrap();
+    for (index, group) in groups.iter().enumerate() {
+        writeln!(
+            out,
+            "static const uint32_t ot_group_{}[] = {{{}}};",
+            index,
+            list(
+                group
+                    .iter()
+                    .chain(if group.is_empty() { Some(&0) } else { None })
+            )
+        )
+        .unwrap();
+    }
+    writeln!(out).unwrap();
+
+    writeln!(
+        out,
+        "static size_t ot_enter(uint32_t function, Value *L, size_t nl, const Value *args, size_t argc) {{"
+    )
+    .unwrap();
+    writeln!(out, "    size_t i;").unwrap();
+    writeln!(out, "    for (i = 0; i < nl; i++) ot_release(L[i]);").unwrap();
+    writeln!(out, "    for (i = 0; i < argc; i++) L[i] = args[i];").unwrap();
+    writeln!(
+        out,
+        "    for (; i < argc + ot_localc[function]; i++) L[i] = ot_int(0);"
+    )
+    .unwrap();
+    writeln!(out, "    return i;").unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    // all functions share one C function, so tail calls can jump between them
+    writeln!(
+        out,
+        "static Value ot_run(uint32_t function, const Value *args, size_t argc) {{"
+    )
+    .unwrap();
+    writeln!(out, "    Value L[OT_MAX_LOCALS];").unwrap();
+    writeln!(out, "    Value S[OT_MAX_STACK];").unwrap();
+    writeln!(out, "    size_t nl = ot_enter(function, L, 0, args, argc);").unwrap();
+    writeln!(out, "    size_t i;\n").unwrap();
+    writeln!(out, "    (void)S;").unwrap();
+    writeln!(out, "    (void)i;\n").unwrap();
+    writeln!(out, "    switch (function) {{").unwrap();
+    for f in &functions {
+        writeln!(out, "    case {}: goto f{};", f.index, f.index).unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    return ot_nil();").unwrap();
+    out.push_str(&emitter.out);
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "int main(int argc, char **argv) {{").unwrap();
+    writeln!(out, "    (void)ot_argc;").unwrap();
+    writeln!(out, "    (void)ot_symbols;").unwrap();
+    writeln!(out, "    (void)ot_symbol_names;").unwrap();
+    writeln!(out, "    ot_process_args(argc, argv);").unwrap();
+    writeln!(out, "    ot_init_constants();").unwrap();
+    writeln!(out, "    ot_release(ot_run({}, NULL, 0));", start).unwrap();
+    writeln!(out, "    return 0;").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    return std::mem::take(out);
+}
+
+impl<'a> Emitter<'a> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+
+    // Same location as the VM prints in runtime errors
+    fn location(&self, function: u32, pc: usize) -> String {
+        let location = match self.debug {
+            Some(debug) => {
+                let (_, name, positions) = match debug.iter().find(|(i, _, _)| *i == function) {
+                    Some(f) => f,
+                    None => return c_string(&format!("function #{}", function)),
+                };
+                match positions
+                    .iter()
+                    .take_while(|(index, _)| *index as usize <= pc)
+                    .last()
+                {
+                    Some((_, pos)) => format!("{} ({}:{}:{})", name, pos.file, pos.line, pos.col),
+                    None => name.clone(),
+                }
+            }
+            None => format!("function #{}", function),
+        };
+
+        return c_string(&location);
+    }
+
+    fn argc(&self, function: u32) -> usize {
+        self.functions[function as usize].argc as usize
+    }
+
+    fn function(&mut self, f: &Function, sigs: &[(&str, u32, Vec<SymbolType>, SymbolType)]) {
+        let name = match self.debug {
+            Some(debug) => debug
+                .iter()
+                .find(|(i, _, _)| *i == f.index)
+                .map(|(_, name, _)| format!(" {}", name))
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+        self.out.push_str(&format!(
+            "\n/* function #{}{} */\nf{}:\n",
+            f.index, name, f.index
+        ));
+
+        let mut depth = Some(0);
+        let mut branches: Vec<Branch> = Vec::new();
+
+        for (pc, bc) in f.code.iter().enumerate() {
+            let is_if = matches!(
+                bc,
+                Bytecode::If
+                    | Bytecode::IfEq
+                    | Bytecode::IfLt
+                    | Bytecode::IfGt
+                    | Bytecode::IfLte
+                    | Bytecode::IfGte
+            );
+
+            // unreachable code is skipped, only branches are tracked
+            let d = match depth {
+                Some(d) => d,
+                None => {
+                    if is_if || matches!(bc, Bytecode::Try) {
+                        branches.push(Branch {
+                            id: 0,
+                            depth: None,
+                            then_depth: None,
+                            dead: true,
+                        });
+                    } else if let Bytecode::Else = bc {
+                        let branch = branches.last_mut().unwrap();
+                        if !branch.dead {
+                            branch.then_depth = None;
+                            depth = branch.depth;
+                            self.out
+                                .push_str(&format!("f{}_else{}:;\n", f.index, branch.id));
+                        }
+                    } else if let Bytecode::Catch = bc {
+                        let branch = branches.last_mut().unwrap();
+                        if !branch.dead {
+                            branch.then_depth = None;
+                            depth = branch.depth.map(|d| d + 1);
+                            self.line("}");
+                            self.out
+                                .push_str(&format!("f{}_catch{}:;\n", f.index, branch.id));
+                        }
+                    } else if let Bytecode::End = bc {
+                        let branch = branches.pop().unwrap();
+                        if !branch.dead {
+                            depth = branch.then_depth;
+                            if depth.is_some() {
+                                self.out
+                                    .push_str(&format!("f{}_end{}:;\n", f.index, branch.id));
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let loc = self.location(f.index, pc);
+
+            if is_if {
+                let id = self.branches;
+                self.branches += 1;
+
+                let (pops, cond) = match bc {
+                    Bytecode::If => (1, format!("!ot_truthy(S[{}])", d - 1)),
+                    Bytecode::IfEq => (2, format!("!ot_eq(S[{}], S[{}])", d - 2, d - 1)),
+                    Bytecode::IfLt => (2, format!("ot_cmp(S[{}], S[{}]) < 0", d - 2, d - 1)),
+                    Bytecode::IfGt => (2, format!("ot_cmp(S[{}], S[{}]) > 0", d - 2, d - 1)),
+                    Bytecode::IfLte => (2, format!("ot_cmp(S[{}], S[{}]) <= 0", d - 2, d - 1)),
+                    _ => (2, format!("ot_cmp(S[{}], S[{}]) >= 0", d - 2, d - 1)),
+                };
+
+                let releases = (d - pops..d)
+                    .map(|i| format!(" ot_release(S[{}]);", i))
+                    .collect::<String>();
+                self.line(format!(
+                    "{{ int skip = {};{} if (skip) goto f{}_else{}; }}",
+                    cond, releases, f.index, id
+                ));
+
+                branches.push(Branch {
+                    id,
+                    depth: Some(d - pops),
+                    then_depth: None,
+                    dead: false,
+                });
+                depth = Some(d - pops);
+                continue;
+            }
+
+            depth = match *bc {
+                // the handler lives in the frame of `ot_run`, its locals and stack are kept after longjmp
+                Bytecode::Try => {
+                    let id = self.branches;
+                    self.branches += 1;
+
+                    self.line(format!(
+                        "{{ ot_handler h{}; h{}.prev = ot_handlers; ot_handlers = &h{};",
+                        id, id, id
+                    ));
+                    self.line(format!(
+                        "if (setjmp(h{}.jump)) {{ ot_handlers = h{}.prev; S[{}] = ot_thrown; goto f{}_catch{}; }}",
+                        id, id, d, f.index, id
+                    ));
+
+                    branches.push(Branch {
+                        id,
+                        depth: Some(d),
+                        then_depth: None,
+                        dead: false,
+                    });
+                    Some(d)
+                }
+                Bytecode::Catch => {
+                    let branch = branches.last_mut().unwrap();
+                    branch.then_depth = Some(d);
+                    let (id, start) = (branch.id, branch.depth);
+                    self.line(format!(
+                        "ot_handlers = h{}.prev; goto f{}_end{}; }}",
+                        id, f.index, id
+                    ));
+                    self.out.push_str(&format!("f{}_catch{}:;\n", f.index, id));
+                    start.map(|d| d + 1)
+                }
+                Bytecode::Else => {
+                    let branch = branches.last_mut().unwrap();
+                    branch.then_depth = Some(d);
+                    let (id, start) = (branch.id, branch.depth);
+                    self.line(format!("goto f{}_end{};", f.index, id));
+                    self.out.push_str(&format!("f{}_else{}:;\n", f.index, id));
+                    start
+                }
+                Bytecode::End => {
+                    let branch = branches.pop().unwrap();
+                    if branch.dead {
+                        Some(d)
+                    } else {
+                        // the then branch jumps here unless it returned
+                        if let Some(then_depth) = branch.then_depth {
+                            assert_eq!(then_depth, d, "branches leave different stack sizes");
+                            self.out
+                                .push_str(&format!("f{}_end{}:;\n", f.index, branch.id));
+                        }
+                        Some(d)
+                    }
+                }
+                Bytecode::Nop => Some(d),
+
+                Bytecode::LocalGet(i) => {
+                    self.line(format!("S[{}] = ot_retain(L[{}]);", d, i));
+                    Some(d + 1)
+                }
+                Bytecode::LocalSet(i) => {
+                    self.line(format!("ot_release(L[{}]); L[{}] = S[{}];", i, i, d - 1));
+                    Some(d - 1)
+                }
+                Bytecode::ConstantGet(i) => {
+                    self.line(format!("S[{}] = ot_retain(ot_constants[{}]);", d, i));
+                    Some(d + 1)
+                }
+                Bytecode::ConstantNil => {
+                    self.line(format!("S[{}] = ot_nil();", d));
+                    Some(d + 1)
+                }
+                Bytecode::Atom(a) => {
+                    self.line(format!("S[{}] = ot_atom({});", d, a));
+                    Some(d + 1)
+                }
+
+                Bytecode::Add | Bytecode::Mul => {
+                    let op = if let Bytecode::Add = bc { "add" } else { "mul" };
+                    self.line(format!(
+                        "S[{}] = ot_{}(S[{}], S[{}], {});",
+                        d - 2,
+                        op,
+                        d - 1,
+                        d - 2,
+                        loc
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::Sub | Bytecode::Div => {
+                    let op = if let Bytecode::Sub = bc { "sub" } else { "div" };
+                    self.line(format!(
+                        "S[{}] = ot_{}(S[{}], S[{}], {});",
+                        d - 2,
+                        op,
+                        d - 2,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::Eq | Bytecode::Lt | Bytecode::Gt | Bytecode::Lte | Bytecode::Gte => {
+                    let cond = match bc {
+                        Bytecode::Eq => format!("ot_eq(S[{}], S[{}])", d - 2, d - 1),
+                        Bytecode::Lt => format!("ot_cmp(S[{}], S[{}]) < 0", d - 2, d - 1),
+                        Bytecode::Gt => format!("ot_cmp(S[{}], S[{}]) > 0", d - 2, d - 1),
+                        Bytecode::Lte => format!("ot_cmp(S[{}], S[{}]) <= 0", d - 2, d - 1),
+                        _ => format!("ot_cmp(S[{}], S[{}]) >= 0", d - 2, d - 1),
+                    };
+                    self.line(format!(
+                        "{{ int r = {}; ot_release(S[{}]); ot_release(S[{}]); S[{}] = ot_int(r); }}",
+                        cond,
+                        d - 2,
+                        d - 1,
+                        d - 2
+                    ));
+                    Some(d - 1)
+                }
+
+                Bytecode::Call(i) => {
+                    let argc = self.argc(i);
+                    let args = if argc == 0 {
+                        String::from("NULL")
+                    } else {
+                        format!("&S[{}]", d - argc)
+                    };
+                    self.line(format!(
+                        "S[{}] = ot_run({}, {}, {});",
+                        d - argc,
+                        i,
+                        args,
+                        argc
+                    ));
+                    Some(d - argc + 1)
+                }
+                Bytecode::CallRet(i) => {
+                    // tail call reuses the frame
+                    let argc = self.argc(i);
+                    if d - argc != 0 {
+                        self.line(format!(
+                            "for (i = 0; i < {}; i++) ot_release(S[i]);",
+                            d - argc
+                        ));
+                    }
+                    self.line(format!(
+                        "nl = ot_enter({}, L, nl, &S[{}], {});",
+                        i,
+                        d - argc,
+                        argc
+                    ));
+                    self.line(format!("goto f{};", i));
+                    None
+                }
+                Bytecode::Ret => {
+                    if d > 1 {
+                        self.line(format!("for (i = 0; i < {}; i++) ot_release(S[i]);", d - 1));
+                    }
+                    self.line("for (i = 0; i < nl; i++) ot_release(L[i]);");
+                    self.line(format!("return S[{}];", d - 1));
+                    None
+                }
+                Bytecode::Remove => {
+                    self.line(format!("ot_release(S[{}]);", d - 1));
+                    Some(d - 1)
+                }
+
+                Bytecode::CallLambda(argc) => {
+                    let argc = argc as usize;
+                    let lambda = d - 1;
+                    self.line(format!("{{ Value A[{}];", argc + 1));
+                    self.line(format!(
+                        "  uint32_t target = ot_lambda_target(S[{}], {}, ot_argc, {});",
+                        lambda, argc, loc
+                    ));
+                    self.line(format!("  A[0] = ot_lambda_context(S[{}]);", lambda));
+                    if argc != 0 {
+                        self.line(format!(
+                            "  memcpy(&A[1], &S[{}], sizeof(Value) * {});",
+                            lambda - argc,
+                            argc
+                        ));
+                    }
+                    self.line(format!("  ot_release(S[{}]);", lambda));
+                    self.line(format!(
+                        "  S[{}] = ot_run(target, A, {}); }}",
+                        lambda - argc,
+                        argc + 1
+                    ));
+                    Some(d - argc)
+                }
+
+                Bytecode::Print => {
+                    self.line(format!("S[{}] = ot_print(S[{}]);", d - 1, d - 1));
+                    Some(d)
+                }
+                Bytecode::NewPair => {
+                    self.line(format!(
+                        "S[{}] = ot_pair(S[{}], S[{}]);",
+                        d - 2,
+                        d - 2,
+                        d - 1
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::PairLeft => {
+                    self.line(format!("S[{}] = ot_pair_left(S[{}]);", d - 1, d - 1));
+                    Some(d)
+                }
+                Bytecode::PairRight => {
+                    self.line(format!("S[{}] = ot_pair_right(S[{}]);", d - 1, d - 1));
+                    Some(d)
+                }
+                Bytecode::NewArray(size) => {
+                    let size = size as usize;
+                    self.line(format!(
+                        "S[{}] = ot_array_from(&S[{}], {});",
+                        d - size,
+                        d - size,
+                        size
+                    ));
+                    Some(d - size + 1)
+                }
+                Bytecode::NewLambda(first, ctxc, overloads) => {
+                    let ctxc = ctxc as usize;
+                    self.line(format!(
+                        "S[{}] = ot_lambda({}, {}, &S[{}], {});",
+                        d - ctxc,
+                        first,
+                        overloads,
+                        d - ctxc,
+                        ctxc
+                    ));
+                    Some(d - ctxc + 1)
+                }
+                Bytecode::NewArrayDyn => {
+                    self.line(format!(
+                        "S[{}] = ot_new_array(S[{}], {});",
+                        d - 1,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d)
+                }
+                Bytecode::ArrayGet => {
+                    self.line(format!(
+                        "S[{}] = ot_array_get(S[{}], S[{}], {});",
+                        d - 2,
+                        d - 2,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::LocalArrayGet(local, index) => {
+                    self.line(format!(
+                        "S[{}] = ot_local_array_get(L[{}], {}, {});",
+                        d, local, index, loc
+                    ));
+                    Some(d + 1)
+                }
+                Bytecode::ArraySet => {
+                    self.line(format!(
+                        "S[{}] = ot_array_set(S[{}], S[{}], S[{}], {});",
+                        d - 3,
+                        d - 3,
+                        d - 2,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d - 2)
+                }
+                Bytecode::ArrayLen => {
+                    self.line(format!(
+                        "S[{}] = ot_array_len(S[{}], {});",
+                        d - 1,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d)
+                }
+                Bytecode::StringLen => {
+                    self.line(format!(
+                        "S[{}] = ot_string_len(S[{}], {});",
+                        d - 1,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d)
+                }
+                Bytecode::AssertEq => {
+                    self.line(format!(
+                        "S[{}] = ot_assert_eq(S[{}], S[{}], {});",
+                        d - 2,
+                        d - 2,
+                        d - 1,
+                        loc
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::ToString => {
+                    self.line(format!("S[{}] = ot_to_string(S[{}]);", d - 1, d - 1));
+                    Some(d)
+                }
+                Bytecode::StringCompare => {
+                    self.line(format!(
+                        "S[{}] = ot_compare(S[{}], S[{}]);",
+                        d - 2,
+                        d - 2,
+                        d - 1
+                    ));
+                    Some(d - 1)
+                }
+                Bytecode::StringConcat
+                | Bytecode::Substring
+                | Bytecode::CharAt
+                | Bytecode::CharToString
+                | Bytecode::StringToInt
+                | Bytecode::IntToString
+                | Bytecode::StringSplit
+                | Bytecode::StringJoin
+                | Bytecode::StringIndexOf
+                | Bytecode::StringUpper
+                | Bytecode::StringLower
+                | Bytecode::ReadFile
+                | Bytecode::WriteFile
+                | Bytecode::AppendFile
+                | Bytecode::ReadLine
+                | Bytecode::ReadAllStdin
+                | Bytecode::FileExists
+                | Bytecode::ProgramArgs
+                | Bytecode::Env
+                | Bytecode::Exit
+                | Bytecode::Throw => {
+                    let (name, argc) = match bc {
+                        Bytecode::StringConcat => ("ot_string_concat", 2),
+                        Bytecode::Substring => ("ot_substring", 3),
+                        Bytecode::CharAt => ("ot_char_at", 2),
+                        Bytecode::CharToString => ("ot_char_to_string", 1),
+                        Bytecode::StringToInt => ("ot_string_to_int", 1),
+                        Bytecode::IntToString => ("ot_int_to_string", 1),
+                        Bytecode::StringSplit => ("ot_string_split", 2),
+                        Bytecode::StringJoin => ("ot_string_join", 2),
+                        Bytecode::StringIndexOf => ("ot_string_index_of", 2),
+                        Bytecode::StringUpper => ("ot_string_upper", 1),
+                        Bytecode::StringLower => ("ot_string_lower", 1),
+                        Bytecode::ReadFile => ("ot_read_file", 1),
+                        Bytecode::WriteFile => ("ot_write_file", 2),
+                        Bytecode::AppendFile => ("ot_append_file", 2),
+                        Bytecode::ReadLine => ("ot_read_line", 0),
+                        Bytecode::ReadAllStdin => ("ot_read_all_stdin", 0),
+                        Bytecode::FileExists => ("ot_file_exists", 1),
+                        Bytecode::ProgramArgs => ("ot_program_args", 0),
+                        Bytecode::Env => ("ot_env", 1),
+                        Bytecode::Exit => ("ot_exit", 1),
+                        _ => ("ot_throw", 1),
+                    };
+                    let args = (d - argc..d)
+                        .map(|i| format!("S[{}], ", i))
+                        .collect::<String>();
+                    self.line(format!("S[{}] = {}({}{});", d - argc, name, args, loc));
+                    Some(d - argc + 1)
+                }
+
+                Bytecode::Import(group) => {
+                    self.line(format!(
+                        "S[{}] = ot_import(S[{}], ot_symbol_names, ot_symbols, {}, ot_group_{}, sizeof(ot_group_{}) / sizeof(uint32_t), {});",
+                        d - 1,
+                        d - 1,
+                        sigs.len(),
+                        group,
+                        group,
+                        loc
+                    ));
+                    Some(d)
+                }
+                Bytecode::CallDynamic(sig) => {
+                    let (_, _, args, ret) = &sigs[sig as usize];
+                    let argc = args.len();
+                    self.line(format!(
+                        "S[{}] = ot_call_dynamic(ot_symbols[{}], &S[{}], {}, {}, {});",
+                        d - argc,
+                        sig,
+                        d - argc,
+                        argc,
+                        matches!(ret, SymbolType::Str) as u8,
+                        loc
+                    ));
+                    Some(d - argc + 1)
+                }
+
+                Bytecode::If
+                | Bytecode::IfEq
+                | Bytecode::IfLt
+                | Bytecode::IfGt
+                | Bytecode::IfLte
+                | Bytecode::IfGte => unreachable!(),
+            };
+
+            if let Some(d) = depth {
+                self.max_stack = self.max_stack.max(d);
+            }
+        }
+
+        // VM returns nil when function has no more instructions
+        if let Some(d) = depth {
+            if d != 0 {
+                self.line(format!("for (i = 0; i < {}; i++) ot_release(S[i]);", d));
+            }
+            self.line("for (i = 0; i < nl; i++) ot_release(L[i]);");
+            self.line("return ot_nil();");
+        }
+    }
+}
+
+fn list<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    items.map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// C string literal, non printable characters are written as octal escapes
+fn c_string(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            0x20..=0x7e => literal.push(b as char),
+            b => literal.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    literal.push('"');
+
+    return literal;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::{cell::RefCell, rc::Rc};
+
+    use vm::{deserialize, ErrorKind, VM};
+
+    use super::{emit_c, RUNTIME_HEADER};
+    use crate::{loader::compile, serialize::serialize};
+
+    // Output and exit status of the program built from the C source and run in the VM,
+    // `None` when there is no C compiler
+    fn outputs(name: &str, source: &str) -> Option<((String, i32), (String, i32))> {
+        let dir = std::env::temp_dir().join(format!("ot-c-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.ot"), source).unwrap();
+        let root = dir.to_str().unwrap();
+
+        let c = compile(root, false, false, false, None, |ctx| emit_c(ctx, true));
+        let bytes = compile(root, false, false, false, None, |ctx| serialize(&ctx, true));
+        std::fs::write(dir.join("ot.h"), RUNTIME_HEADER).unwrap();
+        std::fs::write(dir.join("main.c"), c).unwrap();
+
+        let program = dir.join("main");
+        let built = Command::new("cc")
+            .arg(dir.join("main.c"))
+            .arg("-o")
+            .arg(&program)
+            .arg("-ldl")
+            .status();
+        let native = match built {
+            Ok(status) => {
+                assert!(status.success());
+                let output = Command::new(&program).output().unwrap();
+                let stdout = String::from_utf8(output.stdout).unwrap();
+                (stdout, output.status.code().unwrap())
+            }
+            Err(_) => {
+                std::fs::remove_dir_all(&dir).unwrap();
+                return None;
+            }
+        };
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (start, constants, functions, signatures, groups, debug) = deserialize(&bytes).unwrap();
+        let output = Rc::new(RefCell::new(String::new()));
+        let print = {
+            let output = output.clone();
+            move |s: &str| output.borrow_mut().push_str(s)
+        };
+        let status = match VM::new(
+            constants, functions, signatures, groups, debug, print, false, false,
+        )
+        .run(start, Vec::new())
+        {
+            Ok(_) => 0,
+            Err(error) => match error.kind {
+                ErrorKind::Exit(code) => code,
+                _ => 1,
+            },
+        };
+        let output = output.borrow().clone();
+
+        return Some((native, (output, status)));
+    }
+
+    #[test]
+    fn same_output_as_vm() {
+        let source = "mod main
+
+(defn fib [0] 0 [1] 1 [n] (+ (fib (- n 1)) (fib (- n 2))))
+
+(defn count [0 acc] acc [n acc] (count (- n 1) (+ acc 1)))
+
+(def add (lambda [a b] (+ a b)))
+
+(print (fib 15))
+(print \" \")
+(print (count 100000 0))
+(print \" \")
+(print (add 2 3))
+(print \" \")
+(print (concat (upper \"ab\") (int->str 42)))
+(print \" \")
+(print (pair 1 (pair \"a\" !)))
+(print \" \")
+(print (try (/ 1 0) (catch e (right e))))
+(print \" \")
+(print (try (throw 5) (catch e (+ e 1))))
+";
+        if let Some((native, vm)) = outputs("output", source) {
+            assert_eq!(native, vm);
+            assert_eq!(native.1, 0);
+        }
+    }
+
+    #[test]
+    fn same_status_as_vm() {
+        let exit = "mod main\n\n(print \"a\")\n(exit 3)\n(print \"b\")\n";
+        if let Some((native, vm)) = outputs("exit", exit) {
+            assert_eq!(native, (String::from("a"), 3));
+            assert_eq!(native, vm);
+        }
+
+        let error = "mod main\n\n(print \"a\")\n(print (/ 1 0))\n";
+        if let Some((native, vm)) = outputs("error", error) {
+            assert_eq!(native, (String::from("a"), 1));
+            assert_eq!(native, vm);
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use std::path::{Path, PathBuf};
+
+use parser::SymbolType;
+use vm_bytecode::Bytecode;
+
+use crate::compiler::Constant;
+use crate::object::{Object, ObjectFunction, ObjectLabel};
+
+const MAGIC: &[u8; 4] = b"OTCO";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Directory with compiled modules, one file per source file
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    /// Object compiled from the same contents of the file, if there is one
+    pub fn load(&self, path: &str, hash: u64) -> Option<Object> {
+        let bytes = std::fs::read(self.file(path)).ok()?;
+        let object = decode(&bytes)?;
+
+        if object.path != path || object.hash != hash {
+            return None;
+        }
+        return Some(object);
+    }
+
+    /// Stores the object, failure only means it is compiled again next time
+    pub fn store(&self, object: &Object) {
+        let mut bytes = Vec::new();
+        encode(object, &mut bytes);
+
+        let _ = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.file(&object.path), bytes));
+    }
+
+    // files of dependencies can have the same names as files of the project
+    fn file(&self, path: &str) -> PathBuf {
+        let stem = Path::new(path).file_stem().unwrap_or_default();
+        self.dir
+            .join(format!(
+                "{}-{:08x}",
+                stem.to_string_lossy(),
+                hash(path) as u32
+            ))
+            .with_extension("otm")
+    }
+}
+
+/// FNV-1a hash of the source
+pub fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn encode(object: &Object, bytes: &mut Vec<u8>) {
+    bytes.extend(MAGIC);
+    string(bytes, VERSION);
+
+    string(bytes, &object.module);
+    string(bytes, &object.path);
+    bytes.extend(object.hash.to_le_bytes());
+
+    u32(bytes, object.exports.len());
+    for (name, argc) in &object.exports {
+        string(bytes, name);
+        bytes.push(*argc);
+    }
+
+    u32(bytes, object.uses.len());
+    for (name, arities) in &object.uses {
+        string(bytes, name);
+        bytes.push(arities.len() as u8);
+        bytes.extend(arities);
+    }
+
+    u32(bytes, object.functions.len());
+    for f in &object.functions {
+        string(bytes, &f.name);
+        bytes.push(f.argc);
+        bytes.push(f.localc);
+
+        let mut code = Vec::new();
+        f.code.iter().for_each(|bc| bc.encode(&mut code));
+        u32(bytes, code.len());
+        bytes.extend(code);
+
+        u32(bytes, f.positions.len());
+        for &(index, line, col) in &f.positions {
+            bytes.extend(index.to_le_bytes());
+            bytes.extend(line.to_le_bytes());
+            bytes.extend(col.to_le_bytes());
+        }
+    }
+
+    u32(bytes, object.constants.len());
+    for constant in &object.constants {
+        match constant {
+            Constant::Int(i) => {
+                bytes.push(0);
+                bytes.extend(i.to_le_bytes());
+            }
+            Constant::String(s) => {
+                bytes.push(1);
+                string(bytes, s);
+            }
+        }
+    }
+
+    u32(bytes, object.atoms.len());
+    object.atoms.iter().for_each(|atom| string(bytes, atom));
+
+    u32(bytes, object.signatures.len());
+    for (index, name, args, ret) in &object.signatures {
+        bytes.extend(index.to_le_bytes());
+        string(bytes, name);
+        bytes.push(args.len() as u8);
+        args.iter().for_each(|&t| bytes.push(t as u8));
+        bytes.push(*ret as u8);
+    }
+
+    u32(bytes, object.groups.len());
+    for group in &object.groups {
+        u32(bytes, group.len());
+        group.iter().for_each(|i| bytes.extend(i.to_le_bytes()));
+    }
+
+    u32(bytes, object.labels.len());
+    for label in &object.labels {
+        bytes.extend(label.function.to_le_bytes());
+        bytes.extend(label.index.to_le_bytes());
+        string(bytes, &label.name);
+        bytes.push(label.arity);
+        bytes.extend(label.line.to_le_bytes());
+        bytes.extend(label.col.to_le_bytes());
+    }
+}
+
+fn u32(bytes: &mut Vec<u8>, n: usize) {
+    bytes.extend((n as u32).to_le_bytes());
+}
+
+fn string(bytes: &mut Vec<u8>, s: &str) {
+    u32(bytes, s.len());
+    bytes.extend(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        return Some(taken);
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        return Some(u64::from_le_bytes(b));
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn symbol_type(&mut self) -> Option<SymbolType> {
+        match self.u8()? {
+            0 => Some(SymbolType::Int),
+            1 => Some(SymbolType::Str),
+            _ => None,
+        }
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.u32()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+}
+
+fn decode(bytes: &[u8]) -> Option<Object> {
+    let mut r = Reader { bytes };
+
+    // objects of other compiler versions are compiled again
+    if r.take(4)? != MAGIC || r.string()? != VERSION {
+        return None;
+    }
+
+    let module = r.string()?;
+    let path = r.string()?;
+    let hash = r.u64()?;
+
+    let exports = r.list(|r| Some((r.string()?, r.u8()?)))?;
+    let uses = r.list(|r| {
+        let name = r.string()?;
+        let len = r.u8()? as usize;
+        Some((name, r.take(len)?.to_vec()))
+    })?;
+
+    let functions = r.list(|r| {
+        let name = r.string()?;
+        let argc = r.u8()?;
+        let localc = r.u8()?;
+        let len = r.u32()?;
+        let code = Bytecode::decode(r.take(len as usize)?, len);
+        let positions = r.list(|r| Some((r.u32()?, r.u32()?, r.u32()?)))?;
+
+        Some(ObjectFunction {
+            name,
+            argc,
+            localc,
+            code,
+            positions,
+        })
+    })?;
+
+    let constants = r.list(|r| match r.u8()? {
+        0 => Some(Constant::Int(r.u32()? as i32)),
+        1 => Some(Constant::String(r.string()?)),
+        _ => None,
+    })?;
+
+    let atoms = r.list(|r| r.string())?;
+
+    let signatures = r.list(|r| {
+        let index = r.u32()?;
+        let name = r.string()?;
+        let args = (0..r.u8()?)
+            .map(|_| r.symbol_type())
+            .collect::<Option<Vec<_>>>()?;
+        Some((index, name, args, r.symbol_type()?))
+    })?;
+
+    let groups = r.list(|r| r.list(|r| r.u32()))?;
+
+    let labels = r.list(|r| {
+        Some(ObjectLabel {
+            function: r.u32()?,
+            index: r.u32()?,
+            name: r.string()?,
+            arity: r.u8()?,
+            line: r.u32()?,
+            col: r.u32()?,
+        })
+    })?;
+
+    Some(Object {
+        module,
+        path,
+        hash,
+        exports,
+        uses,
+        functions,
+        constants,
+        atoms,
+        signatures,
+        groups,
+        labels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::{cell::RefCell, rc::Rc};
+
+    use vm::{deserialize, VM};
+    use vm_bytecode::Bytecode;
+
+    use super::Cache;
+    use crate::compiler::Constant;
+    use crate::object::{Object, ObjectFunction};
+    use crate::{loader::compile, serialize::serialize};
+
+    fn object(path: &str, hash: u64) -> Object {
+        Object {
+            module: String::from("util"),
+            path: path.to_owned(),
+            hash,
+            exports: vec![(String::from("g"), 1)],
+            uses: vec![(String::from("h"), vec![1, 2])],
+            functions: vec![ObjectFunction {
+                name: String::from("g"),
+                argc: 1,
+                localc: 1,
+                code: vec![
+                    Bytecode::LocalGet(0),
+                    Bytecode::ConstantGet(0),
+                    Bytecode::Add,
+                ],
+                positions: vec![(0, 3, 12)],
+            }],
+            constants: vec![Constant::Int(2), Constant::String(String::from("a"))],
+            atoms: vec![String::from("ok")],
+            signatures: Vec::new(),
+            groups: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn objects_round_trip() {
+        let dir = std::env::temp_dir().join(format!("ot-cache-objects-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+
+        cache.store(&object("./util.ot", 7));
+        let loaded = cache.load("./util.ot", 7).unwrap();
+        assert_eq!(
+            format!("{:?}", loaded),
+            format!("{:?}", object("./util.ot", 7))
+        );
+
+        // changed sources and other files with the same name aren't loaded
+        assert!(cache.load("./util.ot", 8).is_none());
+        assert!(cache.load("./lib/util.ot", 7).is_none());
+
+        // neither are broken files
+        std::fs::write(cache.file("./util.ot"), b"OTCO").unwrap();
+        assert!(cache.load("./util.ot", 7).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Builds the project with the cache and returns what it prints
+    fn build(root: &Path, cache: &Cache) -> String {
+        let bytes = compile(
+            root.to_str().unwrap(),
+            false,
+            false,
+            false,
+            Some(cache),
+            |ctx| serialize(&ctx, true),
+        );
+
+        let (start, constants, functions, signatures, groups, debug) = deserialize(&bytes).unwrap();
+        let output = Rc::new(RefCell::new(String::new()));
+        let print = {
+            let output = output.clone();
+            move |s: &str| output.borrow_mut().push_str(s)
+        };
+        VM::new(
+            constants, functions, signatures, groups, debug, print, false, false,
+        )
+        .run(start, Vec::new())
+        .unwrap();
+
+        let output = output.borrow().clone();
+        return output;
+    }
+
+    #[test]
+    fn changed_modules_are_compiled_again() {
+        let dir = std::env::temp_dir().join(format!("ot-cache-project-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = Cache::new(dir.join(".ot-cache"));
+        let (main, util) = (dir.join("main.ot"), dir.join("util.ot"));
+        let main_object = || std::fs::read(cache.file(main.to_str().unwrap())).unwrap();
+
+        std::fs::write(&main, "mod main\n\n(print (g 1))\n").unwrap();
+        std::fs::write(&util, "mod util\n\n(defn g [n] (* n 2))\n").unwrap();
+        assert_eq!(build(&dir, &cache), "2");
+        let stored = main_object();
+        assert_eq!(build(&dir, &cache), "2");
+
+        // the main module is linked with the new code of `g`
+        std::fs::write(&util, "mod util\n\n(defn g [n] (* n 3))\n").unwrap();
+        assert_eq!(build(&dir, &cache), "3");
+        assert_eq!(main_object(), stored);
+
+        // arities of `g` changed, so the main module is compiled again
+        std::fs::write(
+            &util,
+            "mod util\n\n(defn g [n] (* n 4))\n\n(defn g [n m] (* n m))\n",
+        )
+        .unwrap();
+        assert_eq!(build(&dir, &cache), "4");
+        assert_ne!(main_object(), stored);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
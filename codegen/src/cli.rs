@@ -1,27 +1,195 @@
 use std::env::args;
 
+use crate::doc::Format;
+
+pub const HELP: &str = "\
+OT compiler
+
+Usage: otc <command> [options] [root] [-- <args>...]
+
+Commands:
+    build    compile the project in root directory (default `./`)
+    check    parse and analyse the project without writing anything
+    run      compile the project in memory and run it
+    fmt      format source files in root directory, or a single file
+    repl     evaluate expressions interactively
+    test     compile the project with its tests and run them
+    doc      write documentation of functions in every module
+
+Options:
+    --out <path>        where to write the bytecode (default `./.out`), or documentation (default `./doc`)
+    --release           omit debug information from the output
+    --strip             same as `--release`
+    --deny-warnings     fail when any warning is reported
+    --debug             print disassembled bytecode
+    --bundle <path>     create standalone executable instead of the bytecode
+    --runtime <path>    `otvm` executable used for bundling
+    --jit               enable JIT in `run` and in the bundled executable
+    --emit-c <path>     translate the program into C instead of the bytecode
+    --watch             build again whenever a source file changes
+    --no-cache          compile all modules instead of using `.ot-cache`
+    --check             with `fmt`, fail if any file is not formatted instead of writing
+    --format <md|html>  with `doc`, format of the pages (default `md`)
+    -- <args>...        with `run`, arguments of the program
+    -h, --help          print this help
+    -V, --version       print version";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Build,
+    Check,
+    Run,
+    Fmt,
+    Repl,
+    Test,
+    Doc,
+    Help,
+    Version,
+}
+
+#[derive(Debug)]
 pub struct CLI {
+    pub command: Command,
     pub root: String,
+    pub out: String,
     pub debug: bool,
+    pub deny_warnings: bool,
+    pub strip: bool,
+    pub bundle: Option<String>,
+    pub runtime: Option<String>,
+    pub jit: bool,
+    pub emit_c: Option<String>,
+    pub watch: bool,
+    pub no_cache: bool,
+    pub check: bool,
+    pub format: Format,
+    // arguments of the program after `--`
+    pub args: Vec<String>,
 }
 
 impl CLI {
+    /// Parses arguments of the process, exits on invalid arguments
     pub fn new() -> CLI {
-        let args = args().skip(1);
+        match CLI::parse(args().skip(1)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                eprintln!("\x1b[31m\x1b[1mERROR:\x1b[0m {}\n\n{}", e, HELP);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<CLI, String> {
+        let mut args = args.peekable();
 
         let mut cli = CLI {
+            command: Command::Build,
             root: String::from("./"),
+            out: String::from("./.out"),
             debug: false,
+            deny_warnings: false,
+            strip: false,
+            bundle: None,
+            runtime: None,
+            jit: false,
+            emit_c: None,
+            watch: false,
+            no_cache: false,
+            check: false,
+            format: Format::Markdown,
+            args: Vec::new(),
         };
 
-        for arg in args {
-            if arg == "--debug" {
-                cli.debug = true;
-            } else {
-                cli.root = arg;
+        // without command the project is built
+        match args.peek().map(|arg| arg.as_str()) {
+            Some("build") => cli.command = Command::Build,
+            Some("check") => cli.command = Command::Check,
+            Some("run") => cli.command = Command::Run,
+            Some("fmt") => cli.command = Command::Fmt,
+            Some("repl") => cli.command = Command::Repl,
+            Some("test") => cli.command = Command::Test,
+            Some("doc") => cli.command = Command::Doc,
+            Some(arg) if arg.starts_with("-") => (),
+            Some(arg) => return Err(format!("Unknown command `{}`", arg)),
+            None => (),
+        }
+        if cli.command != Command::Build || args.peek().map(|arg| arg == "build") == Some(true) {
+            args.next();
+        }
+
+        let mut root = None;
+        let mut out = None;
+        let mut format = None;
+        let mut program_args = None;
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .ok_or_else(|| format!("`{}` expects a path", flag))
+            };
+
+            match arg.as_str() {
+                "-h" | "--help" => cli.command = Command::Help,
+                "-V" | "--version" => cli.command = Command::Version,
+                "--out" => out = Some(value(&arg)?),
+                "--release" | "--strip" => cli.strip = true,
+                "--deny-warnings" => cli.deny_warnings = true,
+                "--debug" => cli.debug = true,
+                "--bundle" => cli.bundle = Some(value(&arg)?),
+                "--runtime" => cli.runtime = Some(value(&arg)?),
+                "--jit" => cli.jit = true,
+                "--emit-c" => cli.emit_c = Some(value(&arg)?),
+                "--watch" => cli.watch = true,
+                "--no-cache" => cli.no_cache = true,
+                "--check" => cli.check = true,
+                "--format" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` expects a format", arg))?;
+                    format = Some(
+                        Format::parse(&value)
+                            .ok_or_else(|| format!("Unknown format `{}`", value))?,
+                    );
+                }
+                "--" => {
+                    program_args = Some(args.by_ref().collect());
+                    break;
+                }
+                flag if flag.starts_with("-") => return Err(format!("Unknown option `{}`", flag)),
+                _ if root.is_some() => return Err(format!("Unexpected argument `{}`", arg)),
+                _ => root = Some(arg),
+            }
+        }
+
+        if let Some(root) = root {
+            cli.root = root;
+        }
+        match out {
+            Some(out) => cli.out = out,
+            None if cli.command == Command::Doc => cli.out = String::from("./doc"),
+            None => (),
+        }
+
+        if cli.watch && cli.command != Command::Build {
+            return Err(String::from("`--watch` can be used only with `build`"));
+        }
+        if cli.check && cli.command != Command::Fmt {
+            return Err(String::from("`--check` can be used only with `fmt`"));
+        }
+        match program_args {
+            Some(_) if cli.command != Command::Run => {
+                return Err(String::from("`--` can be used only with `run`"));
+            }
+            Some(args) => cli.args = args,
+            None => (),
+        }
+        match format {
+            Some(_) if cli.command != Command::Doc => {
+                return Err(String::from("`--format` can be used only with `doc`"));
             }
+            Some(format) => cli.format = format,
+            None => (),
         }
 
-        return cli;
+        return Ok(cli);
     }
 }
@@ -1,13 +1,17 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::path::Path;
 
 use error::Error;
 use parser::{
-    AritheticsOrCall, Arithmetics, Block, Exp, FunctionDef, Identifier, Import, Symbol, ValOrExp,
-    Value, VariableDef,
+    AritheticsOrCall, Arithmetics, Block, Exp, FunctionDef, Identifier, Import, Symbol, Test,
+    ValOrExp, Value, VariableDef,
 };
 
 use crate::{
-    error::CompilerError, fanalysis::FunctionAnalysis, signatures::Signatures, util::escape_string,
+    error::CompilerError,
+    fanalysis::{FunctionAnalysis, BUILTINS},
+    signatures::Signatures,
+    util::escape_string,
     variables::Variable,
 };
 use vm_bytecode::Bytecode;
@@ -24,8 +28,8 @@ macro_rules! ignore {
 macro_rules! raise_compiler_error {
     ($($t: tt)+) => {
         {
-            println!("\x1b[31m\x1b[1mCOMPILER ERROR:\x1b[0m {}", format!($($t)+));
-            std::process::exit(0);
+            eprintln!("\x1b[31m\x1b[1mCOMPILER ERROR:\x1b[0m {}", format!($($t)+));
+            std::process::exit(1);
         }
     };
 }
@@ -36,31 +40,52 @@ pub enum Constant {
     String(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    pub col: usize,
+}
+
+// Bytecode of function with positions in source where its instructions start
+pub type FunctionCode<'a> = (Vec<Bytecode>, Vec<(u32, Position<'a>)>);
+
 #[derive(Debug)]
 pub struct Label<'a> {
-    name: Identifier<'a>,
-    arity: u8,
-    index: usize,
+    pub(crate) name: Identifier<'a>,
+    pub(crate) arity: u8,
+    pub(crate) index: usize,
+    // package the call is made from, its functions are called before the others
+    pub(crate) namespace: Option<&'a str>,
 }
 
 #[derive(Debug)]
 pub struct CompilerCtx<'a> {
-    vars: Variables<'a>,
+    pub(crate) vars: Variables<'a>,
 
     pub constants: Vec<Constant>,
     pub functions: Functions,
     pub signatures: Signatures<'a>,
 
-    fanalysis: FunctionAnalysis,
+    pub(crate) fanalysis: FunctionAnalysis,
 
-    current_function: Vec<Bytecode>,
+    pub(crate) current_function: Vec<Bytecode>,
+    current_positions: Vec<(u32, Position<'a>)>,
 
-    atoms: Atoms<'a>,
+    // index of function, name and its positions
+    pub debug: Vec<(u32, String, Vec<(u32, Position<'a>)>)>,
 
-    labels: Vec<((String, u8), Label<'a>)>,
+    pub(crate) atoms: Atoms<'a>,
+
+    pub(crate) labels: Vec<((String, u8), Label<'a>)>,
     current_labels: Vec<Label<'a>>,
+    // namespaces of linked dependencies, their functions are named `namespace::name`
+    pub(crate) namespaces: Vec<&'a str>,
 
     lambdac: u32,
+
+    // directories searched for libraries imported by FFI
+    pub(crate) library_paths: Vec<String>,
 }
 
 impl<'a> CompilerCtx<'a> {
@@ -74,13 +99,19 @@ impl<'a> CompilerCtx<'a> {
             fanalysis,
 
             current_function: Vec::new(),
+            current_positions: Vec::new(),
+
+            debug: Vec::new(),
 
             atoms: Atoms::new(),
 
             labels: Vec::new(),
             current_labels: Vec::new(),
+            namespaces: Vec::new(),
 
             lambdac: 0,
+
+            library_paths: Vec::new(),
         }
     }
 
@@ -91,8 +122,25 @@ impl<'a> CompilerCtx<'a> {
     pub fn swap_variables(&mut self, new_vars: &mut Variables<'a>) {
         std::mem::swap(&mut self.vars, new_vars);
     }
-    pub fn swap_func(&mut self, new_func: &mut Vec<Bytecode>) {
-        std::mem::swap(&mut self.current_function, new_func);
+    pub fn swap_func(&mut self, new_func: &mut FunctionCode<'a>) {
+        std::mem::swap(&mut self.current_function, &mut new_func.0);
+        std::mem::swap(&mut self.current_positions, &mut new_func.1);
+    }
+
+    /// Marks the following instructions as compiled from given identifier
+    pub fn position(&mut self, id: &Identifier<'a>) {
+        let index = self.current_function.len() as u32;
+        let position = Position {
+            file: id.file,
+            line: id.line,
+            col: id.start,
+        };
+
+        match self.current_positions.last_mut() {
+            Some((_, last)) if *last == position => (),
+            Some((last_index, last)) if *last_index == index => *last = position,
+            _ => self.current_positions.push((index, position)),
+        }
     }
     pub fn swap_labels(&mut self, new_labels: &mut Vec<Label<'a>>) {
         std::mem::swap(&mut self.current_labels, new_labels);
@@ -102,65 +150,119 @@ impl<'a> CompilerCtx<'a> {
         std::mem::take(&mut self.labels)
             .into_iter()
             .for_each(|((fname, fargc), label)| {
-                if let Some(f) = self.functions.get(label.name.val, label.arity) {
-                    let code = self.functions.get_code(fname, fargc).unwrap();
-                    match code[label.index] {
-                        Bytecode::Call(_) => code[label.index] = Bytecode::Call(f),
-                        Bytecode::CallRet(_) => code[label.index] = Bytecode::CallRet(f),
-                        // Can't be reached because label can't refer to non-call bytecode
-                        _ => unreachable!(),
+                match self.callee(&label) {
+                    Ok(f) => {
+                        let code = self.functions.get_code(fname, fargc).unwrap();
+                        match code[label.index] {
+                            Bytecode::Call(_) => code[label.index] = Bytecode::Call(f),
+                            Bytecode::CallRet(_) => code[label.index] = Bytecode::CallRet(f),
+                            // Can't be reached because label can't refer to non-call bytecode
+                            _ => unreachable!(),
+                        }
                     }
-                } else {
-                    ignore!(CompilerError::new(
-                        format!("Unknown function `{}`", label.name.val),
+                    Err(message) => ignore!(CompilerError::new(
+                        message,
                         &label.name.file,
                         label.name.line,
                         label.name.start,
                     )
-                    .display())
+                    .display()),
                 }
             });
     }
 
+    /// Function called by the label, functions of the caller's package come first, then the ones
+    /// of the project and the prelude and then the one dependency defining it
+    fn callee(&mut self, label: &Label) -> Result<u32, String> {
+        let (name, arity) = (label.name.val, label.arity);
+        let own = label.namespace.and_then(|namespace| {
+            self.functions
+                .get(format!("{}::{}", namespace, name), arity)
+        });
+        if let Some(f) = own.or_else(|| self.functions.get(name, arity)) {
+            return Ok(f);
+        }
+
+        let mut found = Vec::new();
+        for namespace in self.namespaces.clone() {
+            if let Some(f) = self
+                .functions
+                .get(format!("{}::{}", namespace, name), arity)
+            {
+                found.push((namespace, f));
+            }
+        }
+        return match found.as_slice() {
+            [] => Err(self.fanalysis.undefined_message(name, arity)),
+            [(_, f)] => Ok(*f),
+            [(first, _), (second, _), ..] => Err(format!(
+                "call of `{}/{}` is ambiguous, it is defined in packages `{}` and `{}`",
+                name, arity, first, second
+            )),
+        };
+    }
+
     pub fn lambdac_increase(&mut self) -> u32 {
         let curr = self.lambdac;
         self.lambdac += 1;
         return curr;
     }
 
-    pub fn flush_func(&mut self, name: impl Into<String>, argc: u8, localc: u8) {
+    pub fn flush_func(&mut self, name: impl Into<String>, argc: u8, localc: u8) -> u32 {
         let mut f = Vec::new();
         std::mem::swap(&mut self.current_function, &mut f);
 
         let name = name.into();
-        self.functions.define(name.clone(), f, argc, localc);
+        let index = self.functions.define(name.clone(), f, argc, localc);
+
+        // code of function defined again replaces the previous one with its positions and calls
+        let key = (name.clone(), argc);
+        self.debug.retain(|(i, _, _)| *i != index);
+        self.labels.retain(|(function, _)| *function != key);
+
+        self.debug.push((
+            index,
+            name.clone(),
+            std::mem::take(&mut self.current_positions),
+        ));
         self.labels.extend(
             self.current_labels
                 .drain(..)
                 .map(|label| ((name.clone(), argc), label)),
         );
+        return index;
     }
 
-    pub fn flush_func_with_locals(&mut self, name: impl Into<String>, argc: u8) {
-        let mut f = Vec::new();
-        std::mem::swap(&mut self.current_function, &mut f);
-
-        let name = name.into();
-        self.functions
-            .define(name.clone(), f, argc, self.vars.reset());
+    /// Path of the library in the first library path that has it, otherwise the name is kept
+    /// and the library is looked up when the program runs
+    pub(crate) fn find_library(&self, name: &str) -> String {
+        self.library_paths
+            .iter()
+            .map(|dir| Path::new(dir).join(name))
+            .find(|path| path.is_file())
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|| name.to_owned())
+    }
 
-        self.labels.extend(
-            self.current_labels
-                .drain(..)
-                .map(|label| ((name.clone(), argc), label)),
-        );
+    pub fn flush_func_with_locals(&mut self, name: impl Into<String>, argc: u8) {
+        let localc = self.vars.reset();
+        self.flush_func(name, argc, localc);
     }
 }
 
 pub fn block<'a>(ctx: &mut CompilerCtx<'a>, bl: Block<'a>, is_tail: bool) {
     match bl {
         Block::Import(Import { path, symbols }) => {
-            val_or_expression(ctx, path, false);
+            match path {
+                ValOrExp::Val(Value::String(name)) => {
+                    let library = ctx.find_library(&escape_string(name.val));
+                    ctx.constants.push(Constant::String(library));
+                    ctx.current_function
+                        .push(Bytecode::ConstantGet((ctx.constants.len() - 1) as u8));
+                }
+                path => val_or_expression(ctx, path, false),
+            }
 
             let group = ctx.signatures.new_group();
 
@@ -170,7 +272,7 @@ pub fn block<'a>(ctx: &mut CompilerCtx<'a>, bl: Block<'a>, is_tail: bool) {
 
             ctx.current_function.push(Bytecode::Import(group));
         }
-        Block::VariableDef(VariableDef { name, v_or_exp }) => {
+        Block::VariableDef(VariableDef { name, v_or_exp, .. }) => {
             val_or_expression(ctx, v_or_exp, false);
             ctx.current_function.push(Bytecode::LocalSet(
                 ctx.vars.define_variable(name.val).unwrap_or_else(|| {
@@ -184,43 +286,15 @@ pub fn block<'a>(ctx: &mut CompilerCtx<'a>, bl: Block<'a>, is_tail: bool) {
                 }),
             ));
         }
-        Block::FunctionDef(FunctionDef { name, types }) => {
+        Block::FunctionDef(FunctionDef { name, types, .. }) => {
             let mut saved_vars = Variables::new();
-            let mut saved_func = Vec::new();
+            let mut saved_func = Default::default();
             let mut saved_labels = Vec::new();
             ctx.swap_variables(&mut saved_vars);
             ctx.swap_func(&mut saved_func);
             ctx.swap_labels(&mut saved_labels);
 
-            // Own group by
-            let mut groups: HashMap<usize, Vec<(Vec<Value>, ValOrExp)>> = HashMap::new();
-            types
-                .into_iter()
-                .for_each(|(args, body)| groups.entry(args.len()).or_default().push((args, body)));
-
-            for (argc, group) in groups {
-                let mut highest_local = 0;
-                for (args, body) in group {
-                    let labelc = resolve_args(ctx, args);
-                    val_or_expression(ctx, body, true);
-                    match *ctx.current_function.last().unwrap() {
-                        Bytecode::Call(dw) => {
-                            ctx.current_function.pop();
-                            ctx.current_function.push(Bytecode::CallRet(dw));
-                        }
-                        _ => ctx.current_function.push(Bytecode::Ret),
-                    }
-                    (0..labelc).for_each(|_| {
-                        ctx.current_function.push(Bytecode::Else);
-                        ctx.current_function.push(Bytecode::End);
-                    });
-                    let lc = ctx.vars.reset();
-                    if highest_local < lc {
-                        highest_local = lc;
-                    }
-                }
-                ctx.flush_func(name.val, argc as u8, highest_local);
-            }
+            clauses(ctx, name.val, types);
 
             ctx.swap_variables(&mut saved_vars);
             ctx.swap_func(&mut saved_func);
@@ -230,9 +304,102 @@ pub fn block<'a>(ctx: &mut CompilerCtx<'a>, bl: Block<'a>, is_tail: bool) {
             expression(ctx, exp, is_tail);
             ctx.current_function.push(Bytecode::Remove);
         }
+        // only `otc test` compiles tests, see `test`
+        Block::Test(_) => (),
     }
 }
 
+/// Name of the function a test is compiled into, tests of different modules can share names
+pub fn test_function_name(module: &str, name: &str) -> String {
+    format!("@test:{}::{}", module, name)
+}
+
+/// Name of the test as `module::name`, `None` when the function is not a test
+pub fn test_name(function: &str) -> Option<&str> {
+    function.strip_prefix("@test:")
+}
+
+/// Compiles the test into a function without arguments, it can't access variables outside of it
+pub fn test<'a>(ctx: &mut CompilerCtx<'a>, module: &str, Test { name, blocks }: Test<'a>) {
+    let mut saved_vars = Variables::new();
+    let mut saved_func = Default::default();
+    let mut saved_labels = Vec::new();
+    ctx.swap_variables(&mut saved_vars);
+    ctx.swap_func(&mut saved_func);
+    ctx.swap_labels(&mut saved_labels);
+
+    clauses(
+        ctx,
+        &test_function_name(module, name.val),
+        vec![(Vec::new(), ValOrExp::Exp(Exp::Do { blocks }))],
+    );
+
+    ctx.swap_variables(&mut saved_vars);
+    ctx.swap_func(&mut saved_func);
+    ctx.swap_labels(&mut saved_labels);
+}
+
+// Compiles clauses into one function per arity, the functions are defined with consecutive indexes
+// ordered by arity, returns index of the first one and number of arities
+fn clauses<'a>(
+    ctx: &mut CompilerCtx<'a>,
+    name: &str,
+    types: Vec<(Vec<Value<'a>>, ValOrExp<'a>)>,
+) -> (u32, u8) {
+    // Own group by
+    let mut groups: BTreeMap<usize, Vec<(Vec<Value>, ValOrExp)>> = BTreeMap::new();
+    types
+        .into_iter()
+        .for_each(|(args, body)| groups.entry(args.len()).or_default().push((args, body)));
+
+    // Nested lambdas are defined while compiling clauses, so flushing has to wait until all
+    // arities are compiled to keep their indexes consecutive
+    let mut compiled = Vec::with_capacity(groups.len());
+
+    for (argc, group) in groups {
+        let mut highest_local = 0;
+        for (args, body) in group {
+            let labelc = resolve_args(ctx, args);
+            val_or_expression(ctx, body, true);
+            match *ctx.current_function.last().unwrap() {
+                Bytecode::Call(dw) => {
+                    ctx.current_function.pop();
+                    ctx.current_function.push(Bytecode::CallRet(dw));
+                }
+                _ => ctx.current_function.push(Bytecode::Ret),
+            }
+            (0..labelc).for_each(|_| {
+                ctx.current_function.push(Bytecode::Else);
+                ctx.current_function.push(Bytecode::End);
+            });
+            let lc = ctx.vars.reset();
+            if highest_local < lc {
+                highest_local = lc;
+            }
+        }
+
+        let mut func = Default::default();
+        let mut labels = Vec::new();
+        ctx.swap_func(&mut func);
+        ctx.swap_labels(&mut labels);
+        compiled.push((argc as u8, highest_local, func, labels));
+    }
+
+    let indexes = compiled
+        .into_iter()
+        .map(|(argc, localc, mut func, mut labels)| {
+            ctx.swap_func(&mut func);
+            ctx.swap_labels(&mut labels);
+            ctx.flush_func(name, argc, localc)
+        })
+        .collect::<Vec<u32>>();
+
+    return (
+        indexes.first().map(|&x| x).unwrap_or(0),
+        indexes.len() as u8,
+    );
+}
+
 fn resolve_args<'a>(ctx: &mut CompilerCtx<'a>, args: Vec<Value<'a>>) -> u32 {
     let mut labelc: u32 = 0;
     args.into_iter().for_each(|arg| match arg {
@@ -258,23 +425,51 @@ fn resolve_args<'a>(ctx: &mut CompilerCtx<'a>, args: Vec<Value<'a>>) -> u32 {
     return labelc;
 }
 
-fn val_or_expression<'a>(ctx: &mut CompilerCtx<'a>, v_exp: ValOrExp<'a>, is_tail: bool) {
+pub(crate) fn val_or_expression<'a>(ctx: &mut CompilerCtx<'a>, v_exp: ValOrExp<'a>, is_tail: bool) {
     match v_exp {
         ValOrExp::Val(v) => match v {
-            Value::Var(name) => ctx.current_function.push(
-                match ctx.vars.get(name.val).unwrap_or_else(|| {
-                    ignore!(CompilerError::new(
-                        format!("Variable with name `{}` does not exist", name.val),
+            Value::Var(name)
+                if !ctx.vars.is_defined(name.val) && ctx.fanalysis.exists(name.val) =>
+            {
+                let arity = match ctx.fanalysis.arities(name.val).unwrap() {
+                    &[arity] => arity,
+                    arities => ignore!(CompilerError::new(
+                        format!(
+                            "Function `{}` has multiple arities ({}), use `#'{}/<arity>` to pick one",
+                            name.val,
+                            arities
+                                .iter()
+                                .map(|arity| format!("{}/{}", name.val, arity))
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                            name.val
+                        ),
                         name.file,
                         name.line,
                         name.start,
                     )
-                    .display())
-                }) {
-                    Variable::Local(l) => Bytecode::LocalGet(l),
-                    Variable::Array(a) => Bytecode::LocalArrayGet(0, a),
-                },
-            ),
+                    .display()),
+                };
+                function_reference(ctx, name, arity);
+            }
+            Value::Var(name) => {
+                ctx.position(&name);
+                ctx.current_function.push(
+                    match ctx.vars.get(name.val).unwrap_or_else(|| {
+                        ignore!(CompilerError::new(
+                            format!("Variable with name `{}` does not exist", name.val),
+                            name.file,
+                            name.line,
+                            name.start,
+                        )
+                        .display())
+                    }) {
+                        Variable::Local(l) => Bytecode::LocalGet(l),
+                        Variable::Array(a) => Bytecode::LocalArrayGet(0, a),
+                    },
+                )
+            }
+            Value::Function(name, arity) => function_reference(ctx, name, arity),
             Value::Int(dw) => {
                 let local = ctx
                     .constants
@@ -302,6 +497,44 @@ fn val_or_expression<'a>(ctx: &mut CompilerCtx<'a>, v_exp: ValOrExp<'a>, is_tail
     }
 }
 
+// Wraps named function into lambda without context, so it can be passed around as a value
+fn function_reference<'a>(ctx: &mut CompilerCtx<'a>, name: Identifier<'a>, arity: u8) {
+    let wrapper = format!("@{}/{}", name.val, arity);
+
+    let index = match ctx.functions.get(&wrapper, arity) {
+        Some(index) => index,
+        None => {
+            let mut saved_func = Default::default();
+            let mut saved_labels = Vec::new();
+            ctx.swap_func(&mut saved_func);
+            ctx.swap_labels(&mut saved_labels);
+
+            // Local 0 holds the lambda context, arguments start at 1
+            (1..=arity).for_each(|l| ctx.current_function.push(Bytecode::LocalGet(l)));
+
+            let index = ctx.functions.get(name.val, arity).unwrap_or_else(|| {
+                ctx.current_labels.push(Label {
+                    name,
+                    arity,
+                    index: ctx.current_function.len(),
+                    namespace: None,
+                });
+                0
+            });
+            ctx.current_function.push(Bytecode::CallRet(index));
+
+            ctx.flush_func(&wrapper, arity, arity + 1);
+
+            ctx.swap_func(&mut saved_func);
+            ctx.swap_labels(&mut saved_labels);
+
+            ctx.functions.get(&wrapper, arity).unwrap()
+        }
+    };
+
+    ctx.current_function.push(Bytecode::NewLambda(index, 0, 1));
+}
+
 fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
     match exp {
         Exp::Basic { op, args } => match op {
@@ -310,18 +543,21 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                 args.into_iter()
                     .for_each(|arg| val_or_expression(ctx, arg, false));
 
+                ctx.position(&name);
                 ctx.current_function.push(Bytecode::CallDynamic(
                     ctx.signatures
                         .get(name.val, argc)
                         .expect(&format!("Unknown dynamic library call `{}`", name.val)),
                 ));
             }
-            AritheticsOrCall::Arithmetics(a) => arithetics(ctx, a, args),
+            AritheticsOrCall::Arithmetics(a, op) => arithetics(ctx, a, op, args),
             AritheticsOrCall::Call(name) => {
                 let argc = args.len() as u8;
                 args.into_iter()
                     .for_each(|arg| val_or_expression(ctx, arg, false));
 
+                ctx.position(&name);
+
                 match name.val {
                     "pair" => {
                         if argc != 2 {
@@ -427,6 +663,19 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                         ctx.current_function.push(Bytecode::ArrayLen);
                         return;
                     }
+                    "assert-eq" => {
+                        if argc != 2 {
+                            CompilerError::new(
+                                format!("assert-eq/2 expects 2 arguments, found {}", argc),
+                                name.file,
+                                name.line,
+                                name.start,
+                            )
+                            .display();
+                        }
+                        ctx.current_function.push(Bytecode::AssertEq);
+                        return;
+                    }
                     "strlen" => {
                         if argc != 1 {
                             CompilerError::new(
@@ -440,7 +689,31 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                         ctx.current_function.push(Bytecode::StringLen);
                         return;
                     }
-                    _ if !ctx.fanalysis.exists(name.val) => {
+                    _ if instruction(name.val).is_some() => {
+                        let (_, arity) = BUILTINS
+                            .iter()
+                            .find(|(builtin, _)| *builtin == name.val)
+                            .unwrap();
+                        if argc != *arity {
+                            CompilerError::new(
+                                format!(
+                                    "{}/{} expects {} argument{}, found {}",
+                                    name.val,
+                                    arity,
+                                    arity,
+                                    if *arity == 1 { "" } else { "s" },
+                                    argc
+                                ),
+                                name.file,
+                                name.line,
+                                name.start,
+                            )
+                            .display();
+                        }
+                        ctx.current_function.push(instruction(name.val).unwrap());
+                        return;
+                    }
+                    _ if ctx.vars.is_defined(name.val) || !ctx.fanalysis.exists(name.val) => {
                         if let Some(var) = ctx.vars.get(name.val) {
                             match var {
                                 Variable::Local(l) => {
@@ -451,7 +724,7 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                                 }
                             }
 
-                            ctx.current_function.push(Bytecode::CallLambda);
+                            ctx.current_function.push(Bytecode::CallLambda(argc));
                         } else {
                             CompilerError::new(
                                 format!("Function with name {} does not exists", name.val),
@@ -468,6 +741,7 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                                 name,
                                 arity: argc,
                                 index: ctx.current_function.len(),
+                                namespace: None,
                             });
                             0
                         });
@@ -484,7 +758,7 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
         Exp::If { cond, if_, else_ } => {
             match *cond {
                 ValOrExp::Exp(Exp::Basic {
-                    op: AritheticsOrCall::Arithmetics(Arithmetics::Eq),
+                    op: AritheticsOrCall::Arithmetics(Arithmetics::Eq, _),
                     args,
                 }) => {
                     let mut args = args.into_iter();
@@ -498,11 +772,11 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                 }
             }
             ctx.vars.scope();
-            val_or_expression(ctx, *if_, true);
+            val_or_expression(ctx, *if_, is_tail);
             ctx.current_function.push(Bytecode::Else);
             ctx.vars.end_scope();
             ctx.vars.scope();
-            val_or_expression(ctx, *else_, true);
+            val_or_expression(ctx, *else_, is_tail);
             ctx.current_function.push(Bytecode::End);
             ctx.vars.end_scope();
         }
@@ -517,24 +791,35 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
             }
             ctx.vars.end_scope();
         }
-        Exp::Lambda { args, body } => {
-            let mut saved_vars = Variables::new_lambda();
-            let mut saved_func = Vec::new();
+        Exp::Try {
+            body,
+            error,
+            handler,
+        } => {
+            // a tail call in the body would return past the handler
+            ctx.current_function.push(Bytecode::Try);
+            ctx.vars.scope();
+            val_or_expression(ctx, *body, false);
+            ctx.vars.end_scope();
+            ctx.current_function.push(Bytecode::Catch);
+
+            ctx.vars.scope();
+            let local = ctx.vars.define_variable(error.val).unwrap();
+            ctx.current_function.push(Bytecode::LocalSet(local));
+            val_or_expression(ctx, *handler, is_tail);
+            ctx.current_function.push(Bytecode::End);
+            ctx.vars.end_scope();
+        }
+        Exp::Lambda { types } => {
+            let mut saved_vars = Variables::new_lambda(&ctx.vars);
+            let mut saved_func = Default::default();
             let mut saved_labels = Vec::new();
             ctx.swap_variables(&mut saved_vars);
             ctx.swap_func(&mut saved_func);
             ctx.swap_labels(&mut saved_labels);
 
-            let argc = args.len() as u8;
-
-            resolve_args(ctx, args.into_iter().map(|s| Value::Var(s)).collect());
-            val_or_expression(ctx, *body, true);
-            ctx.current_function.push(Bytecode::Ret);
-
-            let localc = ctx.vars.reset();
-
             let name = format!("@lambda{}", ctx.lambdac_increase());
-            ctx.flush_func(&name, argc, localc);
+            let (index, arityc) = clauses(ctx, &name, types);
 
             ctx.swap_func(&mut saved_func);
             ctx.swap_labels(&mut saved_labels);
@@ -555,15 +840,49 @@ fn expression<'a>(ctx: &mut CompilerCtx<'a>, exp: Exp<'a>, is_tail: bool) {
                 );
             }
 
-            ctx.current_function.push(Bytecode::NewLambda(
-                ctx.functions.get(name, argc).unwrap(),
-                ctx_varc,
-            ));
+            ctx.current_function
+                .push(Bytecode::NewLambda(index, ctx_varc, arityc));
         }
     }
 }
 
-fn arithetics<'a>(ctx: &mut CompilerCtx<'a>, a: Arithmetics, args: Vec<ValOrExp<'a>>) {
+/// Builtins compiled to one instruction that takes all their arguments
+fn instruction(name: &str) -> Option<Bytecode> {
+    let bc = match name {
+        "concat" => Bytecode::StringConcat,
+        "substring" => Bytecode::Substring,
+        "char-at" => Bytecode::CharAt,
+        "char->str" => Bytecode::CharToString,
+        "str->int" => Bytecode::StringToInt,
+        "int->str" => Bytecode::IntToString,
+        "to-string" => Bytecode::ToString,
+        "split" => Bytecode::StringSplit,
+        "join" => Bytecode::StringJoin,
+        "index-of" => Bytecode::StringIndexOf,
+        "upper" => Bytecode::StringUpper,
+        "lower" => Bytecode::StringLower,
+        "compare" => Bytecode::StringCompare,
+        "read-file" => Bytecode::ReadFile,
+        "write-file" => Bytecode::WriteFile,
+        "append-file" => Bytecode::AppendFile,
+        "read-line" => Bytecode::ReadLine,
+        "read-all-stdin" => Bytecode::ReadAllStdin,
+        "file-exists?" => Bytecode::FileExists,
+        "args" => Bytecode::ProgramArgs,
+        "env" => Bytecode::Env,
+        "exit" => Bytecode::Exit,
+        "throw" => Bytecode::Throw,
+        _ => return None,
+    };
+    return Some(bc);
+}
+
+fn arithetics<'a>(
+    ctx: &mut CompilerCtx<'a>,
+    a: Arithmetics,
+    op: Identifier<'a>,
+    args: Vec<ValOrExp<'a>>,
+) {
     let mut args = args.into_iter();
     val_or_expression(
         ctx,
@@ -574,6 +893,7 @@ fn arithetics<'a>(ctx: &mut CompilerCtx<'a>, a: Arithmetics, args: Vec<ValOrExp<
 
     args.for_each(|arg| {
         val_or_expression(ctx, arg, false);
+        ctx.position(&op);
         ctx.current_function.push(match a {
             Arithmetics::Add => Bytecode::Add,
             Arithmetics::Sub => Bytecode::Sub,
@@ -590,3 +910,54 @@ fn arithetics<'a>(ctx: &mut CompilerCtx<'a>, a: Arithmetics, args: Vec<ValOrExp<
         });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use vm::{deserialize, RuntimeError, VM};
+
+    use crate::{loader::compile, serialize::serialize};
+
+    // Compiles the source as the main module of a project and runs it, returns the printed output
+    fn run(name: &str, source: &str) -> (String, Result<vm::Value, RuntimeError>) {
+        let dir = std::env::temp_dir().join(format!("ot-compiler-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.ot"), source).unwrap();
+        let bytes = compile(dir.to_str().unwrap(), false, false, false, None, |ctx| {
+            serialize(&ctx, true)
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (start, constants, functions, signatures, groups, debug) = deserialize(&bytes).unwrap();
+        let output = Rc::new(RefCell::new(String::new()));
+        let print = {
+            let output = output.clone();
+            move |s: &str| output.borrow_mut().push_str(s)
+        };
+        let result = VM::new(
+            constants, functions, signatures, groups, debug, print, false, false,
+        )
+        .run(start, Vec::new());
+
+        let output = output.borrow().clone();
+        return (output, result);
+    }
+
+    #[test]
+    fn branches_of_try_body_dont_tail_call() {
+        let (output, result) = run(
+            "try_top_level",
+            "mod main\n\n(defn h [x] x)\n\n(print (try (if 1 (h 5) 0) (catch e e)))\n(print \"end\")\n",
+        );
+        assert_eq!(output, "5end");
+        assert!(result.is_ok());
+
+        let (output, result) = run(
+            "try_in_function",
+            "mod main\n\n(defn h [x] x)\n\n(defn f [x] (+ 0 (try (if 1 (h x) 0) (catch e 99))))\n\n(defn g [x] (do (print (f x)) (throw :oops)))\n\n(print (try (g 1) (catch e 7)))\n",
+        );
+        assert_eq!(output, "17");
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use error::Error;
+use parser::{parse, Block, FileCxt, FunctionDef, Parser};
+
+use crate::util::source_value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+// Every `defn` of the same name in the module is shown as one function
+struct FunctionDoc<'a> {
+    name: &'a str,
+    arities: Vec<usize>,
+    // argument patterns of clauses as they are written
+    clauses: Vec<String>,
+    doc: Vec<&'a str>,
+}
+
+/// Writes documentation of every module in root into `out`, one page per module
+pub fn doc(root: &str, out: &str, format: Format) {
+    let mut paths = std::fs::read_dir(root)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter(|path| path.extension().unwrap_or_default() == "ot")
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_else(|e| fatal(&format!("can't read directory `{}`: {}", root, e)));
+    paths.sort();
+
+    if let Err(e) = std::fs::create_dir_all(out) {
+        fatal(&format!("can't create directory `{}`: {}", out, e));
+    }
+
+    for path in paths {
+        let path = path.to_string_lossy();
+        let source = std::fs::read_to_string(path.as_ref())
+            .unwrap_or_else(|e| fatal(&format!("can't read `{}`: {}", path, e)));
+
+        let (module, page) = document(&source, &path, format);
+        let page_path = Path::new(out).join(format!("{}.{}", module, format.extension()));
+        if let Err(e) = std::fs::write(&page_path, page) {
+            fatal(&format!("can't write `{}`: {}", page_path.display(), e));
+        }
+        println!("documented `{}` in `{}`", module, page_path.display());
+    }
+}
+
+/// Page documenting functions of the source file with the name of its module, exits on
+/// a syntax error
+pub fn document(source: &str, path: &str, format: Format) -> (String, String) {
+    let (ctx, blocks) = parse()
+        .parse(FileCxt::new(source, path))
+        .map_err(|e| {
+            e.display();
+            unreachable!()
+        })
+        .unwrap();
+
+    let mut functions: Vec<FunctionDoc> = Vec::new();
+    for block in &blocks {
+        let FunctionDef { name, types, doc } = match block {
+            Block::FunctionDef(def) => def,
+            _ => continue,
+        };
+
+        let index = match functions.iter().position(|f| f.name == name.val) {
+            Some(index) => index,
+            None => {
+                functions.push(FunctionDoc {
+                    name: name.val,
+                    arities: Vec::new(),
+                    clauses: Vec::new(),
+                    doc: Vec::new(),
+                });
+                functions.len() - 1
+            }
+        };
+        let function = &mut functions[index];
+
+        for (args, _) in types {
+            if !function.arities.contains(&args.len()) {
+                function.arities.push(args.len());
+            }
+            let args = args.iter().map(source_value).collect::<Vec<_>>();
+            function.clauses.push(format!("[{}]", args.join(" ")));
+        }
+        function.arities.sort();
+        function.doc.extend(doc.as_deref());
+    }
+
+    let file = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned());
+    let page = match format {
+        Format::Markdown => markdown(ctx.module, &file, &functions),
+        Format::Html => html(ctx.module, &file, &functions),
+    };
+    return (ctx.module.to_owned(), page);
+}
+
+// Skeleton of the definition with clauses, bodies are left out
+fn signature(function: &FunctionDoc) -> String {
+    let clauses = function
+        .clauses
+        .iter()
+        .map(|clause| format!("\t{} ...\n", clause))
+        .collect::<String>();
+    format!("(defn {}\n{})", function.name, clauses)
+}
+
+fn arities(function: &FunctionDoc) -> Vec<String> {
+    function
+        .arities
+        .iter()
+        .map(|arity| format!("{}/{}", function.name, arity))
+        .collect()
+}
+
+// Page for docusaurus, the title is used in the sidebar
+fn markdown(module: &str, file: &str, functions: &[FunctionDoc]) -> String {
+    let mut out = format!(
+        "---\ntitle: \"{}\"\n---\n\nFunctions of module `{}` defined in `{}`.\n",
+        module, module, file
+    );
+    if functions.is_empty() {
+        out += "\nThe module doesn't define any functions.\n";
+    }
+
+    for function in functions {
+        let arities = arities(function)
+            .iter()
+            .map(|arity| format!("`{}`", arity))
+            .collect::<Vec<_>>();
+
+        out += &format!("\n## `{}`\n\n{}\n\n", function.name, arities.join(", "));
+        out += &format!("```\n{}\n```\n", signature(function));
+        for doc in &function.doc {
+            out += &format!("\n{}\n", doc);
+        }
+    }
+    return out;
+}
+
+fn html(module: &str, file: &str, functions: &[FunctionDoc]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n<p>Functions of module <code>{}</code> defined in <code>{}</code>.</p>\n",
+        escape(module),
+        escape(module),
+        escape(module),
+        escape(file)
+    );
+    if functions.is_empty() {
+        out += "<p>The module doesn't define any functions.</p>\n";
+    }
+
+    for function in functions {
+        let arities = arities(function)
+            .iter()
+            .map(|arity| format!("<code>{}</code>", escape(arity)))
+            .collect::<Vec<_>>();
+
+        out += &format!(
+            "<h2 id=\"{}\"><code>{}</code></h2>\n<p>{}</p>\n<pre><code>{}</code></pre>\n",
+            escape(function.name),
+            escape(function.name),
+            arities.join(", "),
+            escape(&signature(function))
+        );
+        // blank lines separate paragraphs like in Markdown
+        for paragraph in function.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+            out += &format!("<p>{}</p>\n", escape(paragraph.trim()));
+        }
+    }
+    out += "</body>\n</html>\n";
+    return out;
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn fatal(msg: &str) -> ! {
+    println!("\x1b[31m\x1b[1mERROR:\x1b[0m {}", msg);
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{document, Format};
+
+    const SOURCE: &str = "mod math\n\n## Factorial of `n`\n(defn fact\n\t[0] 1\n\t[n] (* n (fact (- n 1)))\n)\n\n## Doubles <n>\n(defn twice [n] (* n 2))\n\n(defn twice [a b] (+ (twice a) (twice b)))\n";
+
+    #[test]
+    fn markdown_lists_functions() {
+        let (module, page) = document(SOURCE, "math.ot", Format::Markdown);
+
+        assert_eq!(module, "math");
+        assert_eq!(
+            page,
+            "---\ntitle: \"math\"\n---\n\nFunctions of module `math` defined in `math.ot`.\n\n## `fact`\n\n`fact/1`\n\n```\n(defn fact\n\t[0] ...\n\t[n] ...\n)\n```\n\nFactorial of `n`\n\n## `twice`\n\n`twice/1`, `twice/2`\n\n```\n(defn twice\n\t[n] ...\n\t[a b] ...\n)\n```\n\nDoubles <n>\n"
+        );
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let (_, page) = document(SOURCE, "math.ot", Format::Html);
+
+        assert!(page.contains("<h2 id=\"twice\"><code>twice</code></h2>"));
+        assert!(page.contains("<p>Doubles &lt;n&gt;</p>"));
+    }
+}
@@ -1,33 +1,146 @@
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 
 use parser::{Block, FunctionDef};
 
+/// Functions compiled directly into bytecode with their arity
+pub const BUILTINS: [(&str, u8); 33] = [
+    ("pair", 2),
+    ("left", 1),
+    ("right", 1),
+    ("print", 1),
+    ("new", 1),
+    ("get", 2),
+    ("set", 3),
+    ("len", 1),
+    ("strlen", 1),
+    ("assert-eq", 2),
+    ("concat", 2),
+    ("substring", 3),
+    ("char-at", 2),
+    ("char->str", 1),
+    ("str->int", 1),
+    ("int->str", 1),
+    ("to-string", 1),
+    ("split", 2),
+    ("join", 2),
+    ("index-of", 2),
+    ("upper", 1),
+    ("lower", 1),
+    ("compare", 2),
+    ("read-file", 1),
+    ("write-file", 2),
+    ("append-file", 2),
+    ("read-line", 0),
+    ("read-all-stdin", 0),
+    ("file-exists?", 1),
+    ("args", 0),
+    ("env", 1),
+    ("exit", 1),
+    ("throw", 1),
+];
+
 #[derive(Debug)]
 pub struct FunctionAnalysis {
-    functions: HashSet<String>,
+    functions: HashMap<String, Vec<u8>>,
+
+    // names looked up since the last `take_queried`, compiled module depends on them
+    queried: RefCell<BTreeSet<String>>,
+}
+
+impl Clone for FunctionAnalysis {
+    /// Copies the functions, the clone starts without queried names
+    fn clone(&self) -> FunctionAnalysis {
+        FunctionAnalysis {
+            functions: self.functions.clone(),
+            queried: RefCell::new(BTreeSet::new()),
+        }
+    }
 }
 
 impl FunctionAnalysis {
     pub fn new() -> FunctionAnalysis {
         FunctionAnalysis {
-            functions: HashSet::new(),
+            functions: HashMap::new(),
+            queried: RefCell::new(BTreeSet::new()),
         }
     }
 
-    pub fn add_function(&mut self, name: &str) {
-        self.functions.insert(name.to_owned());
+    pub fn add_function(&mut self, name: &str, argc: u8) {
+        let arities = self.functions.entry(name.to_owned()).or_default();
+        if !arities.contains(&argc) {
+            arities.push(argc);
+            arities.sort();
+        }
     }
 
     pub fn exists(&self, name: &str) -> bool {
-        self.functions.contains(name)
+        self.queried.borrow_mut().insert(name.to_owned());
+        self.functions.contains_key(name)
+    }
+
+    pub fn arities(&self, name: &str) -> Option<&[u8]> {
+        self.queried.borrow_mut().insert(name.to_owned());
+        self.functions.get(name).map(|arities| arities.as_slice())
+    }
+
+    /// Names looked up by `exists` or `arities`, clears them
+    pub fn take_queried(&self) -> Vec<String> {
+        std::mem::take(&mut *self.queried.borrow_mut())
+            .into_iter()
+            .collect()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|name| name.as_str())
+    }
+
+    /// Message for a call of function that is not defined with given arity
+    pub fn undefined_message(&self, name: &str, arity: u8) -> String {
+        match self.arities(name) {
+            Some(arities) => format!(
+                "`{}/{}` is not defined; {} {}",
+                name,
+                arity,
+                arities
+                    .iter()
+                    .map(|arity| format!("`{}/{}`", name, arity))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                if arities.len() == 1 {
+                    "exists"
+                } else {
+                    "exist"
+                }
+            ),
+            None => format!("Unknown function `{}`", name),
+        }
     }
 }
 
 pub fn analyse(blocks: &[Block], fanalysis: &mut FunctionAnalysis) {
     for block in blocks {
         match block {
-            Block::FunctionDef(FunctionDef { name, types: _ }) => fanalysis.add_function(name.val),
+            Block::FunctionDef(FunctionDef { name, types, .. }) => types
+                .iter()
+                .for_each(|(args, _)| fanalysis.add_function(name.val, args.len() as u8)),
             _ => (),
         }
     }
 }
+
+/// Functions defined in the blocks with their arities
+pub fn exports(blocks: &[Block]) -> Vec<(String, u8)> {
+    let mut exports = Vec::new();
+    for block in blocks {
+        if let Block::FunctionDef(FunctionDef { name, types, .. }) = block {
+            for (args, _) in types {
+                let export = (name.val.to_owned(), args.len() as u8);
+                if !exports.contains(&export) {
+                    exports.push(export);
+                }
+            }
+        }
+    }
+    return exports;
+}
@@ -0,0 +1,278 @@
+use parser::{parse, syntax, FileCxt, Node, Parser};
+
+use error::Error;
+
+// Longest line that is kept on one line, tabs count as 4 columns
+const WIDTH: usize = 100;
+const TAB: usize = 4;
+
+/// Formats the source into canonical layout, exits on parser error
+pub fn format(source: &str, path: &str) -> String {
+    // only valid programs are formatted
+    if let Err(e) = parse().parse(FileCxt::new(source, path)) {
+        e.display();
+    }
+
+    let nodes = match syntax().parse(FileCxt::new(source, path)) {
+        Ok((_, nodes)) => nodes,
+        Err(e) => {
+            e.display();
+            unreachable!()
+        }
+    };
+
+    let mut out = String::new();
+    let mut nodes = nodes.iter().peekable();
+
+    // module header
+    if let (Some((_, Node::Token(m))), Some((_, Node::Token(name)))) = (nodes.next(), nodes.next())
+    {
+        out += &format!("{} {}", m.val, name.val);
+    }
+    if let Some((0, Node::Comment(c))) = nodes.peek() {
+        out += &format!(" {}", c.val.trim_end());
+        nodes.next();
+    }
+    out += "\n";
+
+    let mut first = true;
+    for (lines, node) in nodes {
+        match node {
+            Node::Comment(c) if *lines == 0 && !first => {
+                out = format!("{} {}", out.trim_end(), c.val.trim_end())
+            }
+            _ => {
+                if first || *lines > 1 {
+                    out += "\n";
+                }
+                out += &self::node(node, 0, 0);
+            }
+        }
+        out += "\n";
+        first = false;
+    }
+
+    // line endings of the source are kept
+    if source.contains("\r\n") {
+        out = out.replace('\n', "\r\n");
+    }
+
+    return out;
+}
+
+fn node(node: &Node, indent: usize, col: usize) -> String {
+    match node {
+        Node::Token(id) => id.val.to_owned(),
+        Node::Comment(c) => c.val.trim_end().to_owned(),
+        Node::List { square, items } => match flat(node) {
+            Some(flat) if col + flat.len() <= WIDTH => flat,
+            _ => list(*square, items, indent, col),
+        },
+    }
+}
+
+// Node on one line, if it has no comments and no form that is always broken
+fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Token(id) => Some(id.val.to_owned()),
+        Node::Comment(_) => None,
+        Node::List { square, items } => {
+            // multiple blocks and clauses always have their own lines
+            match head(items) {
+                Some("do") if items.len() > 2 => return None,
+                Some("deftest") if items.len() > 3 => return None,
+                Some("defn") if items.len() > 4 => return None,
+                Some("lambda" | "λ") if items.len() > 3 => return None,
+                _ => (),
+            }
+
+            let items = items
+                .iter()
+                .map(|(_, item)| flat(item))
+                .collect::<Option<Vec<_>>>()?;
+            let (open, close) = brackets(*square);
+            Some(format!("{}{}{}", open, items.join(" "), close))
+        }
+    }
+}
+
+fn list(square: bool, items: &[(usize, Node)], indent: usize, col: usize) -> String {
+    let tokens = items
+        .iter()
+        .take_while(|(_, item)| matches!(item, Node::Token(_)))
+        .count();
+
+    match head(items) {
+        _ if square => block(square, items, 0, false, indent, col),
+        Some("do" | "try") => block(square, items, 1, false, indent, col),
+        Some("catch") => block(square, items, 2.min(tokens), false, indent, col),
+        Some("if" | "?") => block(square, items, 2.min(items.len()), false, indent, col),
+        Some("import") => block(square, items, 2.min(tokens), false, indent, col),
+        Some("deftest") if items.len() > 3 => {
+            block(square, items, 2.min(tokens), false, indent, col)
+        }
+        Some("def") | Some("deftest") => hanging(items, indent, col)
+            .unwrap_or_else(|| block(square, items, 2.min(tokens), false, indent, col)),
+        Some("defn") | Some("lambda" | "λ") => {
+            let head = if head(items) == Some("defn") {
+                2.min(tokens)
+            } else {
+                1
+            };
+            match items.len() == head + 2 {
+                true => hanging(items, indent, col),
+                false => None,
+            }
+            .unwrap_or_else(|| block(square, items, head, true, indent, col))
+        }
+        _ => hanging(items, indent, col)
+            .unwrap_or_else(|| block(square, items, 1.min(tokens), false, indent, col)),
+    }
+}
+
+// All items on the first line and the last one continues on the following lines, used only
+// when the last item is a block
+fn hanging(items: &[(usize, Node)], indent: usize, col: usize) -> Option<String> {
+    let (last, rest) = items.split_last()?;
+    let is_block = match &last.1 {
+        Node::List { items, .. } => {
+            matches!(head(items), Some("do" | "if" | "?" | "lambda" | "λ"))
+                || flat(&last.1).is_none()
+        }
+        _ => false,
+    };
+    if rest.is_empty() || !is_block {
+        return None;
+    }
+
+    let rest = rest
+        .iter()
+        .map(|(_, item)| flat(item))
+        .collect::<Option<Vec<_>>>()?
+        .join(" ");
+    if col + rest.len() >= WIDTH {
+        return None;
+    }
+
+    let start = format!("({} ", rest);
+    let col = col + start.len();
+    return Some(format!("{}{})", start, node(&last.1, indent, col)));
+}
+
+// Head on the first line, each of the other items on its own line and closing bracket on the last
+fn block(
+    square: bool,
+    items: &[(usize, Node)],
+    head: usize,
+    clauses: bool,
+    indent: usize,
+    col: usize,
+) -> String {
+    let (open, close) = brackets(square);
+    let inner = indent + 1;
+
+    let mut out = String::from(open);
+    let mut col = col + 1;
+    for (i, (_, item)) in items[..head].iter().enumerate() {
+        if i != 0 {
+            out += " ";
+            col += 1;
+        }
+        let item = node(item, inner, col);
+        col += item.len();
+        out += &item;
+    }
+
+    let mut first = true;
+    let mut items = items[head..].iter().peekable();
+    while let Some((lines, item)) = items.next() {
+        if let (Node::Comment(c), 0) = (item, lines) {
+            out += " ";
+            out += c.val.trim_end();
+            continue;
+        }
+
+        if *lines > 1 && !first {
+            out += "\n";
+        }
+        out += "\n";
+        out += &"\t".repeat(inner);
+        first = false;
+
+        let col = inner * TAB;
+        let item = node(item, inner, col);
+
+        // clause arguments are followed by the body on the same line
+        match (clauses, item.starts_with('['), items.peek()) {
+            (true, true, Some((_, body))) if !matches!(body, Node::Comment(_)) => {
+                let body = node(body, inner, col + item.len() + 1);
+                out += &format!("{} {}", item, body);
+                items.next();
+            }
+            _ => out += &item,
+        }
+    }
+
+    out += "\n";
+    out += &"\t".repeat(indent);
+    out += close;
+
+    return out;
+}
+
+fn head<'a>(items: &'a [(usize, Node)]) -> Option<&'a str> {
+    match items.first() {
+        Some((_, Node::Token(id))) => Some(id.val),
+        _ => None,
+    }
+}
+
+fn brackets(square: bool) -> (&'static str, &'static str) {
+    if square {
+        ("[", "]")
+    } else {
+        ("(", ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    #[test]
+    fn idempotent_on_examples() {
+        for dir in std::fs::read_dir("../examples").unwrap() {
+            let dir = dir.unwrap().path();
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for file in std::fs::read_dir(dir).unwrap() {
+                let path = file.unwrap().path();
+                if path.extension().unwrap_or_default() != "ot" {
+                    continue;
+                }
+
+                let path = path.to_str().unwrap();
+                let once = format(&std::fs::read_to_string(path).unwrap(), path);
+                assert_eq!(format(&once, path), once, "{} is not stable", path);
+            }
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let source = "mod main # entry\n\n\n(defn f [0] :zero   [n]\n  (do (print n) # print it\n n))\n# end\n(def x #'f/1)";
+        let expected = "mod main # entry\n\n(defn f\n\t[0] :zero\n\t[n] (do\n\t\t(print n) # print it\n\t\tn\n\t)\n)\n# end\n(def x #'f/1)\n";
+
+        assert_eq!(format(source, "test.ot"), expected);
+    }
+
+    #[test]
+    fn tests_have_blocks_on_own_lines() {
+        let source = "mod main\n(deftest one (assert-eq 1 1))\n(deftest two (def x 2) (assert-eq 4 (+ x x)))";
+        let expected = "mod main\n\n(deftest one (assert-eq 1 1))\n(deftest two\n\t(def x 2)\n\t(assert-eq 4 (+ x x))\n)\n";
+
+        assert_eq!(format(source, "test.ot"), expected);
+    }
+}
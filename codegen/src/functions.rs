@@ -28,11 +28,11 @@ impl Functions {
         self.main_function
     }
 
-    pub fn get_all(self) -> Vec<(u32, Vec<Bytecode>, u8, u8)> {
+    pub fn get_all(&self) -> Vec<(u32, Vec<Bytecode>, u8, u8)> {
         let mut vec = self
             .funcs
-            .into_iter()
-            .map(|((_, argc), (index, localc, b))| (index, b, argc, localc))
+            .iter()
+            .map(|((_, argc), (index, localc, b))| (*index, b.clone(), *argc, *localc))
             .collect::<Vec<(u32, Vec<Bytecode>, u8, u8)>>();
         vec.sort_by_key(|(index, _, _, _)| *index);
 
@@ -55,12 +55,20 @@ impl Functions {
         localc: u8,
     ) -> u32 {
         let name: String = name.into();
+
+        // function defined again keeps its index, so calls compiled before use the new code
+        let index = match self.funcs.get(&(name.clone(), argc)) {
+            Some((index, _, _)) => *index,
+            None => {
+                self.fc += 1;
+                self.fc - 1
+            }
+        };
+
         if name == "@main" {
-            self.main_function = Some(self.fc);
+            self.main_function = Some(index);
         }
-        self.funcs.insert((name, argc), (self.fc, localc, code));
-        let fc = self.fc;
-        self.fc += 1;
-        return fc;
+        self.funcs.insert((name, argc), (index, localc, code));
+        return index;
     }
 }
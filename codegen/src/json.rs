@@ -0,0 +1,294 @@
+use std::fmt::{self, Display, Write};
+
+/// JSON value, numbers are kept as `f64` like in JavaScript
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // keys in order of insertion
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// Value of the field, `Null` when it is missing or this is not an object
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value)
+                .unwrap_or(&Json::Null),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Json> {
+        let mut p = JsonParser {
+            s: s.as_bytes(),
+            i: 0,
+        };
+        let value = p.value()?;
+        p.whitespace();
+        if p.i != p.s.len() {
+            return None;
+        }
+        return Some(value);
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => {
+                f.write_char('"')?;
+                for ch in s.chars() {
+                    match ch {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+                        ch => f.write_char(ch)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.clone()), value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn whitespace(&mut self) {
+        while self.i < self.s.len() && self.s[self.i].is_ascii_whitespace() {
+            self.i += 1;
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Option<Json> {
+        if !self.s[self.i..].starts_with(literal.as_bytes()) {
+            return None;
+        }
+        self.i += literal.len();
+        return Some(value);
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = *self.s.get(self.i)?;
+        self.i += 1;
+        return Some(byte);
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match *self.s.get(self.i)? {
+            b'n' => self.literal("null", Json::Null),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.i += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.whitespace();
+                    if self.s.get(self.i) == Some(&b']') && items.is_empty() {
+                        self.i += 1;
+                        return Some(Json::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.whitespace();
+                    match self.next()? {
+                        b',' => (),
+                        b']' => return Some(Json::Array(items)),
+                        _ => return None,
+                    }
+                }
+            }
+            b'{' => {
+                self.i += 1;
+                let mut fields = Vec::new();
+                loop {
+                    self.whitespace();
+                    if self.s.get(self.i) == Some(&b'}') && fields.is_empty() {
+                        self.i += 1;
+                        return Some(Json::Object(fields));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    if self.next()? != b':' {
+                        return None;
+                    }
+                    fields.push((key, self.value()?));
+                    self.whitespace();
+                    match self.next()? {
+                        b',' => (),
+                        b'}' => return Some(Json::Object(fields)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                let start = self.i;
+                while self.i < self.s.len() && b"+-.eE0123456789".contains(&self.s[self.i]) {
+                    self.i += 1;
+                }
+                std::str::from_utf8(&self.s[start..self.i])
+                    .ok()?
+                    .parse()
+                    .ok()
+                    .map(Json::Number)
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.next()? != b'"' {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => return String::from_utf8(bytes).ok(),
+                b'\\' => {
+                    let ch = match self.next()? {
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let high = self.hex()?;
+                            // characters outside of the basic plane are surrogate pairs
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                if self.next()? != b'\\' || self.next()? != b'u' {
+                                    return None;
+                                }
+                                let low = self.hex()?;
+                                0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)?)
+                            } else {
+                                high
+                            };
+                            char::from_u32(code)?
+                        }
+                        byte => byte as char,
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    fn hex(&mut self) -> Option<u32> {
+        let digits = self.s.get(self.i..self.i + 4)?;
+        self.i += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    #[test]
+    fn round_trip() {
+        let source = r#"{"id":1,"params":{"text":"a \"b\"\né😀","list":[true,null,-1.5,[]]}}"#;
+        let json = Json::parse(source).unwrap();
+
+        assert_eq!(json.get("id").as_usize(), Some(1));
+        assert_eq!(
+            json.get("params").get("text").as_str(),
+            Some("a \"b\"\né😀")
+        );
+        assert_eq!(json.get("params").get("list").as_array().len(), 4);
+        assert_eq!(Json::parse(&json.to_string()), Some(json));
+    }
+}
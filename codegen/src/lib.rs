@@ -1,14 +1,29 @@
 mod atoms;
+pub mod bundle;
+pub mod c_backend;
+pub mod cache;
 pub mod cli;
 pub mod compiler;
+pub mod doc;
 mod error;
 pub mod fanalysis;
+pub mod fmt;
 mod functions;
+pub mod json;
+pub mod lint;
 pub mod loader;
+pub mod lsp;
+pub mod manifest;
+pub mod object;
+pub mod prelude;
+pub mod repl;
+pub mod resolve;
 pub mod serialize;
 mod signatures;
+pub mod testing;
 mod util;
 mod variables;
+pub mod watch;
 
 use ::error::Error;
 use fanalysis::{analyse, FunctionAnalysis};
@@ -16,6 +31,7 @@ pub use parser;
 
 use compiler::{CompilerCtx, Constant};
 use parser::{parse, FileCxt, Parser};
+use resolve::resolve;
 use serialize::serialize;
 
 pub fn compile_file(contents: &str, error_handle: impl Fn(&str)) -> Vec<u8> {
@@ -33,6 +49,11 @@ pub fn compile_file(contents: &str, error_handle: impl Fn(&str)) -> Vec<u8> {
     let mut fanalysis = FunctionAnalysis::new();
     analyse(&ast, &mut fanalysis);
 
+    if let Some(e) = resolve("main", &ast, &fanalysis).first() {
+        error_handle(&e.get_formated_with_file(contents));
+        return Vec::with_capacity(0);
+    }
+
     let mut ctx = CompilerCtx::new(fanalysis);
 
     for block in ast {
@@ -42,5 +63,5 @@ pub fn compile_file(contents: &str, error_handle: impl Fn(&str)) -> Vec<u8> {
     ctx.flush_func_with_locals("@main", 0);
     ctx.fill_labels();
 
-    return serialize(ctx);
+    return serialize(&ctx, true);
 }
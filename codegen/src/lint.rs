@@ -0,0 +1,541 @@
+use std::collections::{HashMap, HashSet};
+
+use error::Error;
+use parser::{
+    AritheticsOrCall, Block, Exp, FunctionDef, Identifier, Import, Test, ValOrExp, Value,
+    VariableDef,
+};
+
+use crate::{error::CompilerError, fanalysis::FunctionAnalysis};
+
+// Builtins that only compute their result
+const PURE_BUILTINS: [&str; 22] = [
+    "pair",
+    "left",
+    "right",
+    "new",
+    "get",
+    "len",
+    "strlen",
+    "concat",
+    "substring",
+    "char-at",
+    "char->str",
+    "str->int",
+    "int->str",
+    "to-string",
+    "split",
+    "join",
+    "index-of",
+    "upper",
+    "lower",
+    "compare",
+    "args",
+    "env",
+];
+const IMPURE_BUILTINS: [&str; 11] = [
+    "print",
+    "set",
+    "assert-eq",
+    "read-file",
+    "write-file",
+    "append-file",
+    "read-line",
+    "read-all-stdin",
+    "file-exists?",
+    "exit",
+    "throw",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lint {
+    UnusedVariables,
+    ShadowedNames,
+    UnreachableClauses,
+    UnusedResults,
+}
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::ShadowedNames => "shadowed_names",
+            Lint::UnreachableClauses => "unreachable_clauses",
+            Lint::UnusedResults => "unused_results",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        match name {
+            "unused_variables" => Some(Lint::UnusedVariables),
+            "shadowed_names" => Some(Lint::ShadowedNames),
+            "unreachable_clauses" => Some(Lint::UnreachableClauses),
+            "unused_results" => Some(Lint::UnusedResults),
+            _ => None,
+        }
+    }
+}
+
+/// Lints suppressed by `#[allow(lint)]` comments, they apply to their own and the following line.
+/// `#![allow(lint)]` applies to the whole file.
+#[derive(Debug, Default)]
+struct Allows {
+    file: Vec<Lint>,
+    lines: HashMap<usize, Vec<Lint>>,
+}
+
+impl Allows {
+    fn new(content: &str) -> Allows {
+        let mut allows = Allows::default();
+
+        for (index, line) in content.split("\n").enumerate() {
+            let (attr, whole_file) = if let Some(pos) = line.find("#![allow(") {
+                (&line[pos + 9..], true)
+            } else if let Some(pos) = line.find("#[allow(") {
+                (&line[pos + 8..], false)
+            } else {
+                continue;
+            };
+
+            let lints = attr[..attr.find(")").unwrap_or(attr.len())]
+                .split(",")
+                .filter_map(|name| Lint::from_name(name.trim()));
+
+            if whole_file {
+                allows.file.extend(lints);
+            } else {
+                let lints = lints.collect::<Vec<_>>();
+                // lines are numbered from 1
+                for line in [index + 1, index + 2] {
+                    allows
+                        .lines
+                        .entry(line)
+                        .or_default()
+                        .extend(lints.iter().copied());
+                }
+            }
+        }
+
+        return allows;
+    }
+
+    fn is_allowed(&self, lint: Lint, line: usize) -> bool {
+        self.file.contains(&lint)
+            || self
+                .lines
+                .get(&line)
+                .map(|lints| lints.contains(&lint))
+                .unwrap_or(false)
+    }
+}
+
+struct Binding<'a, 'b> {
+    name: &'b Identifier<'a>,
+    used: bool,
+}
+
+struct Linter<'a, 'b> {
+    fanalysis: &'b FunctionAnalysis,
+    allows: HashMap<&'b str, Allows>,
+
+    // functions without side effects
+    pure: HashSet<&'a str>,
+
+    scopes: Vec<Vec<Binding<'a, 'b>>>,
+
+    warnings: usize,
+}
+
+/// Reports warnings for every module and returns their count
+pub fn lint<'a>(
+    modules: &HashMap<String, Vec<Block<'a>>>,
+    sources: &[(String, String)],
+    fanalysis: &FunctionAnalysis,
+) -> usize {
+    let mut linter = Linter {
+        fanalysis,
+        allows: sources
+            .iter()
+            .map(|(content, path)| (path.as_str(), Allows::new(content)))
+            .collect(),
+        pure: HashSet::new(),
+        scopes: Vec::new(),
+        warnings: 0,
+    };
+
+    linter.find_pure_functions(modules);
+
+    let mut names = modules.keys().collect::<Vec<_>>();
+    names.sort();
+
+    for name in names {
+        let blocks = &modules[name];
+        linter.scopes.push(Vec::new());
+        if *name == "main" {
+            blocks.iter().for_each(|bl| linter.block(bl, true));
+        } else {
+            blocks
+                .iter()
+                .filter(|bl| matches!(bl, Block::FunctionDef(_) | Block::Test(_)))
+                .for_each(|bl| linter.block(bl, true));
+        }
+        linter.end_scope();
+    }
+
+    return linter.warnings;
+}
+
+impl<'a, 'b> Linter<'a, 'b> {
+    fn warn(&mut self, lint: Lint, msg: String, id: &Identifier<'a>) {
+        if self
+            .allows
+            .get(id.file)
+            .map(|allows| allows.is_allowed(lint, id.line))
+            .unwrap_or(false)
+        {
+            return;
+        }
+
+        self.warnings += 1;
+        CompilerError::new(
+            format!("{} [{}]", msg, lint.name()),
+            id.file,
+            id.line,
+            id.start,
+        )
+        .warn();
+    }
+
+    // Functions start as pure and are marked impure until nothing changes
+    fn find_pure_functions(&mut self, modules: &'b HashMap<String, Vec<Block<'a>>>) {
+        let functions = modules
+            .values()
+            .flatten()
+            .filter_map(|bl| match bl {
+                Block::FunctionDef(fd) => Some(fd),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        self.pure = functions.iter().map(|fd| fd.name.val).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for FunctionDef { name, types, .. } in &functions {
+                if self.pure.contains(name.val) && !types.iter().all(|(_, body)| self.is_pure(body))
+                {
+                    self.pure.remove(name.val);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn is_pure(&self, v_exp: &ValOrExp<'a>) -> bool {
+        match v_exp {
+            ValOrExp::Val(_) => true,
+            ValOrExp::Exp(exp) => self.is_pure_exp(exp),
+        }
+    }
+
+    fn is_pure_exp(&self, exp: &Exp<'a>) -> bool {
+        match exp {
+            Exp::Basic { op, args } => {
+                let op = match op {
+                    AritheticsOrCall::Arithmetics(_, _) => true,
+                    AritheticsOrCall::ExternCall(_) => false,
+                    AritheticsOrCall::Call(name) => {
+                        if PURE_BUILTINS.contains(&name.val) {
+                            true
+                        } else if IMPURE_BUILTINS.contains(&name.val) {
+                            false
+                        } else {
+                            // lambdas can do anything
+                            self.pure.contains(name.val)
+                        }
+                    }
+                };
+                op && args.iter().all(|arg| self.is_pure(arg))
+            }
+            Exp::If { cond, if_, else_ } => {
+                self.is_pure(cond) && self.is_pure(if_) && self.is_pure(else_)
+            }
+            Exp::Try { body, handler, .. } => self.is_pure(body) && self.is_pure(handler),
+            Exp::Do { blocks } => blocks.iter().all(|bl| match bl {
+                Block::VariableDef(VariableDef { v_or_exp, .. }) => self.is_pure(v_or_exp),
+                Block::Expression(exp) => self.is_pure_exp(exp),
+                Block::Import(_) => false,
+                Block::FunctionDef(_) | Block::Test(_) => true,
+            }),
+            Exp::Lambda { .. } => true,
+        }
+    }
+
+    fn bind(&mut self, name: &'b Identifier<'a>) {
+        if self
+            .scopes
+            .iter()
+            .flatten()
+            .any(|binding| binding.name.val == name.val)
+        {
+            self.warn(
+                Lint::ShadowedNames,
+                format!("`{}` shadows variable with the same name", name.val),
+                name,
+            );
+        } else if self.fanalysis.exists(name.val) {
+            self.warn(
+                Lint::ShadowedNames,
+                format!("`{}` shadows function with the same name", name.val),
+                name,
+            );
+        }
+
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push(Binding { name, used: false });
+    }
+
+    fn use_variable(&mut self, name: &str) {
+        if let Some(binding) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name.val == name)
+        {
+            binding.used = true;
+        }
+    }
+
+    fn end_scope(&mut self) {
+        for Binding { name, used } in self.scopes.pop().unwrap() {
+            if !used && !name.val.starts_with("_") {
+                self.warn(
+                    Lint::UnusedVariables,
+                    format!("unused variable `{}`", name.val),
+                    name,
+                );
+            }
+        }
+    }
+
+    fn block(&mut self, bl: &'b Block<'a>, discarded: bool) {
+        match bl {
+            Block::Import(Import { path, symbols: _ }) => self.val_or_expression(path),
+            Block::VariableDef(VariableDef { name, v_or_exp, .. }) => {
+                self.val_or_expression(v_or_exp);
+                self.bind(name);
+            }
+            Block::FunctionDef(FunctionDef { name, types, .. }) => {
+                // functions can't access variables outside of them
+                let saved = std::mem::take(&mut self.scopes);
+                self.clauses(name.val, name, types);
+                self.scopes = saved;
+            }
+            Block::Test(Test { name: _, blocks }) => {
+                // result of a test is not used
+                let saved = std::mem::take(&mut self.scopes);
+                self.scopes.push(Vec::new());
+                blocks.iter().for_each(|bl| self.block(bl, true));
+                self.end_scope();
+                self.scopes = saved;
+            }
+            Block::Expression(exp) => {
+                if discarded && self.is_pure_exp(exp) {
+                    if let Exp::Basic {
+                        op: AritheticsOrCall::Call(name),
+                        ..
+                    } = exp
+                    {
+                        self.warn(
+                            Lint::UnusedResults,
+                            format!(
+                                "result of `{}` is discarded and the call has no side effects",
+                                name.val
+                            ),
+                            name,
+                        );
+                    }
+                }
+                self.expression(exp);
+            }
+        }
+    }
+
+    fn clauses(
+        &mut self,
+        name: &str,
+        position: &'b Identifier<'a>,
+        types: &'b [(Vec<Value<'a>>, ValOrExp<'a>)],
+    ) {
+        let mut catch_all: Vec<usize> = Vec::new();
+
+        for (args, body) in types {
+            if catch_all.contains(&args.len()) {
+                let id = args.iter().find_map(identifier).unwrap_or(position);
+                self.warn(
+                    Lint::UnreachableClauses,
+                    format!(
+                        "clause of `{}/{}` can never match, an earlier clause matches all arguments",
+                        name,
+                        args.len()
+                    ),
+                    id,
+                );
+            } else if args.iter().all(|arg| matches!(arg, Value::Var(_))) {
+                catch_all.push(args.len());
+            }
+
+            self.scopes.push(Vec::new());
+            args.iter().for_each(|arg| match arg {
+                Value::Var(name) => self.bind(name),
+                _ => (),
+            });
+            self.val_or_expression(body);
+            self.end_scope();
+        }
+    }
+
+    fn val_or_expression(&mut self, v_exp: &'b ValOrExp<'a>) {
+        match v_exp {
+            ValOrExp::Val(Value::Var(name)) => self.use_variable(name.val),
+            ValOrExp::Val(_) => (),
+            ValOrExp::Exp(exp) => self.expression(exp),
+        }
+    }
+
+    fn expression(&mut self, exp: &'b Exp<'a>) {
+        match exp {
+            Exp::Basic { op, args } => {
+                if let AritheticsOrCall::Call(name) = op {
+                    self.use_variable(name.val);
+                }
+                args.iter().for_each(|arg| self.val_or_expression(arg));
+            }
+            Exp::If { cond, if_, else_ } => {
+                self.val_or_expression(cond);
+                self.val_or_expression(if_);
+                self.val_or_expression(else_);
+            }
+            Exp::Do { blocks } => {
+                self.scopes.push(Vec::new());
+                if let Some((last, blocks)) = blocks.split_last() {
+                    blocks.iter().for_each(|bl| self.block(bl, true));
+                    self.block(last, false);
+                }
+                self.end_scope();
+            }
+            Exp::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.val_or_expression(body);
+                self.scopes.push(Vec::new());
+                self.bind(error);
+                self.val_or_expression(handler);
+                self.end_scope();
+            }
+            Exp::Lambda { types } => {
+                let position = types
+                    .iter()
+                    .flat_map(|(args, _)| args.iter())
+                    .find_map(identifier);
+                match position {
+                    Some(position) => self.clauses("lambda", position, types),
+                    // only lambdas without named arguments, there is nothing to report
+                    None => types.iter().for_each(|(_, body)| {
+                        self.scopes.push(Vec::new());
+                        self.val_or_expression(body);
+                        self.end_scope();
+                    }),
+                }
+            }
+        }
+    }
+}
+
+fn identifier<'a, 'b>(value: &'b Value<'a>) -> Option<&'b Identifier<'a>> {
+    match value {
+        Value::String(id) | Value::Atom(id) | Value::Var(id) | Value::Function(id, _) => Some(id),
+        Value::Int(_) | Value::Null => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use parser::{parse, FileCxt, Parser};
+
+    use super::lint;
+    use crate::fanalysis::{analyse, FunctionAnalysis};
+
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+
+    // Number of warnings in the source of the main module, warnings show lines of the file
+    fn warnings(source: &str) -> usize {
+        let source = format!("mod main\n\n{}\n", source);
+        let file = std::env::temp_dir().join(format!(
+            "ot-lint-{}-{}.ot",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&file, &source).unwrap();
+        let path = file.to_string_lossy().into_owned();
+
+        let (_, blocks) = parse().parse(FileCxt::new(&source, &path)).unwrap();
+        let mut fanalysis = FunctionAnalysis::new();
+        analyse(&blocks, &mut fanalysis);
+
+        let modules = HashMap::from([(String::from("main"), blocks)]);
+        let warnings = lint(&modules, &[(source.clone(), path.clone())], &fanalysis);
+        std::fs::remove_file(&file).unwrap();
+        return warnings;
+    }
+
+    #[test]
+    fn unused_variables() {
+        assert_eq!(warnings("(def x 1)"), 1);
+        assert_eq!(warnings("(def _x 1)"), 0);
+        assert_eq!(warnings("(def x 1)\n(print x)"), 0);
+        assert_eq!(warnings("(defn f [n] 1)\n(print (f 1))"), 1);
+        assert_eq!(warnings("(print (try 1 (catch e 2)))"), 1);
+    }
+
+    #[test]
+    fn shadowed_names() {
+        assert_eq!(warnings("(defn f [n] n)\n(defn g [f] f)\n(print (g 1))"), 1);
+        assert_eq!(warnings("(def x 1)\n(print (do (def x 2) (print x)))"), 2);
+        assert_eq!(warnings("(defn f [n] n)\n(defn g [m] m)\n(print (g 1))"), 0);
+    }
+
+    #[test]
+    fn unreachable_clauses() {
+        assert_eq!(warnings("(defn f [n] n [1] 2)\n(print (f 1))"), 1);
+        assert_eq!(warnings("(defn f [1] 2 [n] n)\n(print (f 1))"), 0);
+        assert_eq!(warnings("(defn f [n] n [a b] a)\n(print (f 1))"), 1);
+    }
+
+    #[test]
+    fn unused_results() {
+        assert_eq!(warnings("(defn f [n] (+ n 1))\n(f 1)\n(print (f 2))"), 1);
+        assert_eq!(warnings("(defn f [n] (print n))\n(f 1)"), 0);
+        assert_eq!(warnings("(print (do (strlen \"a\") (+ 1 0)))"), 1);
+    }
+
+    #[test]
+    fn allowed_lints() {
+        assert_eq!(warnings("#[allow(unused_variables)]\n(def x 1)"), 0);
+        assert_eq!(warnings("#[allow(unused_variables)]\n\n(def x 1)"), 1);
+        assert_eq!(
+            warnings("#![allow(unused_variables, unused_results)]\n(def x 1)\n(strlen \"a\")"),
+            0
+        );
+        assert_eq!(warnings("#[allow(shadowed_names)]\n(def x 1)"), 1);
+    }
+}
@@ -3,68 +3,189 @@ use std::collections::HashMap;
 use error::Error;
 use parser::{parse, Block, FileCxt, Parser};
 
+use crate::cache::{hash, Cache};
 use crate::compiler;
-use crate::fanalysis::{analyse, FunctionAnalysis};
+use crate::fanalysis::{analyse, exports, FunctionAnalysis};
+use crate::lint::lint;
+use crate::manifest::{packages, Package};
+use crate::object::{link, Object};
+use crate::prelude;
+use crate::resolve::resolve;
 use crate::{serialize::serialize, CompilerCtx};
 
-pub fn load_files(root: &str, debug: bool) -> Vec<u8> {
-    let dir = std::fs::read_dir(root).expect("cant read directory");
+pub fn load_files(
+    root: &str,
+    debug: bool,
+    deny_warnings: bool,
+    strip: bool,
+    cache: Option<&Cache>,
+) -> Vec<u8> {
+    return compile(root, debug, deny_warnings, false, cache, |ctx| {
+        serialize(&ctx, !strip)
+    });
+}
 
-    let res = dir
-        .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path())
-        .filter(|path| path.is_file())
-        .filter(|path| path.extension().unwrap_or_default() == "ot")
-        .map(|path| {
-            (
-                std::fs::read_to_string(&path).unwrap(),
-                path.to_str().unwrap().to_owned(),
-            )
+/// Compiles all files in the root directory and in directories of its dependencies and passes
+/// the result to the backend
+///
+/// Modules are compiled separately and linked, with cache only changed modules and modules
+/// depending on their functions are compiled again. Tests are compiled only when `tests` is set,
+/// cached modules don't have them
+pub fn compile<T>(
+    root: &str,
+    debug: bool,
+    deny_warnings: bool,
+    tests: bool,
+    cache: Option<&Cache>,
+    backend: impl FnOnce(CompilerCtx) -> T,
+) -> T {
+    let packages = packages(root).unwrap_or_else(|e| fatal(&e));
+
+    // sources of each package sorted by path, package of every source is kept next to them
+    let (res, owners): (Vec<_>, Vec<_>) = packages
+        .iter()
+        .enumerate()
+        .flat_map(|(owner, package)| {
+            sources(&package.root)
+                .into_iter()
+                .map(move |source| (source, owner))
         })
-        .collect::<Vec<_>>();
+        .unzip();
 
-    let mut modules: HashMap<&str, Vec<Block>> = res
+    // all modules are linted when warnings are denied
+    let mut cached = res
         .iter()
-        .map(|(file, path)| {
-            parse()
-                .parse(FileCxt::new(file, path))
-                .map_err(|e| {
-                    e.display();
-                    unreachable!()
-                })
-                .unwrap()
+        .zip(&owners)
+        .map(|((file, path), &owner)| match cache {
+            Some(cache) if !deny_warnings => cache.load(path, source_hash(file, &packages[owner])),
+            _ => None,
         })
-        .map(|(ctx, blocks)| (ctx.module, blocks))
-        .collect();
+        .collect::<Vec<_>>();
+
+    let mut modules: HashMap<String, Vec<Block>> = HashMap::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    let mut parse_file = |index: usize, modules: &mut HashMap<_, _>| {
+        let (file, path) = &res[index];
+        let (ctx, blocks) = parse()
+            .parse(FileCxt::new(file, path))
+            .map_err(|e| {
+                e.display();
+                unreachable!()
+            })
+            .unwrap();
+        let name = packages[owners[index]]
+            .module_name(ctx.module)
+            .unwrap_or_else(|e| fatal(&format!("{} in `{}`", e, path)));
+        if indexes.insert(name.clone(), index).is_some() {
+            fatal(&format!("module `{}` is defined more than once", name));
+        }
+        modules.insert(name, blocks);
+    };
+
+    (0..res.len())
+        .filter(|&i| cached[i].is_none())
+        .for_each(|i| parse_file(i, &mut modules));
 
     let mut fanalysis = FunctionAnalysis::new();
     modules
+        .values()
+        .for_each(|blocks| analyse(blocks, &mut fanalysis));
+    cached
         .iter()
-        .for_each(|(_, blocks)| analyse(blocks, &mut fanalysis));
+        .flatten()
+        .flat_map(|object| object.exports.iter())
+        .for_each(|(name, argc)| fanalysis.add_function(name, *argc));
 
-    let mut ctx = CompilerCtx::new(fanalysis);
+    // all functions of the prelude can be called, only the used ones are compiled, variables
+    // with the same names as its functions are not linted as shadowing them
+    let project = fanalysis.clone();
+    let prelude = prelude::functions(&fanalysis);
+    analyse(&prelude, &mut fanalysis);
 
-    let main = modules.remove("main").expect("Main module is missing");
+    // functions used by cached module changed
+    for (i, object) in cached.iter_mut().enumerate() {
+        if matches!(object, Some(object) if !object.is_fresh(&fanalysis)) {
+            *object = None;
+            parse_file(i, &mut modules);
+        }
+    }
 
-    for block in main {
-        compiler::block(&mut ctx, block, false);
+    let mut names = modules.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    let errors = names
+        .iter()
+        .flat_map(|name| resolve(name, &modules[name], &fanalysis))
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        errors.iter().for_each(|e| e.report());
+        std::process::exit(1);
     }
-    ctx.flush_func_with_locals("@main", 0);
 
-    modules
-        .into_values()
-        .try_for_each(|blocks| {
-            blocks
-                .into_iter()
-                .map(|block| match &block {
-                    Block::FunctionDef(_) => Some(block),
-                    _ => None,
-                })
-                .try_for_each(|block| Some(compiler::block(&mut ctx, block?, false)))
-        })
-        .expect("Only main module can have top-level expressions");
+    let warnings = lint(&modules, &res, &project);
+    if deny_warnings && warnings != 0 {
+        eprintln!(
+            "\x1b[31m\x1b[1mCOMPILER ERROR:\x1b[0m aborting due to {} warning(s)",
+            warnings
+        );
+        std::process::exit(1);
+    }
+
+    for name in names {
+        let index = indexes[&name];
+        let (file, path) = &res[index];
+        let package = &packages[owners[index]];
+        let blocks = modules.remove(&name).unwrap();
+        let object = compile_module(
+            &name,
+            path,
+            source_hash(file, package),
+            blocks,
+            &fanalysis,
+            package.library_paths(),
+            tests,
+        );
+        if let Some(cache) = cache {
+            cache.store(&object);
+        }
+        cached[index] = Some(object);
+    }
 
-    ctx.fill_labels();
+    let mut objects = cached.into_iter().flatten().collect::<Vec<_>>();
+    let used = prelude::used(
+        prelude,
+        objects
+            .iter()
+            .flat_map(|object| object.uses.iter().map(|(name, _)| name.as_str())),
+        &fanalysis,
+    );
+    if !used.is_empty() {
+        objects.push(compile_module(
+            prelude::MODULE,
+            prelude::PATH,
+            0,
+            used,
+            &fanalysis,
+            Vec::new(),
+            false,
+        ));
+    }
+    objects.sort_by(|a, b| (a.module != "main", &a.module).cmp(&(b.module != "main", &b.module)));
+    if objects
+        .first()
+        .map(|object| object.module != "main")
+        .unwrap_or(true)
+    {
+        fatal(&format!(
+            "entry module `{}` is missing",
+            packages[0].manifest.entry
+        ));
+    }
+
+    if let Some(error) = duplicate_function(&objects) {
+        fatal(&error);
+    }
+
+    let ctx = link(&objects, fanalysis);
 
     if debug {
         for (name, argc, index, localc, bytecode) in ctx.get_functions() {
@@ -73,5 +194,177 @@ pub fn load_files(root: &str, debug: bool) -> Vec<u8> {
         }
     }
 
-    return serialize(ctx);
+    return backend(ctx);
+}
+
+fn compile_module<'a>(
+    name: &str,
+    path: &str,
+    hash: u64,
+    blocks: Vec<Block<'a>>,
+    fanalysis: &FunctionAnalysis,
+    library_paths: Vec<String>,
+    tests: bool,
+) -> Object {
+    let mut ctx = CompilerCtx::new(fanalysis.clone());
+    ctx.library_paths = library_paths;
+    let exports = exports(&blocks);
+
+    if name == "main" {
+        for block in blocks {
+            match block {
+                Block::Test(test) if tests => compiler::test(&mut ctx, name, test),
+                block => compiler::block(&mut ctx, block, false),
+            }
+        }
+        ctx.flush_func_with_locals("@main", 0);
+    } else {
+        blocks
+            .into_iter()
+            .try_for_each(|block| match block {
+                Block::FunctionDef(_) => {
+                    compiler::block(&mut ctx, block, false);
+                    Some(())
+                }
+                Block::Test(test) if tests => {
+                    compiler::test(&mut ctx, name, test);
+                    Some(())
+                }
+                Block::Test(_) => Some(()),
+                _ => None,
+            })
+            .unwrap_or_else(|| fatal("only main module can have top-level expressions"));
+    }
+
+    return Object::new(name, path, hash, exports, ctx);
+}
+
+// Functions are called by name and arity from every module of the package, so only one module of
+// the package can define each of them, packages have their own functions
+fn duplicate_function(objects: &[Object]) -> Option<String> {
+    let mut defined: HashMap<(Option<&str>, &(String, u8)), &str> = HashMap::new();
+    for object in objects {
+        for export in &object.exports {
+            if let Some(module) = defined.insert((object.namespace(), export), &object.module) {
+                return Some(format!(
+                    "function `{}/{}` is defined in modules `{}` and `{}`",
+                    export.0, export.1, module, object.module
+                ));
+            }
+        }
+    }
+    return None;
+}
+
+// Source files in the directory with their paths, sorted by path
+fn sources(dir: &str) -> Vec<(String, String)> {
+    let mut sources = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| fatal(&format!("can't read directory `{}`: {}", dir, e)))
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().unwrap_or_default() == "ot")
+        .map(|path| {
+            (
+                std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| fatal(&format!("can't read `{}`: {}", path.display(), e))),
+                path.to_str().unwrap().to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    sources.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+    return sources;
+}
+
+// Compiled module depends on the manifest of its package as well, it decides the name of the
+// module and where imported libraries are
+fn source_hash(source: &str, package: &Package) -> u64 {
+    hash(&format!(
+        "{}\0{:?}\0{}\0{}",
+        source,
+        package.namespace,
+        package.manifest.entry,
+        package.library_paths().join("\0")
+    ))
+}
+
+fn fatal(msg: &str) -> ! {
+    eprintln!("\x1b[31m\x1b[1mCOMPILER ERROR:\x1b[0m {}", msg);
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use vm::{deserialize, VM};
+
+    use super::compile;
+    use crate::serialize::serialize;
+
+    // Compiles the project in `app` with its dependency in `lib` and returns what it prints
+    fn run(name: &str, app: &[(&str, &str)], lib: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("ot-packages-{}-{}", name, std::process::id()));
+        for (package, files) in [("app", app), ("lib", lib)] {
+            std::fs::create_dir_all(dir.join(package)).unwrap();
+            for (file, source) in files {
+                std::fs::write(dir.join(package).join(file), source).unwrap();
+            }
+        }
+        std::fs::write(
+            dir.join("app").join("ot.toml"),
+            "[package]\nname = \"app\"\n\n[dependencies]\nlib = { path = \"../lib\" }\n",
+        )
+        .unwrap();
+        let root = dir.join("app");
+        let bytes = compile(root.to_str().unwrap(), false, false, false, None, |ctx| {
+            serialize(&ctx, true)
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (start, constants, functions, signatures, groups, debug) = deserialize(&bytes).unwrap();
+        let output = Rc::new(RefCell::new(String::new()));
+        let print = {
+            let output = output.clone();
+            move |s: &str| output.borrow_mut().push_str(s)
+        };
+        VM::new(
+            constants, functions, signatures, groups, debug, print, false, false,
+        )
+        .run(start, Vec::new())
+        .unwrap();
+
+        let output = output.borrow().clone();
+        return output;
+    }
+
+    #[test]
+    fn functions_colliding_across_packages() {
+        let output = run(
+            "colliding",
+            &[(
+                "main.ot",
+                "mod main\n\n(defn helper [n] (+ n 1))\n\n(print (helper 1))\n(print \" \")\n(print (scale 2))\n",
+            )],
+            &[
+                ("math.ot", "mod math\n\n(defn helper [n] (* n 100))\n"),
+                ("util.ot", "mod util\n\n(defn scale [n] (helper n))\n"),
+            ],
+        );
+        assert_eq!(output, "2 200");
+    }
+
+    #[test]
+    fn dependencies_call_functions_of_project() {
+        let output = run(
+            "project",
+            &[(
+                "main.ot",
+                "mod main\n\n(defn double [n] (* n 2))\n\n(print (apply 4))\n",
+            )],
+            &[("util.ot", "mod util\n\n(defn apply [n] (double n))\n")],
+        );
+        assert_eq!(output, "8");
+    }
 }
@@ -0,0 +1,849 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use error::Error;
+use parser::{
+    parse, AritheticsOrCall, Block, Exp, FileCxt, FunctionDef, Identifier, Import, Parser,
+    ParserError, Test, ValOrExp, Value, VariableDef,
+};
+
+use crate::error::CompilerError;
+use crate::fanalysis::{analyse, FunctionAnalysis, BUILTINS};
+use crate::json::Json;
+use crate::manifest::{packages, Manifest};
+use crate::prelude;
+use crate::resolve::resolve;
+use crate::util::source_value;
+
+// Kinds of LSP symbols and completion items
+const SYMBOL_MODULE: usize = 2;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+const COMPLETION_FUNCTION: usize = 3;
+
+/// Language server, answers messages of the client one by one
+///
+/// Project of a document is the directory it is in, like for `otc`. Open documents are used
+/// instead of the files on disk.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    /// Whether the client asked for shutdown before exit
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Handles request or notification, returns messages for the client
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = message.get("id");
+
+        let result = match method {
+            "initialize" => Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        // whole document is sent on every change
+                        ("textDocumentSync", 1.into()),
+                        ("definitionProvider", true.into()),
+                        ("hoverProvider", true.into()),
+                        ("completionProvider", Json::object(vec![])),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![
+                        ("name", "otls".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ]),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/didOpen" => {
+                let path = document_path(params);
+                let text = params.get("textDocument").get("text").as_str();
+                self.documents
+                    .insert(path, text.unwrap_or_default().to_owned());
+                return self.diagnostics();
+            }
+            "textDocument/didChange" => {
+                let path = document_path(params);
+                if let Some(text) = params
+                    .get("contentChanges")
+                    .as_array()
+                    .last()
+                    .and_then(|change| change.get("text").as_str())
+                {
+                    self.documents.insert(path, text.to_owned());
+                }
+                return self.diagnostics();
+            }
+            "textDocument/didClose" => {
+                let path = document_path(params);
+                self.documents.remove(&path);
+
+                let mut messages = self.diagnostics();
+                messages.push(publish(&path, Vec::new()));
+                return messages;
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.symbols(params),
+            _ if *id == Json::Null => return Vec::new(),
+            _ => {
+                return vec![Json::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("id", id.clone()),
+                    (
+                        "error",
+                        Json::object(vec![
+                            ("code", Json::Number(-32601.0)),
+                            ("message", format!("Unknown method `{}`", method).into()),
+                        ]),
+                    ),
+                ])]
+            }
+        };
+
+        return vec![Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            ("result", result),
+        ])];
+    }
+
+    // Sources of the project the file is in and of its dependencies, sorted by path like in `otc`
+    fn sources(&self, path: &str) -> Vec<(String, String)> {
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        let dirs = packages(&dir.to_string_lossy())
+            .map(|packages| packages.into_iter().map(|package| package.root).collect())
+            .unwrap_or_else(|_| vec![dir.to_string_lossy().into_owned()]);
+
+        let mut sources = dirs
+            .iter()
+            .flat_map(|dir| {
+                std::fs::read_dir(dir)
+                    .map(|entries| {
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .map(|entry| entry.path())
+                            .filter(|path| {
+                                path.is_file() && path.extension().unwrap_or_default() == "ot"
+                            })
+                            .filter_map(|path| path.to_str().map(String::from))
+                            .filter(|path| !self.documents.contains_key(path))
+                            .filter_map(|path| Some((std::fs::read_to_string(&path).ok()?, path)))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        sources.extend(
+            self.documents
+                .iter()
+                .filter(|(open, _)| {
+                    dirs.iter()
+                        .any(|dir| Path::new(open).parent() == Some(Path::new(dir)))
+                })
+                .map(|(path, text)| (text.clone(), path.clone())),
+        );
+        sources.sort_by(|(_, a), (_, b)| a.cmp(b));
+
+        return sources;
+    }
+
+    fn diagnostics(&self) -> Vec<Json> {
+        let mut paths = self.documents.keys().collect::<Vec<_>>();
+        paths.sort();
+
+        let mut messages = Vec::new();
+        for path in paths {
+            let sources = self.sources(path);
+            let project = Project::new(&sources);
+            let module = match project.module(path) {
+                Some(module) => module,
+                None => continue,
+            };
+
+            let blocks = match &module.blocks {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    messages.push(publish(path, vec![diagnostic(&sources, e, 0)]));
+                    continue;
+                }
+            };
+
+            // the entry module named in the manifest is compiled as `main`
+            let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+            let entry = Manifest::load(&dir.to_string_lossy())
+                .map(|manifest| manifest.entry)
+                .unwrap_or_else(|_| String::from("main"));
+            let name = if module.name == entry {
+                "main"
+            } else {
+                module.name
+            };
+
+            let mut errors = Vec::new();
+            if name != "main" {
+                errors.extend(
+                    blocks
+                        .iter()
+                        .filter(|block| !matches!(block, Block::FunctionDef(_) | Block::Test(_)))
+                        .map(|block| {
+                            // the error is shown on the first name in the block
+                            let mut walker = Walker {
+                                scopes: vec![Vec::new()],
+                                symbols: Vec::new(),
+                            };
+                            walker.block(block);
+                            let (line, col) = walker
+                                .symbols
+                                .first()
+                                .map(|(name, _)| (name.line, name.start))
+                                .unwrap_or((1, 1));
+                            CompilerError::new(
+                                "only main module can have top-level expressions",
+                                path,
+                                line,
+                                col,
+                            )
+                        }),
+                );
+            }
+            // functions of modules that can't be parsed are unknown, their calls are not errors
+            if project.modules.iter().all(|m| m.blocks.is_ok()) {
+                errors.extend(resolve(name, blocks, &project.fanalysis));
+            }
+
+            let diagnostics = errors.iter().map(|e| diagnostic(&sources, e, 1)).collect();
+            messages.push(publish(path, diagnostics));
+        }
+
+        return messages;
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let (path, line, col) = position(params);
+        let sources = self.sources(&path);
+        let project = Project::new(&sources);
+
+        let locations = match project.symbol_at(&path, &sources, line, col) {
+            Some((_, Symbol::Variable(def))) => vec![location(&sources, def)],
+            Some((_, Symbol::Function(name))) => project
+                .functions(name)
+                .into_iter()
+                .filter(|(_, def)| def.name.file != prelude::PATH)
+                .map(|(_, def)| location(&sources, &def.name))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        return Json::Array(locations);
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let (path, line, col) = position(params);
+        let sources = self.sources(&path);
+        let project = Project::new(&sources);
+
+        let (name, contents) = match project.symbol_at(&path, &sources, line, col) {
+            Some((name, Symbol::Variable(def))) => (
+                name,
+                format!("variable `{}`, defined on line {}", def.val, def.line),
+            ),
+            Some((name, Symbol::Builtin(builtin, arity))) => {
+                (name, format!("builtin function `{}/{}`", builtin, arity))
+            }
+            Some((name, Symbol::Function(function))) => {
+                let defs = project.functions(function);
+                let clauses = defs
+                    .iter()
+                    .flat_map(|(_, def)| def.types.iter())
+                    .map(|(args, _)| {
+                        let args = args.iter().map(source_value).collect::<Vec<_>>();
+                        format!("\t[{}] ...", args.join(" "))
+                    })
+                    .collect::<Vec<_>>();
+                let arities = project
+                    .fanalysis
+                    .arities(function)
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|arity| format!("`{}/{}`", function, arity))
+                    .collect::<Vec<_>>();
+                let modules = defs
+                    .iter()
+                    .map(|(module, _)| format!("`{}`", module))
+                    .collect::<Vec<_>>();
+                let docs = defs
+                    .iter()
+                    .filter_map(|(_, def)| def.doc.as_deref())
+                    .map(|doc| format!("\n\n{}", doc))
+                    .collect::<String>();
+
+                let contents = format!(
+                    "```ot\n(defn {}\n{}\n)\n```\n{} defined in {}{}",
+                    function,
+                    clauses.join("\n"),
+                    arities.join(", "),
+                    modules.join(", "),
+                    docs
+                );
+                (name, contents)
+            }
+            None => return Json::Null,
+        };
+
+        return Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![
+                    ("kind", "markdown".into()),
+                    ("value", contents.into()),
+                ]),
+            ),
+            ("range", range(&sources, name)),
+        ]);
+    }
+
+    fn completion(&self, params: &Json) -> Json {
+        let (path, _, _) = position(params);
+        let sources = self.sources(&path);
+        let project = Project::new(&sources);
+
+        let mut functions = project.fanalysis.names().collect::<Vec<_>>();
+        functions.sort();
+
+        let functions = functions.into_iter().map(|name| {
+            let arities = project
+                .fanalysis
+                .arities(name)
+                .unwrap_or(&[])
+                .iter()
+                .map(|arity| format!("{}/{}", name, arity))
+                .collect::<Vec<_>>();
+            completion(name, arities.join(", "))
+        });
+        let builtins = BUILTINS
+            .iter()
+            .map(|(name, arity)| completion(name, format!("builtin {}/{}", name, arity)));
+
+        return Json::Array(functions.chain(builtins).collect());
+    }
+
+    fn symbols(&self, params: &Json) -> Json {
+        let path = document_path(params);
+        let sources = self.sources(&path);
+        let project = Project::new(&sources);
+
+        let (module, blocks) = match project.module(&path) {
+            Some(Module {
+                name,
+                blocks: Ok(blocks),
+                ..
+            }) => (name, blocks),
+            _ => return Json::Array(Vec::new()),
+        };
+
+        let children = blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::FunctionDef(FunctionDef { name, types, .. }) => {
+                    let mut arities = types.iter().map(|(args, _)| args.len()).collect::<Vec<_>>();
+                    arities.dedup();
+                    let detail = arities
+                        .iter()
+                        .map(|arity| format!("{}/{}", name.val, arity))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    Some(symbol(&sources, name, SYMBOL_FUNCTION, detail))
+                }
+                Block::VariableDef(VariableDef { name, .. }) => {
+                    Some(symbol(&sources, name, SYMBOL_VARIABLE, String::new()))
+                }
+                Block::Test(Test { name, .. }) => Some(symbol(
+                    &sources,
+                    name,
+                    SYMBOL_FUNCTION,
+                    String::from("test"),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        // module spans the whole file
+        let text = sources
+            .iter()
+            .find(|(_, p)| *p == path)
+            .map(|(text, _)| text.as_str())
+            .unwrap_or_default();
+        let end = Json::object(vec![
+            ("line", text.lines().count().into()),
+            ("character", 0.into()),
+        ]);
+        let start = Json::object(vec![("line", 0.into()), ("character", 0.into())]);
+        let whole = Json::object(vec![("start", start), ("end", end)]);
+
+        return Json::Array(vec![Json::object(vec![
+            ("name", (*module).into()),
+            ("kind", SYMBOL_MODULE.into()),
+            ("range", whole.clone()),
+            ("selectionRange", whole),
+            ("children", Json::Array(children)),
+        ])]);
+    }
+}
+
+struct Module<'a> {
+    name: &'a str,
+    path: &'a str,
+    blocks: Result<Vec<Block<'a>>, ParserError<'a>>,
+}
+
+/// Parsed modules of one directory with their functions
+struct Project<'a> {
+    modules: Vec<Module<'a>>,
+    fanalysis: FunctionAnalysis,
+}
+
+/// What a name in the source refers to
+enum Symbol<'p, 'a> {
+    Function(&'a str),
+    Variable(&'p Identifier<'a>),
+    Builtin(&'static str, u8),
+}
+
+impl<'a> Project<'a> {
+    fn new(sources: &'a [(String, String)]) -> Project<'a> {
+        let mut fanalysis = FunctionAnalysis::new();
+        let mut modules = sources
+            .iter()
+            .map(
+                |(text, path)| match parse().parse(FileCxt::new(text, path)) {
+                    Ok((ctx, blocks)) => {
+                        analyse(&blocks, &mut fanalysis);
+                        Module {
+                            name: ctx.module,
+                            path,
+                            blocks: Ok(blocks),
+                        }
+                    }
+                    Err(e) => Module {
+                        name: "",
+                        path,
+                        blocks: Err(e),
+                    },
+                },
+            )
+            .collect::<Vec<_>>();
+
+        // prelude is shown on hover and in completion, it can't be opened
+        let prelude = prelude::functions(&fanalysis);
+        analyse(&prelude, &mut fanalysis);
+        modules.push(Module {
+            name: "prelude",
+            path: prelude::PATH,
+            blocks: Ok(prelude),
+        });
+
+        Project { modules, fanalysis }
+    }
+
+    fn module(&self, path: &str) -> Option<&Module<'a>> {
+        self.modules.iter().find(|module| module.path == path)
+    }
+
+    /// Definitions of the function in all modules with names of the modules
+    fn functions(&self, name: &str) -> Vec<(&'a str, &FunctionDef<'a>)> {
+        self.modules
+            .iter()
+            .filter_map(|module| Some((module.name, module.blocks.as_ref().ok()?)))
+            .flat_map(|(module, blocks)| blocks.iter().map(move |block| (module, block)))
+            .filter_map(|(module, block)| match block {
+                Block::FunctionDef(def) if def.name.val == name => Some((module, def)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Name under the cursor in the file and what it refers to
+    fn symbol_at(
+        &self,
+        path: &str,
+        sources: &[(String, String)],
+        line: usize,
+        col: usize,
+    ) -> Option<(&Identifier<'a>, Symbol<'_, 'a>)> {
+        let blocks = self.module(path)?.blocks.as_ref().ok()?;
+        let text = sources.iter().find(|(_, p)| p == path)?.0.as_str();
+
+        let mut walker = Walker {
+            scopes: vec![Vec::new()],
+            symbols: Vec::new(),
+        };
+        blocks.iter().for_each(|block| walker.block(block));
+
+        return walker.symbols.into_iter().find(|(name, _)| {
+            let (start, end) = (
+                utf16_col(text, name.line, name.start),
+                utf16_col(text, name.line, name.end),
+            );
+            name.line == line + 1 && start <= col && col <= end
+        });
+    }
+}
+
+// Collects names in the source with symbols they refer to, scopes follow the resolver
+struct Walker<'p, 'a> {
+    scopes: Vec<Vec<&'p Identifier<'a>>>,
+    symbols: Vec<(&'p Identifier<'a>, Symbol<'p, 'a>)>,
+}
+
+impl<'p, 'a> Walker<'p, 'a> {
+    fn variable(&self, name: &str) -> Option<&'p Identifier<'a>> {
+        self.scopes
+            .iter()
+            .flatten()
+            .rev()
+            .find(|var| var.val == name)
+            .copied()
+    }
+
+    fn block(&mut self, block: &'p Block<'a>) {
+        match block {
+            Block::Import(Import { path, .. }) => self.val_or_expression(path),
+            Block::VariableDef(VariableDef { name, v_or_exp, .. }) => {
+                self.val_or_expression(v_or_exp);
+                self.scopes.last_mut().unwrap().push(name);
+                self.symbols.push((name, Symbol::Variable(name)));
+            }
+            Block::FunctionDef(FunctionDef { name, types, .. }) => {
+                self.symbols.push((name, Symbol::Function(name.val)));
+
+                let saved = std::mem::take(&mut self.scopes);
+                self.clauses(types);
+                self.scopes = saved;
+            }
+            Block::Expression(exp) => self.expression(exp),
+            Block::Test(Test { name: _, blocks }) => {
+                let saved = std::mem::replace(&mut self.scopes, vec![Vec::new()]);
+                blocks.iter().for_each(|block| self.block(block));
+                self.scopes = saved;
+            }
+        }
+    }
+
+    fn clauses(&mut self, types: &'p [(Vec<Value<'a>>, ValOrExp<'a>)]) {
+        for (args, body) in types {
+            let mut scope = Vec::new();
+            for arg in args {
+                match arg {
+                    Value::Var(name) => {
+                        scope.push(name);
+                        self.symbols.push((name, Symbol::Variable(name)));
+                    }
+                    value => self.value(value),
+                }
+            }
+
+            self.scopes.push(scope);
+            self.val_or_expression(body);
+            self.scopes.pop();
+        }
+    }
+
+    fn value(&mut self, value: &'p Value<'a>) {
+        match value {
+            Value::Var(name) => match self.variable(name.val) {
+                Some(def) => self.symbols.push((name, Symbol::Variable(def))),
+                None => self.symbols.push((name, Symbol::Function(name.val))),
+            },
+            Value::Function(name, _) => self.symbols.push((name, Symbol::Function(name.val))),
+            _ => (),
+        }
+    }
+
+    fn val_or_expression(&mut self, v_exp: &'p ValOrExp<'a>) {
+        match v_exp {
+            ValOrExp::Val(v) => self.value(v),
+            ValOrExp::Exp(exp) => self.expression(exp),
+        }
+    }
+
+    fn expression(&mut self, exp: &'p Exp<'a>) {
+        match exp {
+            Exp::Basic { op, args } => {
+                if let AritheticsOrCall::Call(name) = op {
+                    let builtin = BUILTINS.iter().find(|(builtin, _)| *builtin == name.val);
+                    let symbol = match (builtin, self.variable(name.val)) {
+                        (Some((builtin, arity)), _) => Symbol::Builtin(builtin, *arity),
+                        (None, Some(def)) => Symbol::Variable(def),
+                        (None, None) => Symbol::Function(name.val),
+                    };
+                    self.symbols.push((name, symbol));
+                }
+                args.iter().for_each(|arg| self.val_or_expression(arg));
+            }
+            Exp::If { cond, if_, else_ } => {
+                self.val_or_expression(cond);
+                self.val_or_expression(if_);
+                self.val_or_expression(else_);
+            }
+            Exp::Do { blocks } => {
+                self.scopes.push(Vec::new());
+                blocks.iter().for_each(|block| self.block(block));
+                self.scopes.pop();
+            }
+            Exp::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.val_or_expression(body);
+                self.symbols.push((error, Symbol::Variable(error)));
+                self.scopes.push(vec![error]);
+                self.val_or_expression(handler);
+                self.scopes.pop();
+            }
+            Exp::Lambda { types } => self.clauses(types),
+        }
+    }
+}
+
+/// Path of the file from `file://` URI
+pub fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match (byte, tail.get(..2)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    return String::from_utf8_lossy(&bytes).into_owned();
+}
+
+pub fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri += &format!("%{:02X}", byte),
+        }
+    }
+    return uri;
+}
+
+fn document_path(params: &Json) -> String {
+    uri_to_path(
+        params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .unwrap_or_default(),
+    )
+}
+
+// Path, line and column of the cursor
+fn position(params: &Json) -> (String, usize, usize) {
+    let position = params.get("position");
+    (
+        document_path(params),
+        position.get("line").as_usize().unwrap_or_default(),
+        position.get("character").as_usize().unwrap_or_default(),
+    )
+}
+
+// LSP counts columns in UTF-16 code units from 0, the parser counts characters from 1
+fn utf16_col(text: &str, line: usize, col: usize) -> usize {
+    text.split('\n')
+        .nth(line.saturating_sub(1))
+        .unwrap_or_default()
+        .chars()
+        .take(col.saturating_sub(1))
+        .map(char::len_utf16)
+        .sum()
+}
+
+fn text<'s>(sources: &'s [(String, String)], file: &str) -> &'s str {
+    sources
+        .iter()
+        .find(|(_, path)| path == file)
+        .map(|(text, _)| text.as_str())
+        .unwrap_or_default()
+}
+
+fn point(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("line", line.saturating_sub(1).into()),
+        ("character", character.into()),
+    ])
+}
+
+fn range(sources: &[(String, String)], name: &Identifier) -> Json {
+    let text = text(sources, name.file);
+    Json::object(vec![
+        (
+            "start",
+            point(name.line, utf16_col(text, name.line, name.start)),
+        ),
+        (
+            "end",
+            point(name.line, utf16_col(text, name.line, name.end)),
+        ),
+    ])
+}
+
+fn location(sources: &[(String, String)], name: &Identifier) -> Json {
+    Json::object(vec![
+        ("uri", path_to_uri(name.file).into()),
+        ("range", range(sources, name)),
+    ])
+}
+
+// Errors of the parser count columns from 0, errors of the compiler from 1
+fn diagnostic(sources: &[(String, String)], e: &impl Error, base: usize) -> Json {
+    let (msg, file, line, col) = e.get();
+    let col = utf16_col(text(sources, file), line, col + 1 - base);
+
+    Json::object(vec![
+        (
+            "range",
+            Json::object(vec![
+                ("start", point(line, col)),
+                ("end", point(line, col + 1)),
+            ]),
+        ),
+        ("severity", 1.into()),
+        ("source", "otls".into()),
+        ("message", msg.into()),
+    ])
+}
+
+fn publish(path: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", path_to_uri(path).into()),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn completion(name: &str, detail: String) -> Json {
+    Json::object(vec![
+        ("label", name.into()),
+        ("kind", COMPLETION_FUNCTION.into()),
+        ("detail", detail.into()),
+    ])
+}
+
+fn symbol(sources: &[(String, String)], name: &Identifier, kind: usize, detail: String) -> Json {
+    let range = range(sources, name);
+    Json::object(vec![
+        ("name", name.val.into()),
+        ("kind", kind.into()),
+        ("detail", detail.into()),
+        ("range", range.clone()),
+        ("selectionRange", range),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path_to_uri, Server};
+    use crate::json::Json;
+
+    fn request(server: &mut Server, method: &str, params: Json) -> Json {
+        let message = Json::object(vec![
+            ("id", 1.into()),
+            ("method", method.into()),
+            ("params", params),
+        ]);
+        server.handle(&message).remove(0).get("result").clone()
+    }
+
+    fn at(uri: &str, line: usize, character: usize) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", uri.into())])),
+            (
+                "position",
+                Json::object(vec![("line", line.into()), ("character", character.into())]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn navigation_and_diagnostics() {
+        let path = "/nonexistent/otls/main.ot";
+        let uri = path_to_uri(path);
+        let text = "mod main\n(defn double [n] (+ n n))\n(def x (double 2))\n(print (doubel x))";
+
+        let mut server = Server::new();
+        let open = Json::object(vec![(
+            "textDocument",
+            Json::object(vec![("uri", uri.as_str().into()), ("text", text.into())]),
+        )]);
+        let messages = server.handle(&Json::object(vec![
+            ("method", "textDocument/didOpen".into()),
+            ("params", open),
+        ]));
+        let diagnostics = messages[0].get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("range").get("start"), &{
+            Json::object(vec![("line", 3.into()), ("character", 8.into())])
+        });
+
+        // call of `double` leads to its definition
+        let definition = request(&mut server, "textDocument/definition", at(&uri, 2, 9));
+        let start = definition.as_array()[0].get("range").get("start").clone();
+        assert_eq!(start.get("line").as_usize(), Some(1));
+        assert_eq!(start.get("character").as_usize(), Some(6));
+
+        // variable `x` leads to its `def`
+        let definition = request(&mut server, "textDocument/definition", at(&uri, 3, 15));
+        let start = definition.as_array()[0].get("range").get("start").clone();
+        assert_eq!(start.get("line").as_usize(), Some(2));
+        assert_eq!(start.get("character").as_usize(), Some(5));
+
+        let hover = request(&mut server, "textDocument/hover", at(&uri, 2, 9));
+        let contents = hover.get("contents").get("value").as_str().unwrap();
+        assert!(contents.contains("[n] ...") && contents.contains("`double/1`"));
+    }
+}
@@ -1,12 +1,170 @@
-use codegen::cli::CLI;
+use std::io::Write;
+
+use codegen::bundle::bundle;
+use codegen::c_backend::{emit_c, RUNTIME_HEADER};
+use codegen::cache::Cache;
+use codegen::cli::{Command, CLI, HELP};
+use codegen::doc::doc;
+use codegen::fmt::format;
 use codegen::loader;
+use codegen::repl::repl;
+use codegen::testing::run_tests;
+use codegen::watch::watch;
 
-use std::io::Write;
+use vm::{deserialize, ErrorKind, RuntimeError, VM};
 
 pub fn main() {
     let cli = CLI::new();
-    let serialized = loader::load_files(&cli.root, cli.debug);
 
-    let mut file = std::fs::File::create("./.out").unwrap();
-    file.write_all(&serialized).unwrap();
+    match cli.command {
+        Command::Help => println!("{}", HELP),
+        Command::Version => println!("otc {}", env!("CARGO_PKG_VERSION")),
+        Command::Check => {
+            loader::compile(&cli.root, cli.debug, cli.deny_warnings, false, None, |_| ())
+        }
+        Command::Run => run(&cli),
+        Command::Fmt => fmt(&cli),
+        Command::Repl => repl(),
+        Command::Test => {
+            if !run_tests(&cli.root, cli.debug) {
+                std::process::exit(1);
+            }
+        }
+        Command::Doc => doc(&cli.root, &cli.out, cli.format),
+        Command::Build if cli.watch => watch(&cli.root),
+        Command::Build => build(&cli),
+    }
+}
+
+fn build(cli: &CLI) {
+    let cache = cache(cli);
+    let cache = cache.as_ref();
+
+    if let Some(out) = &cli.emit_c {
+        let source = loader::compile(
+            &cli.root,
+            cli.debug,
+            cli.deny_warnings,
+            false,
+            cache,
+            |ctx| emit_c(ctx, !cli.strip),
+        );
+        return write_c(out, &source);
+    }
+
+    let serialized = loader::load_files(&cli.root, cli.debug, cli.deny_warnings, cli.strip, cache);
+
+    if let Some(out) = &cli.bundle {
+        return bundle(&serialized, out, cli.runtime.as_deref(), cli.jit);
+    }
+
+    write(&cli.out, serialized.as_slice());
+}
+
+fn run(cli: &CLI) {
+    // the program is compiled in memory, nothing is written to the project directory
+    let serialized = loader::load_files(&cli.root, cli.debug, cli.deny_warnings, cli.strip, None);
+    let (start, constants, functions, signatures, groups, debug) =
+        deserialize(&serialized).unwrap();
+
+    let mut vm = VM::new(
+        constants,
+        functions,
+        signatures,
+        groups,
+        debug,
+        |s| print!("{}", s),
+        cli.jit,
+        false,
+    );
+    vm.program_args(cli.args.clone());
+    let result = vm.run(start, vec![]);
+
+    let _ = std::io::stdout().flush();
+    match result {
+        Err(RuntimeError {
+            kind: ErrorKind::Exit(code),
+            ..
+        }) => std::process::exit(code),
+        // the status is set only by `exit`, the result of the program isn't used
+        Ok(_) => (),
+        Err(error) => {
+            eprintln!("\x1b[31m\x1b[1mVM ERROR:\x1b[0m {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn fmt(cli: &CLI) {
+    let root = std::path::Path::new(&cli.root);
+    let mut paths = if root.is_file() {
+        vec![root.to_path_buf()]
+    } else {
+        std::fs::read_dir(root)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .filter(|path| path.extension().unwrap_or_default() == "ot")
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "\x1b[31m\x1b[1mERROR:\x1b[0m can't read `{}`: {}",
+                    cli.root, e
+                );
+                std::process::exit(1);
+            })
+    };
+    paths.sort();
+
+    let mut unformatted = 0;
+    for path in paths {
+        let name = path.to_string_lossy();
+        let source = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            eprintln!("\x1b[31m\x1b[1mERROR:\x1b[0m can't read `{}`: {}", name, e);
+            std::process::exit(1);
+        });
+
+        let formatted = format(&source, &name);
+        if formatted == source {
+            continue;
+        }
+
+        unformatted += 1;
+        if cli.check {
+            println!("`{}` is not formatted", name);
+        } else {
+            write(&name, formatted.as_bytes());
+            println!("formatted `{}`", name);
+        }
+    }
+
+    if cli.check && unformatted != 0 {
+        std::process::exit(1);
+    }
+}
+
+fn cache(cli: &CLI) -> Option<Cache> {
+    if cli.no_cache {
+        return None;
+    }
+    return Some(Cache::new(
+        std::path::Path::new(&cli.root).join(".ot-cache"),
+    ));
+}
+
+// Runtime header is written next to the generated source
+fn write_c(out: &str, source: &str) {
+    let header = std::path::Path::new(out).with_file_name("ot.h");
+
+    write(out, source.as_bytes());
+    write(&header.to_string_lossy(), RUNTIME_HEADER.as_bytes());
+}
+
+fn write(path: &str, content: &[u8]) {
+    if let Err(e) = std::fs::write(path, content) {
+        eprintln!("\x1b[31m\x1b[1mERROR:\x1b[0m can't write `{}`: {}", path, e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,338 @@
+use std::path::Path;
+
+pub const MANIFEST: &str = "ot.toml";
+
+/// Contents of `ot.toml`, paths are relative to the directory of the manifest
+///
+/// ```toml
+/// [package]
+/// name = "app"
+/// entry = "main"
+/// library-paths = ["./lib"]
+///
+/// [dependencies]
+/// strings = { path = "../strings" }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub entry: String,
+    // where libraries imported by FFI are looked up
+    pub library_paths: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub path: String,
+}
+
+/// Package of the build, modules of dependencies are prefixed with their name
+#[derive(Debug)]
+pub struct Package {
+    pub namespace: Option<String>,
+    pub root: String,
+    pub manifest: Manifest,
+}
+
+impl Package {
+    /// Name of the module in the build, the entry module of the project is compiled as `main`
+    pub fn module_name(&self, module: &str) -> Result<String, String> {
+        match &self.namespace {
+            Some(namespace) => Ok(format!("{}::{}", namespace, module)),
+            None if module == self.manifest.entry => Ok(String::from("main")),
+            None if module == "main" => Err(format!(
+                "module `main` can't be used when the entry module is `{}`",
+                self.manifest.entry
+            )),
+            None => Ok(module.to_owned()),
+        }
+    }
+
+    /// Paths of libraries imported by FFI relative to the working directory
+    pub fn library_paths(&self) -> Vec<String> {
+        self.manifest
+            .library_paths
+            .iter()
+            .map(|path| join(&self.root, path))
+            .collect()
+    }
+}
+
+impl Manifest {
+    /// Manifest of the package in root, package without `ot.toml` is named after its directory
+    pub fn load(root: &str) -> Result<Manifest, String> {
+        let path = Path::new(root).join(MANIFEST);
+        if !path.is_file() {
+            let name = Path::new(root)
+                .canonicalize()
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| String::from("main"));
+            return Ok(Manifest {
+                name,
+                entry: String::from("main"),
+                library_paths: Vec::new(),
+                dependencies: Vec::new(),
+            });
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("can't read `{}`: {}", path.display(), e))?;
+        return Manifest::parse(&source).map_err(|e| format!("{}:{}", path.display(), e));
+    }
+
+    /// Parses the subset of TOML used by manifests, every key with its value is on one line
+    pub fn parse(source: &str) -> Result<Manifest, String> {
+        let mut name = None;
+        let mut entry = None;
+        let mut library_paths = None;
+        let mut dependencies = Vec::new();
+
+        let mut table = "";
+        for (line, text) in source.lines().enumerate() {
+            let error = |msg: String| format!("{}: {}", line + 1, msg);
+
+            let text = strip_comment(text).trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(header) = text.strip_prefix('[') {
+                table = header
+                    .strip_suffix(']')
+                    .map(|header| header.trim())
+                    .filter(|header| ["package", "dependencies"].contains(header))
+                    .ok_or_else(|| error(format!("unknown table `{}`", text)))?;
+                continue;
+            }
+
+            let (key, value) = text
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected `key = value`, found `{}`", text)))?;
+            let (key, value) = (key.trim(), value.trim());
+            let value = self::value(value).map_err(error)?;
+
+            match (table, key, value) {
+                ("package", "name", Value::String(s)) => name = Some(s),
+                ("package", "entry", Value::String(s)) => entry = Some(s),
+                ("package", "library-paths", Value::Array(paths)) => library_paths = Some(paths),
+                ("package", "name" | "entry", _) => {
+                    return Err(error(format!("`{}` expects a string", key)))
+                }
+                ("package", "library-paths", _) => {
+                    return Err(error(format!("`{}` expects an array of strings", key)))
+                }
+                ("dependencies", _, Value::Table(fields)) => {
+                    let mut path = None;
+                    for (field, value) in fields {
+                        match field.as_str() {
+                            "path" => path = Some(value),
+                            _ => {
+                                return Err(error(format!(
+                                    "unknown field `{}` of dependency `{}`",
+                                    field, key
+                                )))
+                            }
+                        }
+                    }
+                    let path =
+                        path.ok_or_else(|| error(format!("dependency `{}` has no path", key)))?;
+                    if dependencies.iter().any(|d: &Dependency| d.name == key) {
+                        return Err(error(format!("dependency `{}` is already defined", key)));
+                    }
+                    dependencies.push(Dependency {
+                        name: key.to_owned(),
+                        path,
+                    });
+                }
+                ("dependencies", _, _) => {
+                    return Err(error(format!(
+                        "dependency `{}` expects `{{ path = \"...\" }}`",
+                        key
+                    )))
+                }
+                ("", _, _) => return Err(error(format!("`{}` is outside of a table", key))),
+                (table, _, _) => {
+                    return Err(error(format!("unknown key `{}` in [{}]", key, table)))
+                }
+            }
+        }
+
+        return Ok(Manifest {
+            name: name.ok_or("1: package has no name")?,
+            entry: entry.unwrap_or_else(|| String::from("main")),
+            library_paths: library_paths.unwrap_or_default(),
+            dependencies,
+        });
+    }
+}
+
+/// Root package followed by its dependencies and their dependencies, every package is in the
+/// build only once
+pub fn packages(root: &str) -> Result<Vec<Package>, String> {
+    let mut packages = vec![Package {
+        namespace: None,
+        root: root.to_owned(),
+        manifest: Manifest::load(root)?,
+    }];
+
+    let mut next = 0;
+    while next < packages.len() {
+        let dependencies = packages[next]
+            .manifest
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                (
+                    dependency.name.clone(),
+                    join(&packages[next].root, &dependency.path),
+                )
+            })
+            .collect::<Vec<_>>();
+        next += 1;
+
+        for (name, root) in dependencies {
+            let same = |package: &&Package| package.namespace.as_ref() == Some(&name);
+            match packages.iter().find(same) {
+                Some(package) if same_dir(&package.root, &root) => continue,
+                Some(package) => {
+                    return Err(format!(
+                        "dependency `{}` is required from both `{}` and `{}`",
+                        name, package.root, root
+                    ))
+                }
+                None => (),
+            }
+
+            if !Path::new(&root).is_dir() {
+                return Err(format!("dependency `{}` not found in `{}`", name, root));
+            }
+            let manifest = Manifest::load(&root)?;
+            packages.push(Package {
+                namespace: Some(name),
+                root,
+                manifest,
+            });
+        }
+    }
+
+    return Ok(packages);
+}
+
+enum Value {
+    String(String),
+    Array(Vec<String>),
+    Table(Vec<(String, String)>),
+}
+
+fn value(s: &str) -> Result<Value, String> {
+    if let Some(items) = s.strip_prefix('[') {
+        let items = items
+            .strip_suffix(']')
+            .ok_or_else(|| format!("unclosed array `{}`", s))?;
+        return items
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(string)
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+
+    if let Some(fields) = s.strip_prefix('{') {
+        let fields = fields
+            .strip_suffix('}')
+            .ok_or_else(|| format!("unclosed table `{}`", s))?;
+        return fields
+            .split(',')
+            .filter(|field| !field.trim().is_empty())
+            .map(|field| {
+                let (key, value) = field
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected `key = value`, found `{}`", field.trim()))?;
+                Ok((key.trim().to_owned(), string(value.trim())?))
+            })
+            .collect::<Result<_, _>>()
+            .map(Value::Table);
+    }
+
+    return string(s).map(Value::String);
+}
+
+fn string(s: &str) -> Result<String, String> {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .filter(|s| !s.contains('"'))
+        .map(|s| s.replace("\\\\", "\\"))
+        .ok_or_else(|| format!("expected a string, found `{}`", s))
+}
+
+// `#` inside of strings is not a comment
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    return line;
+}
+
+fn join(root: &str, path: &str) -> String {
+    Path::new(root).join(path).to_string_lossy().into_owned()
+}
+
+fn same_dir(a: &str, b: &str) -> bool {
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dependency, Manifest};
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = Manifest::parse(
+            "# project\n[package]\nname = \"app\"\nentry = \"start\" # entry module\nlibrary-paths = [\"./lib\", \"/usr/lib\"]\n\n[dependencies]\nstrings = { path = \"../strings\" }\n",
+        );
+
+        assert_eq!(
+            manifest,
+            Ok(Manifest {
+                name: String::from("app"),
+                entry: String::from("start"),
+                library_paths: vec![String::from("./lib"), String::from("/usr/lib")],
+                dependencies: vec![Dependency {
+                    name: String::from("strings"),
+                    path: String::from("../strings"),
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn manifest_errors() {
+        assert_eq!(
+            Manifest::parse("[package]\nentry = \"main\"\n"),
+            Err(String::from("1: package has no name"))
+        );
+        assert_eq!(
+            Manifest::parse("[package]\nname = \"app\"\nversion = \"1\"\n"),
+            Err(String::from("3: unknown key `version` in [package]"))
+        );
+        assert_eq!(
+            Manifest::parse(
+                "[package]\nname = \"app\"\n[dependencies]\nstrings = \"../strings\"\n"
+            ),
+            Err(String::from(
+                "4: dependency `strings` expects `{ path = \"...\" }`"
+            ))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use parser::{Identifier, SymbolType};
+use vm_bytecode::Bytecode;
+
+use crate::compiler::{test_name, CompilerCtx, Constant, Label, Position};
+use crate::fanalysis::FunctionAnalysis;
+
+/// Module compiled on its own, indexes in its bytecode are local to it until it is linked
+#[derive(Debug)]
+pub struct Object {
+    pub module: String,
+    pub path: String,
+    pub hash: u64,
+
+    // functions defined in the module
+    pub exports: Vec<(String, u8)>,
+    // arities of names looked up while compiling, empty when it is not a function
+    pub uses: Vec<(String, Vec<u8>)>,
+
+    pub functions: Vec<ObjectFunction>,
+    pub constants: Vec<Constant>,
+    pub atoms: Vec<String>,
+    pub signatures: Vec<(u32, String, Vec<SymbolType>, SymbolType)>,
+    pub groups: Vec<Vec<u32>>,
+    pub labels: Vec<ObjectLabel>,
+}
+
+#[derive(Debug)]
+pub struct ObjectFunction {
+    pub name: String,
+    pub argc: u8,
+    pub localc: u8,
+    pub code: Vec<Bytecode>,
+    // index of instruction, line and column
+    pub positions: Vec<(u32, u32, u32)>,
+}
+
+/// Call of function defined in another module or later in the same one
+#[derive(Debug)]
+pub struct ObjectLabel {
+    pub function: u32,
+    pub index: u32,
+    pub name: String,
+    pub arity: u8,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Object {
+    /// Takes compiled module out of the context, its labels are left unresolved
+    pub fn new(
+        module: &str,
+        path: &str,
+        hash: u64,
+        exports: Vec<(String, u8)>,
+        mut ctx: CompilerCtx,
+    ) -> Object {
+        let uses = ctx
+            .fanalysis
+            .take_queried()
+            .into_iter()
+            .map(|name| {
+                let arities = ctx.fanalysis.arities(&name).unwrap_or(&[]).to_vec();
+                (name, arities)
+            })
+            .collect();
+
+        let labels = std::mem::take(&mut ctx.labels)
+            .into_iter()
+            .map(|((fname, fargc), label)| ObjectLabel {
+                function: ctx.functions.get(fname, fargc).unwrap(),
+                index: label.index as u32,
+                name: label.name.val.to_owned(),
+                arity: label.arity,
+                line: label.name.line as u32,
+                col: label.name.start as u32,
+            })
+            .collect();
+
+        let atoms = ctx.atoms.names().into_iter().map(String::from).collect();
+
+        let mut debug = std::mem::take(&mut ctx.debug);
+        debug.sort_by_key(|(index, _, _)| *index);
+        let functions = ctx
+            .functions
+            .get_all()
+            .into_iter()
+            .zip(debug)
+            .map(
+                |((_, code, argc, localc), (_, name, positions))| ObjectFunction {
+                    name,
+                    argc,
+                    localc,
+                    code,
+                    positions: positions
+                        .into_iter()
+                        .map(|(index, pos)| (index, pos.line as u32, pos.col as u32))
+                        .collect(),
+                },
+            )
+            .collect();
+
+        let (signatures, groups) = ctx.signatures.get_signatures_and_groups();
+
+        Object {
+            module: module.to_owned(),
+            path: path.to_owned(),
+            hash,
+            exports,
+            uses,
+            functions,
+            constants: ctx.constants,
+            atoms,
+            signatures: signatures
+                .into_iter()
+                .map(|(name, index, args, ret)| (index, name.to_owned(), args, ret))
+                .collect(),
+            groups,
+            labels,
+        }
+    }
+
+    /// Whether the functions it depends on still have the same arities
+    pub fn is_fresh(&self, fanalysis: &FunctionAnalysis) -> bool {
+        self.uses
+            .iter()
+            .all(|(name, arities)| fanalysis.arities(name).unwrap_or(&[]) == arities.as_slice())
+    }
+
+    /// Namespace of the dependency the module is from, modules of the project don't have it
+    pub fn namespace(&self) -> Option<&str> {
+        self.module.split_once("::").map(|(namespace, _)| namespace)
+    }
+}
+
+/// Links objects into one program, main module has to be the first one
+pub fn link<'a>(objects: &'a [Object], fanalysis: FunctionAnalysis) -> CompilerCtx<'a> {
+    let mut ctx = CompilerCtx::new(fanalysis);
+    objects
+        .iter()
+        .filter_map(|object| object.namespace())
+        .for_each(|namespace| {
+            if !ctx.namespaces.contains(&namespace) {
+                ctx.namespaces.push(namespace);
+            }
+        });
+
+    objects
+        .iter()
+        .for_each(|object| link_object(&mut ctx, object));
+    ctx.fill_labels();
+
+    return ctx;
+}
+
+fn link_object<'a>(ctx: &mut CompilerCtx<'a>, object: &'a Object) {
+    let atoms = object
+        .atoms
+        .iter()
+        .map(|atom| ctx.atoms.translate(atom))
+        .collect::<Vec<_>>();
+
+    let constants = object
+        .constants
+        .iter()
+        .map(|constant| {
+            let existing = match constant {
+                Constant::Int(_) => ctx.constants.iter().position(|c| c == constant),
+                Constant::String(_) => None,
+            };
+            existing.unwrap_or_else(|| {
+                ctx.constants.push(constant.clone());
+                ctx.constants.len() - 1
+            }) as u8
+        })
+        .collect::<Vec<_>>();
+
+    let mut signatures = HashMap::new();
+    let groups = object
+        .groups
+        .iter()
+        .map(|group| {
+            let index = ctx.signatures.new_group();
+            for local in group {
+                let (_, name, args, ret) = object
+                    .signatures
+                    .iter()
+                    .find(|(index, _, _, _)| index == local)
+                    .unwrap();
+                signatures.insert(*local, ctx.signatures.define(name, args.clone(), *ret));
+            }
+            index
+        })
+        .collect::<Vec<_>>();
+
+    // Wrappers of named functions are shared, lambdas are renumbered to keep names unique,
+    // functions of dependencies and their wrappers are prefixed by the namespace
+    let namespace = object.namespace();
+    let mut functions = Vec::with_capacity(object.functions.len());
+    let mut names = Vec::with_capacity(object.functions.len());
+    for f in &object.functions {
+        let is_lambda = f
+            .name
+            .strip_prefix("@lambda")
+            .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false);
+        let name = match namespace {
+            _ if is_lambda => format!("@lambda{}", ctx.lambdac_increase()),
+            Some(namespace) if !f.name.starts_with('@') => format!("{}::{}", namespace, f.name),
+            Some(namespace) if f.name.contains('/') && test_name(&f.name).is_none() => {
+                format!("@{}::{}", namespace, &f.name[1..])
+            }
+            _ => f.name.clone(),
+        };
+
+        match ctx.functions.get(&name, f.argc) {
+            Some(index) if name.starts_with('@') && name.contains('/') => {
+                functions.push(index);
+                names.push(None);
+            }
+            _ => {
+                functions.push(ctx.functions.define(&name, Vec::new(), f.argc, f.localc));
+                names.push(Some(name));
+            }
+        }
+    }
+
+    for (local, (f, name)) in object.functions.iter().zip(names).enumerate() {
+        let name = match name {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let code = f
+            .code
+            .iter()
+            .map(|&bc| match bc {
+                Bytecode::Call(i) => Bytecode::Call(functions[i as usize]),
+                Bytecode::CallRet(i) => Bytecode::CallRet(functions[i as usize]),
+                Bytecode::NewLambda(i, ctxc, arityc) => {
+                    Bytecode::NewLambda(functions[i as usize], ctxc, arityc)
+                }
+                Bytecode::ConstantGet(i) => Bytecode::ConstantGet(constants[i as usize]),
+                Bytecode::Atom(i) => Bytecode::Atom(atoms[i as usize]),
+                Bytecode::Import(i) => Bytecode::Import(groups[i as usize]),
+                Bytecode::CallDynamic(i) => Bytecode::CallDynamic(signatures[&i]),
+                bc => bc,
+            })
+            .collect();
+        *ctx.functions.get_code(name.as_str(), f.argc).unwrap() = code;
+
+        let positions = f
+            .positions
+            .iter()
+            .map(|&(index, line, col)| {
+                let position = Position {
+                    file: &object.path,
+                    line: line as usize,
+                    col: col as usize,
+                };
+                (index, position)
+            })
+            .collect();
+        ctx.debug.push((functions[local], name.clone(), positions));
+
+        ctx.labels.extend(
+            object
+                .labels
+                .iter()
+                .filter(|label| label.function as usize == local)
+                .map(|label| {
+                    let id = Identifier {
+                        val: &label.name,
+                        line: label.line as usize,
+                        start: label.col as usize,
+                        end: label.col as usize + label.name.len(),
+                        file: &object.path,
+                    };
+                    let label = Label {
+                        name: id,
+                        arity: label.arity,
+                        index: label.index as usize,
+                        namespace,
+                    };
+                    ((name.clone(), f.argc), label)
+                }),
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeSet;
+
+use error::Error;
+use parser::{parse, Block, FileCxt, Parser};
+
+use crate::fanalysis::FunctionAnalysis;
+use crate::resolve::resolve;
+
+/// Standard library available in every program, it is written in OT and embedded in the compiler
+pub const SOURCE: &str = include_str!("../std/prelude.ot");
+pub const PATH: &str = "@std/prelude.ot";
+
+// modules of projects can't have this name
+pub const MODULE: &str = "@prelude";
+
+/// Functions of the prelude, except those the project defines itself
+pub fn functions(project: &FunctionAnalysis) -> Vec<Block<'static>> {
+    let defined = project.names().collect::<BTreeSet<_>>();
+
+    let (_, blocks) = parse()
+        .parse(FileCxt::new(SOURCE, PATH))
+        .map_err(|e| {
+            e.display();
+            unreachable!()
+        })
+        .unwrap();
+
+    return blocks
+        .into_iter()
+        .filter(|block| matches!(block, Block::FunctionDef(def) if !defined.contains(def.name.val)))
+        .collect();
+}
+
+/// Functions with the given names and functions they call, the rest is not compiled
+pub fn used<'a>(
+    functions: Vec<Block<'static>>,
+    names: impl Iterator<Item = &'a str>,
+    fanalysis: &FunctionAnalysis,
+) -> Vec<Block<'static>> {
+    let name = |block: &Block<'static>| match block {
+        Block::FunctionDef(def) => def.name.val,
+        _ => unreachable!(),
+    };
+
+    let mut used = names
+        .filter(|used| functions.iter().any(|block| name(block) == *used))
+        .map(String::from)
+        .collect::<BTreeSet<_>>();
+
+    // names looked up while resolving a function are the functions it calls
+    let fanalysis = fanalysis.clone();
+    let mut queue = used.iter().cloned().collect::<Vec<_>>();
+    while let Some(next) = queue.pop() {
+        let block = functions.iter().find(|block| name(block) == next).unwrap();
+        resolve(MODULE, std::slice::from_ref(block), &fanalysis);
+
+        for called in fanalysis.take_queried() {
+            if functions.iter().any(|block| name(block) == called) && used.insert(called.clone()) {
+                queue.push(called);
+            }
+        }
+    }
+
+    return functions
+        .into_iter()
+        .filter(|block| used.contains(name(block)))
+        .collect();
+}
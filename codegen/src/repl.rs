@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use error::Error;
+use parser::{parse, value, Block, FileCxt, Parser, ValOrExp, VariableDef, __};
+use vm::{deserialize, Value, VM};
+use vm_bytecode::{asm::format_instruction, Bytecode};
+
+use crate::compiler::{self, CompilerCtx};
+use crate::fanalysis::{analyse, FunctionAnalysis};
+use crate::prelude;
+use crate::resolve::{resolve_value, resolve_with_variables};
+use crate::serialize::serialize;
+
+const HELP: &str = "\
+Enter expressions, `def`s and `defn`s, unfinished forms continue on the next line.
+
+    :load <file>    compile and run the file
+    :dis <name>     print bytecode of the function, `name/arity` picks one arity
+    :help           print this help
+    :quit           exit, same as end of input";
+
+// Parsed input, single value is accepted to show variables
+enum Input {
+    Blocks(Vec<Block<'static>>),
+    Value(parser::Value<'static>),
+}
+
+// Whether the output of the program ends with a new line, results are printed on their own line
+static LINE_START: AtomicBool = AtomicBool::new(true);
+
+/// Compiler and VM kept between inputs, so functions and variables stay defined
+pub struct Repl {
+    ctx: CompilerCtx<'static>,
+    vm: VM<fn(&str)>,
+
+    // top-level variables with their values, in order of their locals
+    variables: Vec<&'static str>,
+    locals: Vec<Value>,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        let print: fn(&str) = |s| {
+            print!("{}", s);
+            if !s.is_empty() {
+                LINE_START.store(s.ends_with('\n'), Ordering::Relaxed);
+            }
+        };
+
+        let mut repl = Repl {
+            ctx: CompilerCtx::new(FunctionAnalysis::new()),
+            vm: VM::new(
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                None,
+                print,
+                false,
+                false,
+            ),
+            variables: Vec::new(),
+            locals: Vec::new(),
+        };
+
+        // the whole prelude is compiled, functions defined later replace its functions
+        let prelude = prelude::functions(&FunctionAnalysis::new());
+        repl.run(Input::Blocks(prelude), prelude::SOURCE)
+            .expect("prelude is compiled");
+        return repl;
+    }
+
+    /// Compiles the input and runs it, returns value of the last block when it is an expression
+    pub fn eval(&mut self, input: &str) -> Result<Option<Value>, String> {
+        // compiled code refers to the source for the whole session
+        let source: &'static str = Box::leak(format!("mod main\n{}", input).into());
+
+        let input = &source["mod main\n".len()..];
+
+        let ctx = FileCxt::new(input, "<repl>");
+        if let Ok((rest, value)) = __(value()).parse(ctx) {
+            if rest.s.trim().is_empty() {
+                return self.run(Input::Value(value), input);
+            }
+        }
+
+        // header is on line 0, so lines are numbered from the first line of the input
+        let ctx = FileCxt {
+            line: 0,
+            ..FileCxt::new(source, "<repl>")
+        };
+        return match parse().parse(ctx) {
+            Ok((_, blocks)) => self.run(Input::Blocks(blocks), input),
+            Err(e) => Err(e.get_formated_with_file(input)),
+        };
+    }
+
+    /// Compiles all blocks of the file and runs them like an input
+    pub fn load(&mut self, path: &str) -> Result<Option<Value>, String> {
+        let source =
+            std::fs::read_to_string(path).map_err(|e| format!("can't read `{}`: {}", path, e))?;
+        let source: &'static str = Box::leak(source.into_boxed_str());
+        let path: &'static str = Box::leak(path.to_owned().into_boxed_str());
+
+        return match parse().parse(FileCxt::new(source, path)) {
+            Ok((_, blocks)) => self.run(Input::Blocks(blocks), source),
+            Err(e) => Err(e.get_formated_with_file(source)),
+        };
+    }
+
+    // Input is compiled into `@main`, which gets values of the variables as arguments and returns
+    // its result paired with values of all its locals, so the next input can continue with them
+    fn run(&mut self, input: Input, content: &str) -> Result<Option<Value>, String> {
+        let mut fanalysis = self.ctx.fanalysis.clone();
+        let errors = match &input {
+            Input::Blocks(blocks) => {
+                analyse(blocks, &mut fanalysis);
+                resolve_with_variables("main", blocks, &fanalysis, self.variables.clone())
+            }
+            Input::Value(value) => resolve_value(value, &fanalysis, self.variables.clone()),
+        };
+        if !errors.is_empty() {
+            return Err(errors
+                .iter()
+                .map(|e| e.get_formated_with_file(content))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        self.ctx.fanalysis = fanalysis;
+
+        let is_expression = match input {
+            // the value stays on the stack as the result
+            Input::Value(value) => {
+                compiler::val_or_expression(&mut self.ctx, ValOrExp::Val(value), false);
+                true
+            }
+            Input::Blocks(blocks) => {
+                let is_expression = matches!(blocks.last(), Some(Block::Expression(_)));
+                for block in blocks {
+                    match block {
+                        // variable defined again gets the new value
+                        Block::VariableDef(VariableDef { name, v_or_exp, .. })
+                            if self.variables.contains(&name.val) =>
+                        {
+                            let local = self.ctx.vars.get_local(name.val).unwrap();
+                            compiler::val_or_expression(&mut self.ctx, v_or_exp, false);
+                            self.ctx.current_function.push(Bytecode::LocalSet(local));
+                        }
+                        Block::VariableDef(def) => {
+                            self.variables.push(def.name.val);
+                            compiler::block(&mut self.ctx, Block::VariableDef(def), false);
+                        }
+                        block => compiler::block(&mut self.ctx, block, false),
+                    }
+                }
+
+                // value of the last expression is kept instead of removed
+                if is_expression {
+                    self.ctx.current_function.pop();
+                }
+                is_expression
+            }
+        };
+
+        let code = &mut self.ctx.current_function;
+        if !is_expression {
+            code.push(Bytecode::ConstantNil);
+        }
+        let localc = self.ctx.vars.highest();
+        for local in 0..localc {
+            code.push(Bytecode::LocalGet(local));
+            code.push(Bytecode::NewPair);
+        }
+        code.push(Bytecode::Ret);
+
+        self.ctx
+            .flush_func("@main", self.locals.len() as u8, localc);
+        self.ctx.fill_labels();
+
+        let (start, constants, functions, signatures, groups, debug) =
+            deserialize(&serialize(&self.ctx, true)).unwrap();
+        self.vm
+            .load(constants, functions, signatures, groups, debug);
+
+        let mut result = match self.vm.run(start, self.locals.clone()) {
+            Ok(result) => result,
+            // variables keep their previous values and the ones defined by the input are nil
+            Err(error) => {
+                self.locals.resize(localc as usize, Value::Nil);
+                return Err(error.to_string());
+            }
+        };
+        let mut locals = vec![Value::Nil; localc as usize];
+        for local in locals.iter_mut().rev() {
+            if let Value::Pair(pair) = result {
+                *local = pair.1.clone();
+                result = pair.0.clone();
+            }
+        }
+        self.locals = locals;
+
+        return Ok(if is_expression { Some(result) } else { None });
+    }
+
+    /// Value as it is written in the source
+    pub fn show(&self, value: &Value) -> String {
+        match value {
+            Value::Int(i) => i.to_string(),
+            Value::Nil => String::from("null"),
+            Value::Atom(atom) => match self.ctx.atoms.names().get(*atom as usize) {
+                Some(name) => format!(":{}", name),
+                None => format!("atom({})", atom),
+            },
+            Value::String(s) => format!("\"{}\"", s.to_string_lossy()),
+            Value::Pair(pair) => format!("(pair {} {})", self.show(&pair.0), self.show(&pair.1)),
+            Value::Array(array) => {
+                let items = array
+                    .borrow()
+                    .iter()
+                    .map(|item| self.show(item))
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(" "))
+            }
+            Value::Lambda(_) => String::from("#lambda"),
+        }
+    }
+
+    /// Bytecode of every arity of the function, `name/arity` selects only one of them
+    pub fn disassemble(&self, function: &str) -> Option<String> {
+        let (name, arity) = match function.rsplit_once('/') {
+            Some((name, arity)) => (name, Some(arity.parse::<u8>().ok()?)),
+            None => (function, None),
+        };
+
+        let names = self
+            .ctx
+            .get_functions()
+            .map(|(name, _, index, _, _)| (*index, name.as_str()))
+            .collect::<HashMap<_, _>>();
+        let label = |index| names.get(&index).copied().unwrap_or("?").to_owned();
+
+        let mut functions = self
+            .ctx
+            .get_functions()
+            .filter(|(f, argc, _, _, _)| *f == name && arity.is_none_or(|a| a == **argc))
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|(_, argc, _, _, _)| **argc);
+        if functions.is_empty() {
+            return None;
+        }
+
+        let mut out = String::new();
+        for (name, argc, _, localc, code) in functions {
+            out += &format!("{}/{}, {} locals\n", name, argc, localc - argc);
+            for (pc, bc) in code.iter().enumerate() {
+                out += &format!("  {:<4}{}\n", pc, format_instruction(*bc, label));
+            }
+        }
+        return Some(out);
+    }
+}
+
+/// Reads inputs from stdin until its end
+pub fn repl() {
+    let mut repl = Repl::new();
+    println!(
+        "OT {} REPL, `:help` for commands",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "ot> " } else { "... " });
+        let _ = std::io::stdout().flush();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        input += &line;
+        input += "\n";
+        if !is_complete(&input) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut input);
+        let command = source.trim();
+        let result = match command.split_once(' ').unwrap_or((command, "")) {
+            ("", _) => continue,
+            (":quit" | ":q", _) => break,
+            (":help", _) => Ok(Some(HELP.to_owned())),
+            (":load", path) => repl
+                .load(path.trim())
+                .map(|value| value.map(|value| repl.show(&value))),
+            (":dis", function) => repl
+                .disassemble(function.trim())
+                .map(|code| Some(code.trim_end().to_owned()))
+                .ok_or_else(|| format!("function `{}` is not defined", function.trim())),
+            (command, _) if command.starts_with(':') => {
+                Err(format!("unknown command `{}`, see `:help`", command))
+            }
+            _ => repl
+                .eval(source.trim_end())
+                .map(|value| value.map(|value| repl.show(&value))),
+        };
+
+        if !LINE_START.swap(true, Ordering::Relaxed) {
+            println!();
+        }
+        match result {
+            Ok(Some(out)) => println!("{}", out),
+            Ok(None) => (),
+            Err(e) => println!("\x1b[31m\x1b[1mERROR:\x1b[0m {}", e),
+        }
+    }
+    println!();
+}
+
+// Whether all brackets of the input are closed, strings and comments are skipped
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            // the string isn't closed
+            '"' if !chars.any(|ch| ch == '"') => return false,
+            '\'' => {
+                chars.find(|&ch| ch == '\'');
+            }
+            // function reference `#'f/1`
+            '#' if chars.peek() == Some(&'\'') => {
+                chars.next();
+            }
+            '#' => {
+                chars.find(|&ch| ch == '\n');
+            }
+            _ => (),
+        }
+    }
+    return depth <= 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_complete, Repl};
+    use vm::Value;
+
+    #[test]
+    fn keeps_definitions() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.eval("(def x 20)"), Ok(None));
+        assert_eq!(repl.eval("(defn twice [n] (* 2 n))"), Ok(None));
+        assert_eq!(repl.eval("(twice x)"), Ok(Some(Value::Int(40))));
+
+        assert_eq!(repl.eval("(def x (+ x 1))"), Ok(None));
+        assert_eq!(repl.eval("x"), Ok(Some(Value::Int(21))));
+
+        // callers use the function defined again
+        assert_eq!(repl.eval("(defn four [] (twice 2))"), Ok(None));
+        assert_eq!(repl.eval("(defn twice [n] (+ n n n))"), Ok(None));
+        assert_eq!(repl.eval("(four)"), Ok(Some(Value::Int(6))));
+
+        assert!(repl.eval("(missing x)").is_err());
+        assert_eq!(repl.eval("x"), Ok(Some(Value::Int(21))));
+    }
+
+    #[test]
+    fn prelude_functions() {
+        let mut repl = Repl::new();
+
+        assert_eq!(
+            repl.eval("(fold (lambda [a b] (+ a b)) 0 (map abs (range -3 0)))"),
+            Ok(Some(Value::Int(6)))
+        );
+
+        // variables captured by lambdas take precedence over functions
+        assert_eq!(repl.eval("(def length 2)"), Ok(None));
+        assert_eq!(repl.eval("(def f (lambda [] length))"), Ok(None));
+        assert_eq!(repl.eval("(f)"), Ok(Some(Value::Int(2))));
+    }
+
+    #[test]
+    fn survives_exit() {
+        let mut repl = Repl::new();
+
+        // the REPL keeps running, `exit` only ends the evaluated input
+        let error = repl.eval("(exit 3)").unwrap_err();
+        assert!(error.starts_with("exited with status 3\n    at @main"));
+        assert_eq!(repl.eval("(+ 1 2)"), Ok(Some(Value::Int(3))));
+    }
+
+    #[test]
+    fn survives_runtime_errors() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.eval("(def x 1)"), Ok(None));
+        let error = repl.eval("(def y (/ x 0))").unwrap_err();
+        assert!(error.starts_with("Division by zero\n    at @main"));
+
+        assert_eq!(repl.eval("x"), Ok(Some(Value::Int(1))));
+        assert_eq!(repl.eval("y"), Ok(Some(Value::Nil)));
+        assert_eq!(repl.eval("(+ x 1)"), Ok(Some(Value::Int(2))));
+    }
+
+    #[test]
+    fn multiline_input() {
+        assert!(!is_complete("(defn f [n]\n"));
+        assert!(!is_complete("(print \"(\"\n"));
+        assert!(is_complete("(print \")\") # (\n"));
+        assert!(is_complete("(f #'g/1 '(')\n"));
+    }
+}
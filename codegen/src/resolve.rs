@@ -0,0 +1,316 @@
+use parser::{
+    AritheticsOrCall, Block, Exp, FunctionDef, Identifier, Import, Test, ValOrExp, Value,
+    VariableDef,
+};
+
+use crate::{
+    error::CompilerError,
+    fanalysis::{FunctionAnalysis, BUILTINS},
+    util::suggest,
+};
+
+struct Resolver<'a, 'b> {
+    fanalysis: &'b FunctionAnalysis,
+
+    scopes: Vec<Vec<&'a str>>,
+
+    errors: Vec<CompilerError<'a>>,
+}
+
+/// Checks that every called function exists with matching arity and every variable is defined
+pub fn resolve<'a>(
+    module: &str,
+    blocks: &[Block<'a>],
+    fanalysis: &FunctionAnalysis,
+) -> Vec<CompilerError<'a>> {
+    return resolve_with_variables(module, blocks, fanalysis, Vec::new());
+}
+
+/// Same as `resolve`, top-level blocks can also use the given variables defined before them
+pub fn resolve_with_variables<'a>(
+    module: &str,
+    blocks: &[Block<'a>],
+    fanalysis: &FunctionAnalysis,
+    variables: Vec<&'a str>,
+) -> Vec<CompilerError<'a>> {
+    let mut resolver = Resolver {
+        fanalysis,
+        scopes: vec![variables],
+        errors: Vec::new(),
+    };
+
+    blocks
+        .iter()
+        // only main module has top-level expressions
+        .filter(|bl| module == "main" || matches!(bl, Block::FunctionDef(_) | Block::Test(_)))
+        .for_each(|bl| resolver.block(bl));
+
+    let mut tests: Vec<&Identifier<'a>> = Vec::new();
+    for bl in blocks {
+        if let Block::Test(Test { name, .. }) = bl {
+            if tests.iter().any(|test| test.val == name.val) {
+                resolver.error(format!("Test `{}` is already defined", name.val), name);
+            }
+            tests.push(name);
+        }
+    }
+
+    return resolver.errors;
+}
+
+/// Checks a single value, which can use the given variables
+pub fn resolve_value<'a>(
+    value: &Value<'a>,
+    fanalysis: &FunctionAnalysis,
+    variables: Vec<&'a str>,
+) -> Vec<CompilerError<'a>> {
+    let mut resolver = Resolver {
+        fanalysis,
+        scopes: vec![variables],
+        errors: Vec::new(),
+    };
+    resolver.value(value);
+
+    return resolver.errors;
+}
+
+impl<'a, 'b> Resolver<'a, 'b> {
+    fn error(&mut self, msg: String, name: &Identifier<'a>) {
+        self.errors
+            .push(CompilerError::new(msg, name.file, name.line, name.start));
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        self.scopes.iter().flatten().any(|&var| var == name)
+    }
+
+    fn suggestion(&self, msg: String, name: &str, variables: bool) -> String {
+        let functions = self
+            .fanalysis
+            .names()
+            .chain(BUILTINS.iter().map(|(builtin, _)| *builtin));
+        let candidates: Vec<&str> = if variables {
+            functions
+                .chain(self.scopes.iter().flatten().copied())
+                .collect()
+        } else {
+            functions.collect()
+        };
+
+        match suggest(name, candidates.into_iter()) {
+            Some(similar) => format!("{}; did you mean `{}`?", msg, similar),
+            None => msg,
+        }
+    }
+
+    fn block(&mut self, bl: &Block<'a>) {
+        match bl {
+            Block::Import(Import { path, symbols: _ }) => self.val_or_expression(path),
+            Block::VariableDef(VariableDef { name, v_or_exp, .. }) => {
+                self.val_or_expression(v_or_exp);
+                self.scopes.last_mut().unwrap().push(name.val);
+            }
+            Block::FunctionDef(FunctionDef { types, .. }) => {
+                // functions can't access variables outside of them
+                let saved = std::mem::take(&mut self.scopes);
+                self.clauses(types);
+                self.scopes = saved;
+            }
+            Block::Expression(exp) => self.expression(exp),
+            Block::Test(Test { name: _, blocks }) => {
+                let saved = std::mem::take(&mut self.scopes);
+                self.scopes.push(Vec::new());
+                blocks.iter().for_each(|bl| self.block(bl));
+                self.scopes = saved;
+            }
+        }
+    }
+
+    fn clauses(&mut self, types: &[(Vec<Value<'a>>, ValOrExp<'a>)]) {
+        for (args, body) in types {
+            self.scopes.push(
+                args.iter()
+                    .filter_map(|arg| match arg {
+                        Value::Var(name) => Some(name.val),
+                        _ => None,
+                    })
+                    .collect(),
+            );
+            args.iter().for_each(|arg| match arg {
+                Value::Var(_) => (),
+                val => self.value(val),
+            });
+            self.val_or_expression(body);
+            self.scopes.pop();
+        }
+    }
+
+    fn value(&mut self, v: &Value<'a>) {
+        match v {
+            Value::Var(name) if !self.is_variable(name.val) && !self.fanalysis.exists(name.val) => {
+                let msg = self.suggestion(
+                    format!("Variable with name `{}` does not exist", name.val),
+                    name.val,
+                    true,
+                );
+                self.error(msg, name);
+            }
+            Value::Function(name, arity)
+                if !self
+                    .fanalysis
+                    .arities(name.val)
+                    .map(|arities| arities.contains(arity))
+                    .unwrap_or(false) =>
+            {
+                let msg = self.fanalysis.undefined_message(name.val, *arity);
+                let msg = if self.fanalysis.exists(name.val) {
+                    msg
+                } else {
+                    self.suggestion(msg, name.val, false)
+                };
+                self.error(msg, name);
+            }
+            _ => (),
+        }
+    }
+
+    fn val_or_expression(&mut self, v_exp: &ValOrExp<'a>) {
+        match v_exp {
+            ValOrExp::Val(v) => self.value(v),
+            ValOrExp::Exp(exp) => self.expression(exp),
+        }
+    }
+
+    fn expression(&mut self, exp: &Exp<'a>) {
+        match exp {
+            Exp::Basic { op, args } => {
+                args.iter().for_each(|arg| self.val_or_expression(arg));
+
+                if let AritheticsOrCall::Call(name) = op {
+                    self.call(name, args.len() as u8);
+                }
+            }
+            Exp::If { cond, if_, else_ } => {
+                self.val_or_expression(cond);
+                self.val_or_expression(if_);
+                self.val_or_expression(else_);
+            }
+            Exp::Do { blocks } => {
+                self.scopes.push(Vec::new());
+                blocks.iter().for_each(|bl| self.block(bl));
+                self.scopes.pop();
+            }
+            Exp::Try {
+                body,
+                error,
+                handler,
+            } => {
+                self.val_or_expression(body);
+                self.scopes.push(vec![error.val]);
+                self.val_or_expression(handler);
+                self.scopes.pop();
+            }
+            Exp::Lambda { types } => self.clauses(types),
+        }
+    }
+
+    fn call(&mut self, name: &Identifier<'a>, argc: u8) {
+        if let Some((_, arity)) = BUILTINS.iter().find(|(builtin, _)| *builtin == name.val) {
+            if *arity != argc {
+                self.error(
+                    format!(
+                        "{}/{} expects {} argument{}, found {}",
+                        name.val,
+                        arity,
+                        arity,
+                        if *arity == 1 { "" } else { "s" },
+                        argc
+                    ),
+                    name,
+                );
+            }
+        } else if self.is_variable(name.val) {
+            // variables take precedence over functions with the same name
+        } else if self.fanalysis.exists(name.val) {
+            if !self.fanalysis.arities(name.val).unwrap().contains(&argc) {
+                let msg = self.fanalysis.undefined_message(name.val, argc);
+                self.error(msg, name);
+            }
+        } else {
+            let msg = self.suggestion(
+                format!("Function with name `{}` does not exist", name.val),
+                name.val,
+                true,
+            );
+            self.error(msg, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::Error;
+    use parser::{parse, FileCxt, Parser};
+
+    use super::resolve;
+    use crate::fanalysis::{analyse, FunctionAnalysis};
+
+    // Messages of errors in the source of the main module
+    fn errors(source: &str) -> Vec<String> {
+        let source = format!("mod main\n\n{}\n", source);
+        let (_, blocks) = parse().parse(FileCxt::new(&source, "./main.ot")).unwrap();
+        let mut fanalysis = FunctionAnalysis::new();
+        analyse(&blocks, &mut fanalysis);
+
+        return resolve("main", &blocks, &fanalysis)
+            .iter()
+            .map(|error| error.get().0)
+            .collect();
+    }
+
+    #[test]
+    fn suggests_similar_names() {
+        assert_eq!(
+            errors("(defn double [n] (* n 2))\n(print (doubel 1))"),
+            ["Function with name `doubel` does not exist; did you mean `double`?"]
+        );
+        assert_eq!(
+            errors("(def count 1)\n(print cont)"),
+            ["Variable with name `cont` does not exist; did you mean `count`?"]
+        );
+        assert_eq!(
+            errors("(print (strln \"a\"))"),
+            ["Function with name `strln` does not exist; did you mean `strlen`?"]
+        );
+        assert_eq!(
+            errors("(defn double [n] (* n 2))\n(print #'doubel/1)"),
+            ["Unknown function `doubel`; did you mean `double`?"]
+        );
+    }
+
+    #[test]
+    fn suggests_only_visible_names() {
+        // variables outside of functions can't be used in them
+        assert_eq!(
+            errors("(def count 1)\n(defn f [n] cont)\n(print (f 1))"),
+            ["Variable with name `cont` does not exist"]
+        );
+        assert_eq!(
+            errors("(print (xyzzy 1))"),
+            ["Function with name `xyzzy` does not exist"]
+        );
+    }
+
+    #[test]
+    fn reports_other_arities() {
+        assert_eq!(
+            errors("(defn double [n] (* n 2))\n(print (double 1 2))"),
+            ["`double/2` is not defined; `double/1` exists"]
+        );
+        assert_eq!(
+            errors("(print (strlen \"a\" 1))"),
+            ["strlen/1 expects 1 argument, found 2"]
+        );
+        assert!(errors("(defn double [n] (* n 2))\n(print (double 1))").is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 use parser::SymbolType;
 use vm_bytecode::Bytecode;
 
-use super::{CompilerCtx, Constant};
+use super::{compiler::Position, CompilerCtx, Constant};
 
 enum Type {
     Int,
@@ -17,7 +17,7 @@ impl From<SymbolType> for Type {
     }
 }
 
-pub fn serialize(ctx: CompilerCtx) -> Vec<u8> {
+pub fn serialize(ctx: &CompilerCtx, debug_info: bool) -> Vec<u8> {
     let mut bytes: Vec<u8> = Vec::new();
 
     magic(&mut bytes); // magic bytes
@@ -27,16 +27,58 @@ pub fn serialize(ctx: CompilerCtx) -> Vec<u8> {
             .expect("Main function is not defined")
             .to_le_bytes(),
     ); // index of start function
-    constants(&mut bytes, ctx.constants); // constants
+    constants(&mut bytes, &ctx.constants); // constants
     code(&mut bytes, ctx.functions.get_all()); // code
 
     let (sigs, sgroups) = ctx.signatures.get_signatures_and_groups();
     signatures(&mut bytes, sigs); // signatures
     groups(&mut bytes, sgroups); // signature groups
 
+    if debug_info {
+        debug(&mut bytes, ctx.debug.clone()); // positions of instructions in source
+    }
+
     return bytes;
 }
 
+fn debug(bytes: &mut Vec<u8>, mut functions: Vec<(u32, String, Vec<(u32, Position)>)>) {
+    functions.sort_by_key(|(index, _, _)| *index);
+
+    let mut files: Vec<&str> = Vec::new();
+    functions
+        .iter()
+        .flat_map(|(_, _, positions)| positions.iter())
+        .for_each(|(_, pos)| {
+            if !files.contains(&pos.file) {
+                files.push(pos.file);
+            }
+        });
+
+    bytes.extend(['D' as u8, 'B' as u8, 'U' as u8, 'G' as u8]);
+
+    bytes.extend((files.len() as u32).to_le_bytes());
+    for file in &files {
+        bytes.extend(file.as_bytes());
+        bytes.push(0); // ending null character
+    }
+
+    bytes.extend((functions.len() as u32).to_le_bytes());
+    for (_, name, positions) in functions {
+        bytes.extend(name.as_bytes());
+        bytes.push(0); // ending null character
+
+        bytes.extend((positions.len() as u32).to_le_bytes());
+        for (index, pos) in positions {
+            bytes.extend(index.to_le_bytes());
+            bytes.extend(
+                (files.iter().position(|&file| file == pos.file).unwrap() as u32).to_le_bytes(),
+            );
+            bytes.extend((pos.line as u32).to_le_bytes());
+            bytes.extend((pos.col as u32).to_le_bytes());
+        }
+    }
+}
+
 fn length(bytes: &mut Vec<u8>) -> impl FnOnce(&mut Vec<u8>) {
     bytes.extend([0, 0, 0, 0]);
     let len = bytes.len();
@@ -92,7 +134,7 @@ fn magic(bytes: &mut Vec<u8>) {
     bytes.extend(['S' as u8, 'P' as u8, 'S' as u8, 'L' as u8]);
 }
 
-fn constants(bytes: &mut Vec<u8>, cons: Vec<Constant>) {
+fn constants(bytes: &mut Vec<u8>, cons: &[Constant]) {
     bytes.extend((cons.len() as u32).to_le_bytes());
 
     for con in cons {
@@ -109,3 +151,60 @@ fn constants(bytes: &mut Vec<u8>, cons: Vec<Constant>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vm::{deserialize, DebugInfo};
+
+    use super::serialize;
+    use crate::loader::compile;
+
+    // Debug section of the project read back by the VM, with paths of its modules
+    fn debug_info(name: &str, strip: bool) -> (Option<DebugInfo>, String, String) {
+        let dir = std::env::temp_dir().join(format!("ot-debug-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (main, util) = (dir.join("main.ot"), dir.join("util.ot"));
+        std::fs::write(
+            &main,
+            "mod main\n\n(defn f [n]\n  (+ n 1))\n\n(print (g (f 1)))\n",
+        )
+        .unwrap();
+        std::fs::write(&util, "mod util\n\n(defn g [n] (* n 2))\n").unwrap();
+
+        let bytes = compile(dir.to_str().unwrap(), false, false, false, None, |ctx| {
+            serialize(&ctx, !strip)
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (_, _, functions, _, _, debug) = deserialize(&bytes).unwrap();
+        if let Some(debug) = &debug {
+            assert_eq!(debug.functions.len(), functions.len());
+        }
+        let path = |path: std::path::PathBuf| path.to_str().unwrap().to_owned();
+        return (debug, path(main), path(util));
+    }
+
+    #[test]
+    fn debug_section_round_trip() {
+        let (debug, main, util) = debug_info("round_trip", false);
+        let debug = debug.unwrap();
+
+        let mut files = debug.files.clone();
+        files.sort();
+        assert_eq!(files, [main.clone(), util.clone()]);
+
+        let function =
+            |name: &str| debug.functions.iter().position(|f| f.name == name).unwrap() as u32;
+        assert_eq!(debug.locate(function("f"), 0), Some((main.as_str(), 4, 6)));
+        assert_eq!(debug.locate(function("g"), 0), Some((util.as_str(), 3, 16)));
+        assert_eq!(debug.function_name(function("@main")), Some("@main"));
+        let (file, line, _) = debug.locate(function("@main"), usize::MAX).unwrap();
+        assert_eq!((file, line), (main.as_str(), 6));
+    }
+
+    #[test]
+    fn stripped_without_debug_section() {
+        let (debug, _, _) = debug_info("stripped", true);
+        assert!(debug.is_none());
+    }
+}
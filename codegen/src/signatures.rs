@@ -21,20 +21,20 @@ impl<'a> Signatures<'a> {
     }
 
     pub fn get_signatures_and_groups(
-        self,
+        &self,
     ) -> (
         Vec<(&'a str, u32, Vec<SymbolType>, SymbolType)>,
         Vec<Vec<u32>>,
     ) {
-        let sig = self.signatures;
+        let sig = &self.signatures;
 
         let mut tmp = sig
-            .into_iter()
-            .map(|((name, _argc), (index, args, ret))| (name, index, args, ret))
+            .iter()
+            .map(|((name, _argc), (index, args, ret))| (*name, *index, args.clone(), *ret))
             .collect::<Vec<(&'a str, u32, Vec<SymbolType>, SymbolType)>>();
         tmp.sort_by(|(_, x, _, _), (_, y, _, _)| x.cmp(&y));
 
-        return (tmp, self.groups);
+        return (tmp, self.groups.clone());
     }
 
     pub fn new_group(&mut self) -> u32 {
@@ -42,13 +42,14 @@ impl<'a> Signatures<'a> {
         return (self.groups.len() - 1) as u32;
     }
 
-    pub fn define(&mut self, name: &'a str, args: Vec<SymbolType>, rett: SymbolType) {
+    pub fn define(&mut self, name: &'a str, args: Vec<SymbolType>, rett: SymbolType) -> u32 {
         self.signatures
             .insert((name, args.len() as u8), (self.signaturec, args, rett));
 
         self.groups.last_mut().unwrap().push(self.signaturec);
 
         self.signaturec += 1;
+        return self.signaturec - 1;
     }
 
     pub fn get(&self, name: &'a str, argc: u8) -> Option<u32> {
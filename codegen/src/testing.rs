@@ -0,0 +1,88 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use vm::{deserialize, VM};
+
+use crate::compiler::test_name;
+use crate::loader;
+use crate::serialize::serialize;
+
+/// Result of a single test, failed test keeps its error and what it printed
+struct Outcome {
+    name: String,
+    failure: Option<(String, String)>,
+}
+
+/// Compiles the project with its tests and runs each of them, returns whether all of them passed
+///
+/// Runtime error fails only the test that raised it, the following tests still run
+pub fn run_tests(root: &str, debug: bool) -> bool {
+    let (tests, program) = loader::compile(root, debug, false, true, None, |ctx| {
+        let mut tests = ctx
+            .get_functions()
+            .filter_map(|(name, _, index, _, _)| Some((*index, test_name(name)?.to_owned())))
+            .collect::<Vec<_>>();
+        tests.sort();
+        (tests, serialize(&ctx, true))
+    });
+
+    let (_, constants, functions, signatures, groups, debug) = deserialize(&program).unwrap();
+    let output = Rc::new(RefCell::new(String::new()));
+    let print = {
+        let output = output.clone();
+        move |s: &str| output.borrow_mut().push_str(s)
+    };
+    let mut vm = VM::new(
+        constants, functions, signatures, groups, debug, print, false, false,
+    );
+
+    println!(
+        "\nrunning {} test{}",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+
+    let mut outcomes = Vec::with_capacity(tests.len());
+    for (index, name) in tests {
+        output.borrow_mut().clear();
+        let failure = vm
+            .run(index, Vec::new())
+            .err()
+            .map(|error| (error.to_string(), output.borrow().clone()));
+
+        match failure {
+            Some(_) => println!("test {} ... \x1b[31m\x1b[1mFAILED\x1b[0m", name),
+            None => println!("test {} ... \x1b[32m\x1b[1mok\x1b[0m", name),
+        }
+        outcomes.push(Outcome { name, failure });
+    }
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.failure.is_some())
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        println!("\nfailures:");
+        for Outcome { name, failure } in &failed {
+            let (error, output) = failure.as_ref().unwrap();
+            println!("\n---- {} ----", name);
+            if !output.is_empty() {
+                println!("{}", output.trim_end());
+            }
+            println!("{}", error);
+        }
+    }
+
+    println!(
+        "\ntest result: {}. {} passed; {} failed\n",
+        if failed.is_empty() {
+            "\x1b[32m\x1b[1mok\x1b[0m"
+        } else {
+            "\x1b[31m\x1b[1mFAILED\x1b[0m"
+        },
+        outcomes.len() - failed.len(),
+        failed.len()
+    );
+
+    return failed.is_empty();
+}
@@ -1,3 +1,5 @@
+use parser::Value;
+
 #[derive(Clone, Copy, PartialEq)]
 enum Escaped {
     True,
@@ -27,3 +29,49 @@ pub fn escape_string(s: &str) -> String {
         .filter(|&char| char != 0 as char)
         .collect::<String>()
 }
+
+/// Value as it is written in the source
+pub fn source_value(value: &Value) -> String {
+    match value {
+        Value::Int(i) => i.to_string(),
+        Value::String(s) => format!("\"{}\"", s.val),
+        Value::Atom(a) => format!(":{}", a.val),
+        Value::Var(name) => name.val.to_owned(),
+        Value::Function(name, arity) => format!("#'{}/{}", name.val, arity),
+        Value::Null => String::from("null"),
+    }
+}
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+
+    return row[b.len()];
+}
+
+/// Finds the closest name, if it is close enough to be a typo
+pub fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+
+    candidates
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
@@ -11,6 +11,9 @@ pub struct Variables<'a> {
 
     lambda: bool,
     lambda_variables: Vec<&'a str>,
+
+    // variables of enclosing functions the lambda can capture
+    outer: Vec<&'a str>,
 }
 
 pub enum Variable {
@@ -28,9 +31,10 @@ impl<'a> Variables<'a> {
             highest_local: 0,
             lambda: false,
             lambda_variables: Vec::with_capacity(0),
+            outer: Vec::with_capacity(0),
         }
     }
-    pub fn new_lambda() -> Variables<'a> {
+    pub fn new_lambda(enclosing: &Variables<'a>) -> Variables<'a> {
         Variables {
             vars: HashMap::new(),
             local_counter: 1,
@@ -39,22 +43,40 @@ impl<'a> Variables<'a> {
             highest_local: 1,
             lambda: true,
             lambda_variables: Vec::new(),
+            outer: enclosing
+                .vars
+                .keys()
+                .map(|(name, _)| *name)
+                .chain(enclosing.outer.iter().copied())
+                .collect(),
         }
     }
 
+    /// Whether the name is a variable of the function or of functions enclosing the lambda,
+    /// variables take precedence over functions with the same name
+    pub fn is_defined(&self, name: &'a str) -> bool {
+        self.get_local(name).is_some() || self.outer.contains(&name)
+    }
+
     pub fn lambda_context_variables(&mut self) -> Vec<&'a str> {
         self.lambda_variables.drain(..).collect()
     }
 
     pub fn reset(&mut self) -> u8 {
         self.vars.clear();
-        self.local_counter = 0;
+        // Lambdas keep their context in the first local
+        self.local_counter = self.lambda as u8;
         self.scope_counter = 0;
         let highest = self.highest_local;
-        self.highest_local = 0;
+        self.highest_local = self.local_counter;
         return highest;
     }
 
+    /// Number of locals the function needs so far
+    pub fn highest(&self) -> u8 {
+        self.highest_local
+    }
+
     pub fn scope(&mut self) {
         self.scope_counter += 1;
     }
@@ -75,7 +97,8 @@ impl<'a> Variables<'a> {
         self.scope_counter -= 1;
     }
 
-    pub fn get(&mut self, name: &'a str) -> Option<Variable> {
+    /// Looks up variable defined in current function without capturing it from lambda context
+    pub fn get_local(&self, name: &'a str) -> Option<u8> {
         self.vars
             .get(&(name, self.scope_counter))
             .or_else(|| {
@@ -89,7 +112,12 @@ impl<'a> Variables<'a> {
                 }
                 None
             })
-            .map(|&x| Variable::Local(x))
+            .map(|&x| x)
+    }
+
+    pub fn get(&mut self, name: &'a str) -> Option<Variable> {
+        self.get_local(name)
+            .map(|x| Variable::Local(x))
             .or_else(|| {
                 if !self.lambda {
                     return None;
@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::manifest::{packages, MANIFEST};
+
+const INTERVAL: Duration = Duration::from_millis(300);
+
+/// Builds the project again whenever a source file or manifest of the project or of its
+/// dependencies changes, never returns
+///
+/// Every build runs in a new process with the same arguments, so compiler errors don't stop
+/// watching
+pub fn watch(root: &str) -> ! {
+    let exe = std::env::current_exe().unwrap();
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != "--watch")
+        .collect();
+
+    let mut last = None;
+    loop {
+        let sources = sources(root);
+        if last.as_ref() != Some(&sources) {
+            if last.is_some() {
+                println!("\x1b[1mchange detected, building\x1b[0m");
+            }
+            match Command::new(&exe).args(&args).status() {
+                Ok(status) if status.success() => println!("\x1b[32m\x1b[1mbuilt\x1b[0m"),
+                Ok(_) => println!("\x1b[31m\x1b[1mbuild failed\x1b[0m"),
+                Err(e) => println!("\x1b[31m\x1b[1mERROR:\x1b[0m can't run compiler: {}", e),
+            }
+            println!("watching `{}` for changes", root);
+            last = Some(sources);
+        }
+
+        std::thread::sleep(INTERVAL);
+    }
+}
+
+// Source files and manifests with times of their last modification, with invalid manifest
+// only root is watched
+fn sources(root: &str) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let dirs = packages(root)
+        .map(|packages| packages.into_iter().map(|package| package.root).collect())
+        .unwrap_or_else(|_| vec![root.to_owned()]);
+
+    let mut sources = dirs
+        .iter()
+        .flat_map(|dir| {
+            std::fs::read_dir(dir)
+                .map(|dir| {
+                    dir.filter_map(|entry| entry.ok())
+                        .map(|entry| entry.path())
+                        .filter(|path| {
+                            path.extension().unwrap_or_default() == "ot"
+                                || path.file_name().unwrap_or_default() == MANIFEST
+                        })
+                        .map(|path| {
+                            let modified = path.metadata().and_then(|m| m.modified()).ok();
+                            (path, modified)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    sources.sort();
+
+    return sources;
+}
@@ -0,0 +1,41 @@
+use std::process::Command;
+
+// Checks the project with the main module and returns whether it passed and what was reported
+fn check(name: &str, source: &str, deny_warnings: bool) -> (bool, String) {
+    let dir = std::env::temp_dir().join(format!("ot-deny-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.ot"), source).unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_codegen"));
+    command.arg("check");
+    if deny_warnings {
+        command.arg("--deny-warnings");
+    }
+    let output = command.arg(&dir).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    return (output.status.success(), stderr);
+}
+
+#[test]
+fn warnings_fail_when_denied() {
+    let source = "mod main\n\n(def x 1)\n";
+
+    let (success, stderr) = check("allowed", source, false);
+    assert!(success);
+    assert!(stderr.contains("unused variable `x` [unused_variables]"));
+
+    let (success, stderr) = check("denied", source, true);
+    assert!(!success);
+    assert!(stderr.contains("aborting due to 1 warning(s)"));
+}
+
+#[test]
+fn allowed_warnings_dont_fail() {
+    let source = "mod main\n\n#[allow(unused_variables)]\n(def x 1)\n";
+
+    let (success, stderr) = check("suppressed", source, true);
+    assert!(success);
+    assert!(stderr.is_empty());
+}
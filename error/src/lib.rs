@@ -10,11 +10,17 @@ macro_rules! raise_vm_error {
 
 pub trait Error {
     fn display(&self) {
+        self.report();
+        std::process::exit(1);
+    }
+
+    /// Prints the error without exiting
+    fn report(&self) {
         let (msg, file, line, col) = self.get();
 
         let content = std::fs::read_to_string(file).unwrap();
 
-        println!(
+        eprintln!(
             "\x1b[31m{}\x1b[0m on line {} and col {}; {}:{}:{}",
             msg, line, col, file, line, col
         );
@@ -32,14 +38,14 @@ pub trait Error {
             .enumerate()
             .for_each(|(index, curr_line)| {
                 if index == offset {
-                    println!(
+                    eprintln!(
                         "\x1b[31m\x1b[1m{}:\t {}\x1b[0m",
-                        (line + index) - 1,
+                        to_skip + index + 1,
                         curr_line
                             .chars()
                             .enumerate()
                             .map(|(i, ch)| {
-                                if i == col - 1 {
+                                if i + 1 == col {
                                     format!("\x1b[41m\x1b[37m\x1b[4m{}\x1b[0m\x1b[31m\x1b[1m", ch)
                                 } else {
                                     ch.to_string()
@@ -48,11 +54,32 @@ pub trait Error {
                             .collect::<String>()
                     );
                 } else {
-                    println!("{}:\t {}", (line + index) - 1, curr_line);
+                    eprintln!("{}:\t {}", to_skip + index + 1, curr_line);
                 }
             });
+    }
 
-        std::process::exit(1);
+    fn warn(&self) {
+        let (msg, file, line, col) = self.get();
+
+        let content = std::fs::read_to_string(file).unwrap();
+
+        eprintln!(
+            "\x1b[33m\x1b[1mwarning:\x1b[0m {} on line {} and col {}; {}:{}:{}",
+            msg, line, col, file, line, col
+        );
+
+        if let Some(curr_line) = content.split("\n").nth(line.saturating_sub(1)) {
+            eprintln!("{}:\t {}", line, curr_line.trim_end());
+            eprintln!(
+                " \t {}\x1b[33m^\x1b[0m",
+                curr_line
+                    .chars()
+                    .take(col.saturating_sub(1))
+                    .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                    .collect::<String>()
+            );
+        }
     }
 
     fn get_formated_with_file(&self, content: &str) -> String {
@@ -79,13 +106,13 @@ pub trait Error {
             .for_each(|(index, curr_line)| {
                 if index == offset {
                     result += &format!(
-                        "\x1b[31m\x1b[1m{}:\t {}\x1b[0m",
-                        (line + index) - 1,
+                        "\n\x1b[31m\x1b[1m{}:\t {}\x1b[0m",
+                        to_skip + index + 1,
                         curr_line
                             .chars()
                             .enumerate()
                             .map(|(i, ch)| {
-                                if i == col - 1 {
+                                if i + 1 == col {
                                     format!("\x1b[41m\x1b[37m\x1b[4m{}\x1b[0m\x1b[31m\x1b[1m", ch)
                                 } else {
                                     ch.to_string()
@@ -94,7 +121,7 @@ pub trait Error {
                             .collect::<String>()
                     );
                 } else {
-                    result += &format!("{}:\t {}", (line + index) - 1, curr_line);
+                    result += &format!("\n{}:\t {}", to_skip + index + 1, curr_line);
                 }
             });
 
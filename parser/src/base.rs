@@ -113,6 +113,7 @@ pub fn non_recoverable<'a, A>(parser: impl Parser<'a, A>) -> impl Parser<'a, A>
     }
 }
 
+// errors which can't be recovered from keep their message
 pub fn map_err<'a, A, S>(parser: impl Parser<'a, A>, f: impl Fn(&str) -> S) -> impl Parser<'a, A>
 where
     S: Into<String>,
@@ -127,7 +128,7 @@ where
                  file,
              }: ParserError| ParserError {
                 recoverable,
-                msg: f(&msg).into(),
+                msg: if recoverable { f(&msg).into() } else { msg },
                 line,
                 char,
                 file,
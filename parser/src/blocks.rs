@@ -1,16 +1,16 @@
 use crate::{
-    error::expected_exp_or_val, identifier, lambda_, left, match_literal, non_recoverable,
-    square_brackets, Identifier,
+    catch_, doc_comment, error::expected_exp_or_val, identifier, lambda_, left, match_literal,
+    non_recoverable, spanned, square_brackets, try_, Identifier,
 };
 
 use super::{
-    arithetics, brackets, def, defn, do_, if_, map, or, pair, right, value, var, zero_or_more,
-    Arithmetics, Parser, Value, __,
+    arithetics, brackets, def, defn, deftest, do_, if_, map, one_or_more, or, pair, right, value,
+    var, zero_or_more, Arithmetics, Parser, Value, __,
 };
 
 #[derive(Debug, PartialEq)]
 pub enum AritheticsOrCall<'a> {
-    Arithmetics(Arithmetics),
+    Arithmetics(Arithmetics, Identifier<'a>),
     Call(Identifier<'a>),
     ExternCall(Identifier<'a>),
 }
@@ -30,8 +30,13 @@ pub enum Exp<'a> {
         blocks: Vec<Block<'a>>,
     },
     Lambda {
-        args: Vec<Identifier<'a>>,
+        types: Vec<(Vec<Value<'a>>, ValOrExp<'a>)>,
+    },
+    Try {
         body: Box<ValOrExp<'a>>,
+        // variable with the thrown value in the handler
+        error: Identifier<'a>,
+        handler: Box<ValOrExp<'a>>,
     },
 }
 
@@ -46,6 +51,9 @@ pub enum ValOrExp<'a> {
 pub struct VariableDef<'a> {
     pub name: Identifier<'a>,
     pub v_or_exp: ValOrExp<'a>,
+
+    // text of `##` comments before the definition
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -53,6 +61,9 @@ pub struct FunctionDef<'a> {
     pub name: Identifier<'a>,
 
     pub types: Vec<(Vec<Value<'a>>, ValOrExp<'a>)>,
+
+    // text of `##` comments before the definition
+    pub doc: Option<String>,
 }
 
 impl<'a> FunctionDef<'a> {
@@ -60,11 +71,15 @@ impl<'a> FunctionDef<'a> {
         name: Identifier<'a>,
         types: Vec<(Vec<Value<'a>>, ValOrExp<'a>)>,
     ) -> FunctionDef<'a> {
-        FunctionDef { name, types }
+        FunctionDef {
+            name,
+            types,
+            doc: None,
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SymbolType {
     Int,
     Str,
@@ -84,23 +99,40 @@ pub struct Import<'a> {
     pub symbols: Vec<Symbol<'a>>,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Test<'a> {
+    pub name: Identifier<'a>,
+
+    pub blocks: Vec<Block<'a>>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Block<'a> {
     VariableDef(VariableDef<'a>),
     FunctionDef(FunctionDef<'a>),
     Expression(Exp<'a>),
     Import(Import<'a>),
+    Test(Test<'a>),
 }
 
 pub fn block<'a>() -> impl Parser<'a, Block<'a>> {
     |ctx| {
-        __(or!(
+        let (ctx, doc) = doc_comment().parse(ctx)?;
+        let (ctx, block) = __(or!(
             map(function_def(), |fd| Block::FunctionDef(fd)),
+            map(test_def(), |test| Block::Test(test)),
             map(variable_def(), |vd| Block::VariableDef(vd)),
             map(import(), |import| Block::Import(import)),
             map(expression(), |exp| Block::Expression(exp))
         ))
-        .parse(ctx)
+        .parse(ctx)?;
+
+        let block = match block {
+            Block::FunctionDef(fd) => Block::FunctionDef(FunctionDef { doc, ..fd }),
+            Block::VariableDef(vd) => Block::VariableDef(VariableDef { doc, ..vd }),
+            block => block,
+        };
+        Ok((ctx, block))
     }
 }
 
@@ -133,6 +165,23 @@ fn if_exp<'a>() -> impl Parser<'a, (ValOrExp<'a>, (ValOrExp<'a>, ValOrExp<'a>))>
     }
 }
 
+// (try body (catch e handler))
+fn try_exp<'a>() -> impl Parser<'a, (ValOrExp<'a>, (Identifier<'a>, ValOrExp<'a>))> {
+    |ctx| {
+        brackets(right(
+            __(try_()),
+            pair(
+                __(value_or_expression()),
+                __(brackets(right(
+                    __(catch_()),
+                    pair(__(var()), __(value_or_expression())),
+                ))),
+            ),
+        ))
+        .parse(ctx)
+    }
+}
+
 /*
    (match x
        0 (foo)
@@ -145,14 +194,18 @@ fn if_exp<'a>() -> impl Parser<'a, (ValOrExp<'a>, (ValOrExp<'a>, ValOrExp<'a>))>
 //     |ctx| brackets()
 // }
 
-fn lambda_exp<'a>() -> impl Parser<'a, (Vec<Identifier<'a>>, ValOrExp<'a>)> {
+// (lambda
+//    [0] :zero
+//    [n] n
+// )
+fn lambda_exp<'a>() -> impl Parser<'a, Vec<(Vec<Value<'a>>, ValOrExp<'a>)>> {
     |ctx| {
         brackets(right(
             __(lambda_()),
-            pair(
-                __(square_brackets(zero_or_more(__(var())))),
+            one_or_more(__(pair(
+                square_brackets(zero_or_more(__(value()))),
                 __(value_or_expression()),
-            ),
+            ))),
         ))
         .parse(ctx)
     }
@@ -162,22 +215,26 @@ fn lambda_exp<'a>() -> impl Parser<'a, (Vec<Identifier<'a>>, ValOrExp<'a>)> {
 fn expression<'a>() -> impl Parser<'a, Exp<'a>> {
     |ctx| {
         or(
-            map(lambda_exp(), |(args, body)| Exp::Lambda {
-                args,
-                body: Box::new(body),
-            }),
+            map(lambda_exp(), |types| Exp::Lambda { types }),
             or(
                 map(do_exp(), |blocks| Exp::Do { blocks }),
-                or(
+                or!(
                     map(if_exp(), |(cond, (tr, fl))| Exp::If {
                         cond: Box::new(cond),
                         if_: Box::new(tr),
                         else_: Box::new(fl),
                     }),
+                    map(try_exp(), |(body, (error, handler))| Exp::Try {
+                        body: Box::new(body),
+                        error,
+                        handler: Box::new(handler),
+                    }),
                     map(
                         brackets(pair(
                             __(or!(
-                                map(arithetics(), |a| AritheticsOrCall::Arithmetics(a)),
+                                map(spanned(arithetics()), |(a, id)| {
+                                    AritheticsOrCall::Arithmetics(a, id)
+                                }),
                                 map(var(), |s| AritheticsOrCall::Call(s)),
                                 map(right(match_literal("!"), var()), |s| {
                                     AritheticsOrCall::ExternCall(s)
@@ -185,8 +242,8 @@ fn expression<'a>() -> impl Parser<'a, Exp<'a>> {
                             )),
                             zero_or_more(__(value_or_expression())),
                         )),
-                        |(op, args)| Exp::Basic { op, args },
-                    ),
+                        |(op, args)| Exp::Basic { op, args }
+                    )
                 ),
             ),
         )
@@ -252,6 +309,26 @@ pub fn function_def<'a>() -> impl Parser<'a, FunctionDef<'a>> {
     )
 }
 
+// (deftest sum
+//    (def x 2)
+//    (assert-eq 4 (+ x x))
+// )
+pub fn test_def<'a>() -> impl Parser<'a, Test<'a>> {
+    map(
+        right(
+            match_literal("("),
+            left(
+                right(
+                    __(deftest()),
+                    non_recoverable(pair(__(var()), one_or_more(__(block())))),
+                ),
+                non_recoverable(__(match_literal(")"))),
+            ),
+        ),
+        |(name, blocks)| Test { name, blocks },
+    )
+}
+
 // (def x 69)
 pub fn variable_def<'a>() -> impl Parser<'a, VariableDef<'a>> {
     map(
@@ -271,6 +348,7 @@ pub fn variable_def<'a>() -> impl Parser<'a, VariableDef<'a>> {
         |(name, val_e)| VariableDef {
             name,
             v_or_exp: val_e,
+            doc: None,
         },
     )
 }
@@ -34,6 +34,14 @@ pub fn if_<'a>() -> impl Parser<'a, ()> {
     or(match_literal("if"), match_literal("?"))
 }
 
+pub fn try_<'a>() -> impl Parser<'a, ()> {
+    match_literal("try")
+}
+
+pub fn catch_<'a>() -> impl Parser<'a, ()> {
+    match_literal("catch")
+}
+
 pub fn lambda_<'a>() -> impl Parser<'a, ()> {
     or!(match_literal("lambda"), match_literal("λ"))
 }
@@ -48,6 +56,10 @@ pub fn defn<'a>() -> impl Parser<'a, ()> {
     match_literal("defn")
 }
 
+pub fn deftest<'a>() -> impl Parser<'a, ()> {
+    match_literal("deftest")
+}
+
 pub fn arithetics<'a>() -> impl Parser<'a, Arithmetics> {
     or!(
         map(match_literal("+"), |_| Arithmetics::Add),
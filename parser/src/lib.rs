@@ -4,6 +4,7 @@ mod error;
 mod keyword;
 mod macros;
 mod string;
+mod syntax;
 mod value;
 
 #[cfg(test)]
@@ -14,16 +15,19 @@ pub use base::*;
 pub use blocks::*;
 pub use keyword::*;
 pub use string::*;
+pub use syntax::*;
 pub use value::*;
 
 pub fn parse<'a>() -> impl Parser<'a, Vec<Block<'a>>> {
     |mut ctx: FileCxt<'a>| {
-        let (ctx_, module) = __(right(
-            match_literal("mod"),
-            __(identifier(|char| char.is_ascii_alphabetic())),
-        ))
-        .parse(ctx)
-        .unwrap();
+        let (ctx_, module) = map_err(
+            __(right(
+                match_literal("mod"),
+                __(identifier(|char| char.is_ascii_alphabetic())),
+            )),
+            |_| "Expected `mod <name>` at the start of the file",
+        )
+        .parse(ctx)?;
         ctx = ctx_;
         ctx.module = module.val;
 
@@ -57,6 +61,196 @@ mod tests {
         println!("{:?}", blocks);
     }
 
+    #[test]
+    fn function_reference() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n# comment\n(def f #'sum/2)",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        match &blocks[..] {
+            [crate::Block::VariableDef(crate::VariableDef {
+                v_or_exp: crate::ValOrExp::Val(crate::Value::Function(name, 2)),
+                ..
+            })] => assert_eq!(name.val, "sum"),
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn function_reference_arity_overflow() {
+        use error::Error;
+
+        let error = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n(def f #'sum/300)",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap_err();
+
+        assert_eq!(error.get().0, "Arity `300` is too large");
+    }
+
+    #[test]
+    fn lambda_clauses() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n(lambda [0] :zero [n] n [a b] (+ a b))",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        match &blocks[..] {
+            [crate::Block::Expression(crate::Exp::Lambda { types })] => assert_eq!(
+                types.iter().map(|(args, _)| args.len()).collect::<Vec<_>>(),
+                vec![1, 1, 2]
+            ),
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn test_definition() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n(deftest sum (def x 2) (assert-eq 4 (+ x x)))",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        match &blocks[..] {
+            [crate::Block::Test(crate::Test { name, blocks })] => {
+                assert_eq!(name.val, "sum");
+                match &blocks[..] {
+                    [_, crate::Block::Expression(crate::Exp::Basic {
+                        op: crate::AritheticsOrCall::Call(call),
+                        ..
+                    })] => assert_eq!(call.val, "assert-eq"),
+                    _ => panic!("unexpected body {:?}", blocks),
+                }
+            }
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn doc_comments() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n## Doubles\n##   the number\n(defn twice [n] (* n 2))\n## not\n# plain\n(defn f [] 1)\n## lost\n\n(def x 1)",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        match &blocks[..] {
+            [crate::Block::FunctionDef(twice), crate::Block::FunctionDef(f), crate::Block::VariableDef(x)] =>
+            {
+                assert_eq!(twice.doc.as_deref(), Some("Doubles\n  the number"));
+                assert_eq!(f.doc, None);
+                assert_eq!(x.doc, None);
+            }
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn names_and_empty_string() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n(str->int (concat \"\" \"1\"))\n(file-exists? x)",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        use crate::{AritheticsOrCall::Call, Block::Expression, Exp::Basic, ValOrExp, Value};
+
+        match &blocks[..] {
+            [Expression(Basic { op: Call(f), args }), Expression(Basic { op: Call(g), .. })] => {
+                assert_eq!(f.val, "str->int");
+                assert_eq!(g.val, "file-exists?");
+                match &args[..] {
+                    [ValOrExp::Exp(Basic { args, .. })] => match &args[..] {
+                        [ValOrExp::Val(Value::String(empty)), _] => assert_eq!(empty.val, ""),
+                        _ => panic!("unexpected arguments {:?}", args),
+                    },
+                    _ => panic!("unexpected arguments {:?}", args),
+                }
+            }
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn try_catch() {
+        let blocks = parse()
+            .parse(FileCxt::new_test(
+                "mod main\n(try (f x) (catch e (print e)))\n(trying x)",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        use crate::{AritheticsOrCall::Call, Block::Expression, Exp, ValOrExp};
+
+        match &blocks[..] {
+            [Expression(Exp::Try {
+                body,
+                error,
+                handler,
+            }), Expression(Exp::Basic { op: Call(f), .. })] => {
+                assert!(matches!(**body, ValOrExp::Exp(Exp::Basic { .. })));
+                assert_eq!(error.val, "e");
+                assert!(matches!(**handler, ValOrExp::Exp(Exp::Basic { .. })));
+                assert_eq!(f.val, "trying");
+            }
+            _ => panic!("unexpected blocks {:?}", blocks),
+        }
+    }
+
+    #[test]
+    fn syntax_keeps_comments() {
+        let nodes = crate::syntax()
+            .parse(FileCxt::new_test(
+                "mod main\n# comment\n(f #'g/1 \"a # b\") # trailing",
+                "test.spsl",
+                0,
+                1,
+            ))
+            .unwrap()
+            .1;
+
+        match &nodes[..] {
+            [_, _, (1, crate::Node::Comment(c)), (1, crate::Node::List { items, .. }), (0, crate::Node::Comment(t))] =>
+            {
+                assert_eq!(c.val, "# comment");
+                assert_eq!(t.val, "# trailing");
+                assert_eq!(items.len(), 3);
+            }
+            _ => panic!("unexpected nodes {:?}", nodes),
+        }
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
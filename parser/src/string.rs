@@ -15,7 +15,7 @@ pub fn __<'a, A>(parser: impl Parser<'a, A>) -> impl Parser<'a, A> {
         map(
             zero_or_more(or(
                 map(identifier(|ch| ch.is_whitespace()), |_| ()),
-                comment(),
+                map(comment(), |_| ()),
             )),
             |_| (),
         ),
@@ -23,11 +23,55 @@ pub fn __<'a, A>(parser: impl Parser<'a, A>) -> impl Parser<'a, A> {
     )
 }
 
-fn comment<'a>() -> impl Parser<'a, ()> {
-    map(
-        pair(match_literal("#"), identifier(|ch| ch != '\n')),
-        |_| (),
-    )
+/// Comment with the leading `#`
+pub fn comment<'a>() -> impl Parser<'a, Identifier<'a>> {
+    |ctx: FileCxt<'a>| {
+        // `#'` starts a function reference, not a comment
+        if ctx.s.starts_with("#'") {
+            return Err(ParserError::new(
+                "Expected comment",
+                ctx.file,
+                ctx.line,
+                ctx.char,
+            ));
+        }
+
+        map(
+            spanned(pair(match_literal("#"), identifier(|ch| ch != '\n'))),
+            |(_, id)| id,
+        )
+        .parse(ctx)
+    }
+}
+
+/// Text of `##` comments before the next token without the markers, documentation ends with
+/// a blank line or a plain comment
+pub fn doc_comment<'a>() -> impl Parser<'a, Option<String>> {
+    |mut ctx: FileCxt<'a>| {
+        let mut lines: Vec<&'a str> = Vec::new();
+        loop {
+            if let Ok((after, space)) = identifier(|ch| ch.is_whitespace()).parse(ctx) {
+                if space.val.matches('\n').count() > 1 {
+                    lines.clear();
+                }
+                ctx = after;
+            } else if let Ok((after, comment)) = comment().parse(ctx) {
+                match comment.val.strip_prefix("##") {
+                    Some(line) => lines.push(line.strip_prefix(' ').unwrap_or(line).trim_end()),
+                    None => lines.clear(),
+                }
+                ctx = after;
+            } else {
+                break;
+            }
+        }
+
+        let doc = match lines.is_empty() {
+            true => None,
+            false => Some(lines.join("\n")),
+        };
+        return Ok((ctx, doc));
+    }
 }
 
 pub fn match_literal<'a>(literal: &'static str) -> impl Parser<'a, ()> {
@@ -54,6 +98,23 @@ pub fn match_literal<'a>(literal: &'static str) -> impl Parser<'a, ()> {
     }
 }
 
+/// Captures input consumed by the parser as identifier, used to keep position of keywords
+pub fn spanned<'a, A>(parser: impl Parser<'a, A>) -> impl Parser<'a, (A, Identifier<'a>)> {
+    move |ctx: FileCxt<'a>| {
+        let (after, val) = parser.parse(ctx)?;
+
+        let id = Identifier {
+            val: &ctx.s[..ctx.s.len() - after.s.len()],
+            line: ctx.line,
+            start: ctx.char + 1,
+            end: after.char + 1,
+            file: ctx.file,
+        };
+
+        Ok((after, (val, id)))
+    }
+}
+
 pub fn identifier<'a>(valid_char: impl Fn(char) -> bool) -> impl Parser<'a, Identifier<'a>> {
     move |ctx: FileCxt<'a>| {
         if let Some(next) = ctx.s.chars().next() {
@@ -0,0 +1,114 @@
+use crate::{
+    comment, identifier, left, map, match_literal, maybe, or, pair, right, spanned, FileCxt,
+    Identifier, Parser, ParserError,
+};
+
+/// Source as a tree of brackets and tokens, keeps comments and line breaks for formatting
+#[derive(Debug, PartialEq)]
+pub enum Node<'a> {
+    List {
+        square: bool,
+        // nodes with number of line breaks before them
+        items: Vec<(usize, Node<'a>)>,
+    },
+    Token(Identifier<'a>),
+    Comment(Identifier<'a>),
+}
+
+// Line breaks in whitespace before the next node
+fn newlines<'a>() -> impl Parser<'a, usize> {
+    maybe(
+        map(identifier(|ch| ch.is_whitespace()), |s| {
+            s.val.matches('\n').count()
+        }),
+        || 0,
+    )
+}
+
+fn token<'a>() -> impl Parser<'a, Identifier<'a>> {
+    let quoted = |quote: &'static str| {
+        map(
+            spanned(right(
+                match_literal(quote),
+                left(
+                    maybe(
+                        map(identifier(move |ch| !quote.starts_with(ch)), |_| ()),
+                        || (),
+                    ),
+                    match_literal(quote),
+                ),
+            )),
+            |(_, id)| id,
+        )
+    };
+
+    or!(
+        quoted("\""),
+        quoted("'"),
+        map(
+            spanned(pair(match_literal("#'"), identifier(is_token_char))),
+            |(_, id)| id
+        ),
+        identifier(is_token_char)
+    )
+}
+
+fn is_token_char(ch: char) -> bool {
+    !ch.is_whitespace() && !"()[]\"#".contains(ch)
+}
+
+fn items<'a>(ctx: FileCxt<'a>) -> Result<(FileCxt<'a>, Vec<(usize, Node<'a>)>), ParserError<'a>> {
+    let mut items = Vec::new();
+    let mut ctx = ctx;
+
+    loop {
+        let (after, lines) = newlines().parse(ctx)?;
+        match node().parse(after) {
+            Ok((after, node)) => {
+                items.push((lines, node));
+                ctx = after;
+            }
+            Err(_) => return Ok((after, items)),
+        }
+    }
+}
+
+fn node<'a>() -> impl Parser<'a, Node<'a>> {
+    |ctx| {
+        or!(
+            map(comment(), |id| Node::Comment(id)),
+            map(
+                right(match_literal("("), left(items, match_literal(")"))),
+                |items| Node::List {
+                    square: false,
+                    items
+                }
+            ),
+            map(
+                right(match_literal("["), left(items, match_literal("]"))),
+                |items| Node::List {
+                    square: true,
+                    items
+                }
+            ),
+            map(token(), |id| Node::Token(id))
+        )
+        .parse(ctx)
+    }
+}
+
+/// Parses the whole file into nodes with number of line breaks before them
+pub fn syntax<'a>() -> impl Parser<'a, Vec<(usize, Node<'a>)>> {
+    |ctx: FileCxt<'a>| {
+        let (ctx, nodes) = items(ctx)?;
+        if let Some(next) = ctx.s.chars().next() {
+            return Err(ParserError::new(
+                format!("Unexpected character `{}`", next),
+                ctx.file,
+                ctx.line,
+                ctx.char,
+            ));
+        }
+        return Ok((ctx, nodes));
+    }
+}
@@ -1,6 +1,8 @@
-use crate::Identifier;
+use crate::{FileCxt, Identifier, ParserError};
 
-use super::{identifier, left, map, match_literal, maybe, or, pair, right, Parser};
+use super::{
+    identifier, left, map, match_literal, maybe, non_recoverable, or, pair, right, Parser,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Value<'a> {
@@ -8,6 +10,7 @@ pub enum Value<'a> {
     String(Identifier<'a>),
     Atom(Identifier<'a>),
     Var(Identifier<'a>),
+    Function(Identifier<'a>, u8),
     Null,
 }
 
@@ -20,7 +23,22 @@ fn number<'a>() -> impl Parser<'a, i32> {
 pub fn string<'a>() -> impl Parser<'a, Identifier<'a>> {
     right(
         match_literal("\""),
-        left(identifier(|char| char != '"'), match_literal("\"")),
+        left(
+            |ctx: FileCxt<'a>| {
+                // `""` is an empty identifier at the closing quote
+                identifier(|char| char != '"').parse(ctx).or_else(|_| {
+                    let empty = Identifier {
+                        val: &ctx.s[..0],
+                        line: ctx.line,
+                        start: ctx.char + 1,
+                        end: ctx.char + 1,
+                        file: ctx.file,
+                    };
+                    Ok((ctx, empty))
+                })
+            },
+            match_literal("\""),
+        ),
     )
 }
 
@@ -74,8 +92,36 @@ fn null<'a>() -> impl Parser<'a, ()> {
     or(match_literal("null"), match_literal("!"))
 }
 
+// #'sum/2
+fn function<'a>() -> impl Parser<'a, (Identifier<'a>, u8)> {
+    right(
+        match_literal("#'"),
+        pair(var(), right(match_literal("/"), non_recoverable(arity()))),
+    )
+}
+
+fn arity<'a>() -> impl Parser<'a, u8> {
+    |ctx| {
+        let (ctx, s) = identifier(|char| char.is_ascii_digit()).parse(ctx)?;
+        match s.val.parse() {
+            Ok(arity) => Ok((ctx, arity)),
+            Err(_) => Err(ParserError::new(
+                format!("Arity `{}` is too large", s.val),
+                s.file,
+                s.line,
+                s.start,
+            )),
+        }
+    }
+}
+
+// `-`, `>` and `?` are used in names like `assert-eq`, `str->int` or `file-exists?`, but
+// they can't start one
 pub fn var<'a>() -> impl Parser<'a, Identifier<'a>> {
-    identifier(|c| c.is_ascii_alphabetic() || c == '_')
+    |ctx| {
+        identifier(|c| c.is_ascii_alphabetic() || c == '_').parse(ctx)?;
+        identifier(|c| c.is_ascii_alphabetic() || "_->?".contains(c)).parse(ctx)
+    }
 }
 
 pub fn value<'a>() -> impl Parser<'a, Value<'a>> {
@@ -84,6 +130,7 @@ pub fn value<'a>() -> impl Parser<'a, Value<'a>> {
         map(string(), |s| Value::String(s)),
         map(int(), |n| Value::Int(n)),
         map(atom(), |a| Value::Atom(a)),
+        map(function(), |(name, arity)| Value::Function(name, arity)),
         map(var(), |s| Value::Var(s))
     )
 }
@@ -0,0 +1,571 @@
+use std::{collections::HashMap, fmt};
+
+use crate::Bytecode;
+
+/// Error found while assembling, lines are numbered from 1
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.msg)
+        } else {
+            write!(f, "line {}: {}", self.line, self.msg)
+        }
+    }
+}
+
+enum Constant {
+    Int(i32),
+    String(String),
+}
+
+// same as type ids used by the compiler
+#[derive(Clone, Copy)]
+enum Type {
+    Int = 0,
+    String = 1,
+}
+
+struct Function {
+    argc: u8,
+    localc: u8,
+    code: Vec<Bytecode>,
+
+    name: Option<String>,
+    // instruction index, file, line, column
+    positions: Vec<(u32, String, u32, u32)>,
+}
+
+#[derive(Default)]
+struct Program {
+    start: Option<u32>,
+    constants: Vec<Constant>,
+    functions: Vec<Function>,
+    signatures: Vec<(String, Vec<Type>, Type)>,
+    groups: Vec<Vec<u32>>,
+}
+
+/// Escapes string so it can be used as a string literal in assembly
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+
+    return escaped;
+}
+
+/// Formats instruction the same way as its `Debug` implementation,
+/// but with function indices replaced by their labels
+pub fn format_instruction(bc: Bytecode, label: impl Fn(u32) -> String) -> String {
+    match bc {
+        Bytecode::Call(f) => format!("Call({})", label(f)),
+        Bytecode::CallRet(f) => format!("CallRet({})", label(f)),
+        Bytecode::NewLambda(f, c, o) => format!("NewLambda({}, {}, {})", label(f), c, o),
+        bc => format!("{:?}", bc),
+    }
+}
+
+/// Assembles textual program into the same bytes as the compiler produces
+///
+/// ```text
+/// .start main
+/// .const str "hello"
+/// .func main 0 0
+/// .name "main"
+/// .pos "./main.ot" 1 1
+///     ConstantGet(0)
+///     Print
+///     Ret
+/// ```
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    let labels = labels(src)?;
+    let program = parse(src, &labels)?;
+
+    return Ok(encode(program));
+}
+
+// Function labels have to be known before the code referencing them
+fn labels(src: &str) -> Result<HashMap<&str, u32>, AsmError> {
+    let mut labels = HashMap::new();
+
+    for (index, line) in src.lines().enumerate() {
+        let tokens = tokenize(line, index + 1)?;
+        if let [".func", label, ..] = tokens.as_slice() {
+            if !is_label(label) {
+                return Err(error(
+                    index + 1,
+                    format!("`{}` is not a valid label", label),
+                ));
+            }
+            if labels.insert(*label, labels.len() as u32).is_some() {
+                return Err(error(
+                    index + 1,
+                    format!("Label `{}` is defined twice", label),
+                ));
+            }
+        }
+    }
+
+    return Ok(labels);
+}
+
+fn parse(src: &str, labels: &HashMap<&str, u32>) -> Result<Program, AsmError> {
+    let mut program = Program::default();
+    // positions of the next instruction
+    let mut pending_positions: Vec<(String, u32, u32)> = Vec::new();
+
+    for (index, line) in src.lines().enumerate() {
+        let line_number = index + 1;
+        let tokens = tokenize(line, line_number)?;
+        let err = |msg: String| error(line_number, msg);
+
+        let (first, args) = match tokens.split_first() {
+            Some((first, args)) => (*first, args),
+            None => continue,
+        };
+
+        match first {
+            ".start" => match args {
+                [label] => program.start = Some(function(label, labels).map_err(err)?),
+                _ => return Err(err(String::from("Expected `.start <label>`"))),
+            },
+            ".const" => match args {
+                ["int", int] => program.constants.push(Constant::Int(
+                    int.parse()
+                        .map_err(|_| err(format!("`{}` is not a valid int", int)))?,
+                )),
+                ["str", s] => program
+                    .constants
+                    .push(Constant::String(unescape(s).map_err(err)?)),
+                _ => {
+                    return Err(err(String::from(
+                        "Expected `.const int <int>` or `.const str <string>`",
+                    )))
+                }
+            },
+            ".sig" => match args {
+                [name, rest @ ..] if rest.len() >= 2 && rest[rest.len() - 2] == "->" => {
+                    let types = rest[..rest.len() - 2]
+                        .iter()
+                        .map(|t| parse_type(t))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(err)?;
+                    let ret = parse_type(rest[rest.len() - 1]).map_err(err)?;
+                    program.signatures.push((name.to_string(), types, ret));
+                }
+                _ => {
+                    return Err(err(String::from(
+                        "Expected `.sig <name> <types> -> <type>`",
+                    )))
+                }
+            },
+            ".group" => program.groups.push(
+                args.iter()
+                    .map(|i| number(i))
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map_err(err)?,
+            ),
+            ".func" => match args {
+                [_, argc, localc] => program.functions.push(Function {
+                    argc: number(argc).map_err(err)?,
+                    localc: number(localc).map_err(err)?,
+                    code: Vec::new(),
+                    name: None,
+                    positions: Vec::new(),
+                }),
+                _ => {
+                    return Err(err(String::from(
+                        "Expected `.func <label> <argc> <locals>`",
+                    )))
+                }
+            },
+            ".name" => match (args, program.functions.last_mut()) {
+                ([name], Some(f)) => f.name = Some(unescape(name).map_err(err)?),
+                (_, None) => return Err(err(String::from("`.name` outside of function"))),
+                _ => return Err(err(String::from("Expected `.name <string>`"))),
+            },
+            ".pos" => match args {
+                [file, line, col] => pending_positions.push((
+                    unescape(file).map_err(err)?,
+                    number(line).map_err(err)?,
+                    number(col).map_err(err)?,
+                )),
+                _ => return Err(err(String::from("Expected `.pos <file> <line> <column>`"))),
+            },
+            directive if directive.starts_with(".") => {
+                return Err(err(format!("Unknown directive `{}`", directive)))
+            }
+            _ => {
+                let bc = instruction(&tokens.concat(), labels).map_err(err)?;
+                let f = match program.functions.last_mut() {
+                    Some(f) => f,
+                    None => return Err(err(String::from("Instruction outside of function"))),
+                };
+                for (file, line, col) in pending_positions.drain(..) {
+                    f.positions.push((f.code.len() as u32, file, line, col));
+                }
+                f.code.push(bc);
+            }
+        }
+    }
+
+    if program.start.is_none() {
+        return Err(error(
+            0,
+            String::from("Start function is not defined, use `.start <label>`"),
+        ));
+    }
+
+    return Ok(program);
+}
+
+fn error(line: usize, msg: String) -> AsmError {
+    AsmError { line, msg }
+}
+
+fn is_label(s: &str) -> bool {
+    s.chars()
+        .next()
+        .map(|c| !c.is_ascii_digit() && c != '"' && c != '.')
+        .unwrap_or(false)
+        && !s.contains(['(', ')', ','])
+}
+
+// Splits line into whitespace separated tokens, string literals are kept whole
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<&str>, AsmError> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() && !rest.starts_with(";") {
+        let end = if rest.starts_with("\"") {
+            let mut escaped = false;
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let found = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    found
+                })
+                .map(|(i, _)| i + 1);
+            match end {
+                Some(end) => end,
+                None => return Err(error(line_number, String::from("Unterminated string"))),
+            }
+        } else {
+            rest.find(|c: char| c.is_whitespace() || c == ';')
+                .unwrap_or(rest.len())
+        };
+
+        tokens.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    return Ok(tokens);
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let inner = s
+        .strip_prefix("\"")
+        .and_then(|s| s.strip_suffix("\""))
+        .ok_or_else(|| format!("`{}` is not a string", s))?;
+
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('u') => {
+                let code: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let c = code
+                    .strip_prefix("{")
+                    .and_then(|code| u32::from_str_radix(code, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid escape `\\u{}}}`", code))?;
+                unescaped.push(c);
+            }
+            c => return Err(format!("Invalid escape `\\{}`", c.unwrap_or(' '))),
+        }
+    }
+
+    return Ok(unescaped);
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse()
+        .map_err(|_| format!("`{}` is not a valid number", s))
+}
+
+fn parse_type(s: &str) -> Result<Type, String> {
+    match s {
+        "Int" => Ok(Type::Int),
+        "String" => Ok(Type::String),
+        _ => Err(format!("Unknown type `{}`", s)),
+    }
+}
+
+// Function is referenced by its label or index
+fn function(s: &str, labels: &HashMap<&str, u32>) -> Result<u32, String> {
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        return number(s);
+    }
+
+    return labels
+        .get(s)
+        .copied()
+        .ok_or_else(|| format!("Label `{}` is not defined", s));
+}
+
+fn instruction(s: &str, labels: &HashMap<&str, u32>) -> Result<Bytecode, String> {
+    let (name, args) = match s.find("(") {
+        Some(start) => {
+            let args = s[start + 1..]
+                .strip_suffix(")")
+                .ok_or_else(|| format!("Missing `)` in `{}`", s))?;
+            (&s[..start], args.split(",").collect::<Vec<_>>())
+        }
+        None => (s, Vec::new()),
+    };
+
+    let bc = match (name, args.as_slice()) {
+        ("LocalGet", [b]) => Bytecode::LocalGet(number(b)?),
+        ("LocalSet", [b]) => Bytecode::LocalSet(number(b)?),
+        ("ConstantGet", [b]) => Bytecode::ConstantGet(number(b)?),
+        ("Add", []) => Bytecode::Add,
+        ("Sub", []) => Bytecode::Sub,
+        ("Mul", []) => Bytecode::Mul,
+        ("Div", []) => Bytecode::Div,
+        ("Eq", []) => Bytecode::Eq,
+        ("Lt", []) => Bytecode::Lt,
+        ("Gt", []) => Bytecode::Gt,
+        ("Lte", []) => Bytecode::Lte,
+        ("Gte", []) => Bytecode::Gte,
+        ("If", []) => Bytecode::If,
+        ("Else", []) => Bytecode::Else,
+        ("End", []) => Bytecode::End,
+        ("Call", [f]) => Bytecode::Call(function(f, labels)?),
+        ("CallRet", [f]) => Bytecode::CallRet(function(f, labels)?),
+        ("Ret", []) => Bytecode::Ret,
+        ("Remove", []) => Bytecode::Remove,
+        ("ConstantNil", []) => Bytecode::ConstantNil,
+        ("CallLambda", [b]) => Bytecode::CallLambda(number(b)?),
+        ("Nop", []) => Bytecode::Nop,
+        ("Atom", [dw]) => Bytecode::Atom(number(dw)?),
+        ("Print", []) => Bytecode::Print,
+        ("NewPair", []) => Bytecode::NewPair,
+        ("NewArray", [dw]) => Bytecode::NewArray(number(dw)?),
+        ("NewLambda", [f, b, o]) => {
+            Bytecode::NewLambda(function(f, labels)?, number(b)?, number(o)?)
+        }
+        ("NewArrayDyn", []) => Bytecode::NewArrayDyn,
+        ("PairLeft", []) => Bytecode::PairLeft,
+        ("PairRight", []) => Bytecode::PairRight,
+        ("ArrayGet", []) => Bytecode::ArrayGet,
+        ("LocalArrayGet", [l, dw]) => Bytecode::LocalArrayGet(number(l)?, number(dw)?),
+        ("ArraySet", []) => Bytecode::ArraySet,
+        ("ArrayLen", []) => Bytecode::ArrayLen,
+        ("StringLen", []) => Bytecode::StringLen,
+        ("AssertEq", []) => Bytecode::AssertEq,
+        ("IfEq", []) => Bytecode::IfEq,
+        ("IfLt", []) => Bytecode::IfLt,
+        ("IfGt", []) => Bytecode::IfGt,
+        ("IfLte", []) => Bytecode::IfLte,
+        ("IfGte", []) => Bytecode::IfGte,
+        ("Import", [dw]) => Bytecode::Import(number(dw)?),
+        ("CallDynamic", [dw]) => Bytecode::CallDynamic(number(dw)?),
+        ("StringConcat", []) => Bytecode::StringConcat,
+        ("Substring", []) => Bytecode::Substring,
+        ("CharAt", []) => Bytecode::CharAt,
+        ("CharToString", []) => Bytecode::CharToString,
+        ("StringToInt", []) => Bytecode::StringToInt,
+        ("IntToString", []) => Bytecode::IntToString,
+        ("ToString", []) => Bytecode::ToString,
+        ("StringSplit", []) => Bytecode::StringSplit,
+        ("StringJoin", []) => Bytecode::StringJoin,
+        ("StringIndexOf", []) => Bytecode::StringIndexOf,
+        ("StringUpper", []) => Bytecode::StringUpper,
+        ("StringLower", []) => Bytecode::StringLower,
+        ("StringCompare", []) => Bytecode::StringCompare,
+        ("ReadFile", []) => Bytecode::ReadFile,
+        ("WriteFile", []) => Bytecode::WriteFile,
+        ("AppendFile", []) => Bytecode::AppendFile,
+        ("ReadLine", []) => Bytecode::ReadLine,
+        ("ReadAllStdin", []) => Bytecode::ReadAllStdin,
+        ("FileExists", []) => Bytecode::FileExists,
+        ("ProgramArgs", []) => Bytecode::ProgramArgs,
+        ("Env", []) => Bytecode::Env,
+        ("Exit", []) => Bytecode::Exit,
+        ("Throw", []) => Bytecode::Throw,
+        ("Try", []) => Bytecode::Try,
+        ("Catch", []) => Bytecode::Catch,
+        _ => return Err(format!("Unknown instruction `{}`", s)),
+    };
+
+    return Ok(bc);
+}
+
+fn encode(program: Program) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+
+    bytes.extend([b'S', b'P', b'S', b'L']);
+    bytes.extend(program.start.unwrap().to_le_bytes());
+
+    bytes.extend((program.constants.len() as u32).to_le_bytes());
+    for con in &program.constants {
+        match con {
+            Constant::String(s) => {
+                bytes.push(Type::String as u8);
+                bytes.extend(s.as_bytes());
+                bytes.push(0); // ending null character
+            }
+            Constant::Int(dw) => {
+                bytes.push(Type::Int as u8);
+                bytes.extend(dw.to_le_bytes());
+            }
+        }
+    }
+
+    bytes.extend((program.functions.len() as u32).to_le_bytes());
+    for f in &program.functions {
+        let mut code = Vec::new();
+        f.code.iter().for_each(|bc| bc.encode(&mut code));
+
+        bytes.push(f.argc);
+        bytes.push(f.localc);
+        bytes.extend((code.len() as u32).to_le_bytes());
+        bytes.extend(code);
+    }
+
+    bytes.extend((program.signatures.len() as u32).to_le_bytes());
+    for (name, args, ret) in &program.signatures {
+        bytes.extend(name.as_bytes());
+        bytes.push(0); // ending null character
+
+        bytes.push(args.len() as u8);
+        args.iter().for_each(|&t| bytes.push(t as u8));
+        bytes.push(*ret as u8);
+    }
+
+    bytes.extend((program.groups.len() as u32).to_le_bytes());
+    for group in &program.groups {
+        bytes.extend((group.len() as u32).to_le_bytes());
+        group.iter().for_each(|i| bytes.extend(i.to_le_bytes()));
+    }
+
+    // debug section is written only if there is something to put in it
+    if program
+        .functions
+        .iter()
+        .any(|f| f.name.is_some() || !f.positions.is_empty())
+    {
+        debug(&mut bytes, &program.functions);
+    }
+
+    return bytes;
+}
+
+fn debug(bytes: &mut Vec<u8>, functions: &[Function]) {
+    let mut files: Vec<&str> = Vec::new();
+    functions
+        .iter()
+        .flat_map(|f| f.positions.iter())
+        .for_each(|(_, file, _, _)| {
+            if !files.contains(&file.as_str()) {
+                files.push(file);
+            }
+        });
+
+    bytes.extend([b'D', b'B', b'U', b'G']);
+
+    bytes.extend((files.len() as u32).to_le_bytes());
+    for file in &files {
+        bytes.extend(file.as_bytes());
+        bytes.push(0); // ending null character
+    }
+
+    bytes.extend((functions.len() as u32).to_le_bytes());
+    for f in functions {
+        bytes.extend(f.name.as_deref().unwrap_or("").as_bytes());
+        bytes.push(0); // ending null character
+
+        bytes.extend((f.positions.len() as u32).to_le_bytes());
+        for (index, file, line, col) in &f.positions {
+            bytes.extend(index.to_le_bytes());
+            bytes.extend((files.iter().position(|f| f == file).unwrap() as u32).to_le_bytes());
+            bytes.extend(line.to_le_bytes());
+            bytes.extend(col.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_program() {
+        let bytes = assemble(
+            r#"
+            ; prints greeting
+            .start main
+            .const str "hi\n"
+            .func main 0 0
+                ConstantGet(0)
+                Print
+                CallRet(main)
+                Ret
+            "#,
+        )
+        .unwrap();
+
+        let mut expected = b"SPSL".to_vec();
+        expected.extend(0u32.to_le_bytes()); // start
+        expected.extend(1u32.to_le_bytes()); // constants
+        expected.extend([1, b'h', b'i', b'\n', 0]);
+        expected.extend(1u32.to_le_bytes()); // functions
+        expected.extend([0, 0]);
+        expected.extend(9u32.to_le_bytes());
+        expected.extend([2, 0, 24, 16, 0, 0, 0, 0, 17]);
+        expected.extend(0u32.to_le_bytes()); // signatures
+        expected.extend(0u32.to_le_bytes()); // groups
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn escapes() {
+        let s = "say \"hi\"\\\n\t\u{1}";
+        assert_eq!(unescape(&escape(s)).unwrap(), s);
+    }
+
+    #[test]
+    fn undefined_label() {
+        let err = assemble(".start main\n.func main 0 0\n  Call(other)").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+}
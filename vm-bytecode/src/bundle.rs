@@ -0,0 +1,45 @@
+/// Program bundled into an executable is appended after the runtime and followed by a trailer:
+/// flags (1 byte), length of the program (u64) and the magic bytes.
+pub const MAGIC: [u8; 8] = *b"OTBUNDLE";
+pub const TRAILER_LEN: usize = 1 + 8 + MAGIC.len();
+
+const FLAG_JIT: u8 = 1;
+
+/// Appends program to the runtime executable
+pub fn append(runtime: &[u8], program: &[u8], jit: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(runtime.len() + program.len() + TRAILER_LEN);
+
+    bytes.extend(runtime);
+    bytes.extend(program);
+    bytes.push(if jit { FLAG_JIT } else { 0 });
+    bytes.extend((program.len() as u64).to_le_bytes());
+    bytes.extend(MAGIC);
+
+    return bytes;
+}
+
+/// Reads trailer from the end of the executable, returns length of the program and whether JIT is enabled
+pub fn trailer(bytes: &[u8]) -> Option<(u64, bool)> {
+    if bytes.len() < TRAILER_LEN || bytes[bytes.len() - MAGIC.len()..] != MAGIC {
+        return None;
+    }
+
+    let trailer = &bytes[bytes.len() - TRAILER_LEN..];
+    let mut len = [0; 8];
+    len.copy_from_slice(&trailer[1..9]);
+
+    return Some((u64::from_le_bytes(len), trailer[0] & FLAG_JIT != 0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundle_trailer() {
+        let bytes = append(b"runtime", b"SPSL", true);
+
+        assert_eq!(trailer(&bytes), Some((4, true)));
+        assert_eq!(trailer(b"runtime"), None);
+    }
+}
@@ -29,15 +29,15 @@ pub enum Bytecode {
 
     ConstantNil,
 
-    CallLambda,
+    CallLambda(u8), // number of arguments to take from stack
 
     Nop,
     Atom(u32),
     Print,
 
     NewPair,
-    NewArray(u32),      // size of the array / number for items to pop
-    NewLambda(u32, u8), // index of function, number of context values to take from stack
+    NewArray(u32),          // size of the array / number for items to pop
+    NewLambda(u32, u8, u8), // index of first function, number of context values to take from stack, number of arity overloads
     NewArrayDyn,
 
     PairLeft,
@@ -47,6 +47,7 @@ pub enum Bytecode {
     ArraySet,
     ArrayLen,
     StringLen,
+    AssertEq,
 
     IfEq,
     IfLt,
@@ -56,6 +57,37 @@ pub enum Bytecode {
 
     Import(u32),      // index of signatures
     CallDynamic(u32), // index of signature
+
+    StringConcat,
+    Substring,
+    CharAt,
+    CharToString,
+    StringToInt,
+    IntToString,
+    ToString,
+    StringSplit,
+    StringJoin,
+    StringIndexOf,
+    StringUpper,
+    StringLower,
+    StringCompare,
+
+    ReadFile,
+    WriteFile,
+    AppendFile,
+    ReadLine,
+    ReadAllStdin,
+    FileExists,
+
+    ProgramArgs,
+    Env,
+    Exit,
+
+    // `Try` installs a handler jumping after the matching `Catch`, which removes it and skips
+    // the handler up to the matching `End` when the body doesn't throw
+    Throw,
+    Try,
+    Catch,
 }
 
 impl Bytecode {
@@ -87,7 +119,7 @@ impl Bytecode {
             Bytecode::Ret => bytes.push(17),
             Bytecode::Remove => bytes.push(18),
             Bytecode::ConstantNil => bytes.push(19),
-            Bytecode::CallLambda => bytes.push(20),
+            Bytecode::CallLambda(b) => bytes.extend([20, b]),
 
             Bytecode::Nop => bytes.push(22),
             Bytecode::Atom(dw) => {
@@ -100,10 +132,11 @@ impl Bytecode {
                 bytes.push(26);
                 bytes.extend(dw.to_le_bytes())
             }
-            Bytecode::NewLambda(dw, b) => {
+            Bytecode::NewLambda(dw, b, o) => {
                 bytes.push(27);
                 bytes.extend(dw.to_le_bytes());
-                bytes.push(b)
+                bytes.push(b);
+                bytes.push(o)
             }
             Bytecode::NewArrayDyn => bytes.push(28),
 
@@ -118,6 +151,7 @@ impl Bytecode {
             Bytecode::ArraySet => bytes.push(34),
             Bytecode::ArrayLen => bytes.push(35),
             Bytecode::StringLen => bytes.push(36),
+            Bytecode::AssertEq => bytes.push(37),
 
             Bytecode::IfEq => bytes.push(40),
             Bytecode::IfLt => bytes.push(41),
@@ -133,6 +167,35 @@ impl Bytecode {
                 bytes.push(51);
                 bytes.extend(dw.to_le_bytes())
             }
+
+            Bytecode::StringConcat => bytes.push(52),
+            Bytecode::Substring => bytes.push(53),
+            Bytecode::CharAt => bytes.push(54),
+            Bytecode::StringToInt => bytes.push(55),
+            Bytecode::IntToString => bytes.push(56),
+            Bytecode::ToString => bytes.push(57),
+            Bytecode::StringSplit => bytes.push(58),
+            Bytecode::StringJoin => bytes.push(59),
+            Bytecode::StringIndexOf => bytes.push(60),
+            Bytecode::StringUpper => bytes.push(61),
+            Bytecode::StringLower => bytes.push(62),
+            Bytecode::StringCompare => bytes.push(63),
+            Bytecode::CharToString => bytes.push(64),
+
+            Bytecode::ReadFile => bytes.push(65),
+            Bytecode::WriteFile => bytes.push(66),
+            Bytecode::AppendFile => bytes.push(67),
+            Bytecode::ReadLine => bytes.push(68),
+            Bytecode::ReadAllStdin => bytes.push(69),
+            Bytecode::FileExists => bytes.push(70),
+
+            Bytecode::ProgramArgs => bytes.push(71),
+            Bytecode::Env => bytes.push(72),
+            Bytecode::Exit => bytes.push(73),
+
+            Bytecode::Throw => bytes.push(74),
+            Bytecode::Try => bytes.push(75),
+            Bytecode::Catch => bytes.push(76),
         }
     }
 
@@ -189,7 +252,10 @@ impl Bytecode {
                 17 => Bytecode::Ret,
                 18 => Bytecode::Remove,
                 19 => Bytecode::ConstantNil,
-                20 => Bytecode::CallLambda,
+                20 => {
+                    i += 1;
+                    Bytecode::CallLambda(bytes[i])
+                }
                 22 => Bytecode::Nop,
                 23 => {
                     let dw = u32::from_le_bytes([
@@ -221,8 +287,9 @@ impl Bytecode {
                         bytes[i + 4],
                     ]);
                     let b = bytes[i + 5];
-                    i += 5;
-                    Bytecode::NewLambda(dw, b)
+                    let o = bytes[i + 6];
+                    i += 6;
+                    Bytecode::NewLambda(dw, b, o)
                 }
                 28 => Bytecode::NewArrayDyn,
 
@@ -243,6 +310,7 @@ impl Bytecode {
                 34 => Bytecode::ArraySet,
                 35 => Bytecode::ArrayLen,
                 36 => Bytecode::StringLen,
+                37 => Bytecode::AssertEq,
 
                 40 => Bytecode::IfEq,
                 41 => Bytecode::IfLt,
@@ -270,6 +338,35 @@ impl Bytecode {
                     i += 4;
                     Bytecode::CallDynamic(dw)
                 }
+
+                52 => Bytecode::StringConcat,
+                53 => Bytecode::Substring,
+                54 => Bytecode::CharAt,
+                55 => Bytecode::StringToInt,
+                56 => Bytecode::IntToString,
+                57 => Bytecode::ToString,
+                58 => Bytecode::StringSplit,
+                59 => Bytecode::StringJoin,
+                60 => Bytecode::StringIndexOf,
+                61 => Bytecode::StringUpper,
+                62 => Bytecode::StringLower,
+                63 => Bytecode::StringCompare,
+                64 => Bytecode::CharToString,
+
+                65 => Bytecode::ReadFile,
+                66 => Bytecode::WriteFile,
+                67 => Bytecode::AppendFile,
+                68 => Bytecode::ReadLine,
+                69 => Bytecode::ReadAllStdin,
+                70 => Bytecode::FileExists,
+
+                71 => Bytecode::ProgramArgs,
+                72 => Bytecode::Env,
+                73 => Bytecode::Exit,
+
+                74 => Bytecode::Throw,
+                75 => Bytecode::Try,
+                76 => Bytecode::Catch,
                 _ => unreachable!(),
             };
             vec.push(bytecode);
@@ -1,3 +1,18 @@
+pub mod asm;
+pub mod bundle;
 mod bytecode;
 
 pub use bytecode::Bytecode;
+
+/// Atoms `:ok` and `:err` have the same number in every program, builtins returning results
+/// tag them with these atoms
+pub const ATOM_OK: u32 = 0;
+pub const ATOM_ERR: u32 = 1;
+
+/// Kinds of errors raised by the VM, caught errors are `(pair kind message)`
+pub const ATOM_TYPE_ERROR: u32 = 2;
+pub const ATOM_ARITHMETIC_ERROR: u32 = 3;
+pub const ATOM_INDEX_ERROR: u32 = 4;
+pub const ATOM_ARITY_ERROR: u32 = 5;
+pub const ATOM_ASSERTION_ERROR: u32 = 6;
+pub const ATOM_STACK_OVERFLOW: u32 = 7;
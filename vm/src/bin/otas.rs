@@ -0,0 +1,30 @@
+use std::env::args;
+
+use vm_bytecode::asm::assemble;
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let (path, out) = match args.as_slice() {
+        [path] => (path, String::from(".out")),
+        [path, flag, out] if flag == "--out" => (path, out.clone()),
+        _ => {
+            println!("Usage: otas <assembly file> [--out <bytecode file>]");
+            std::process::exit(1);
+        }
+    };
+
+    let src = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        println!("Can't read `{}`: {}", path, e);
+        std::process::exit(1);
+    });
+
+    let bytes = assemble(&src).unwrap_or_else(|e| {
+        println!("{}: {}", path, e);
+        std::process::exit(1);
+    });
+
+    std::fs::write(&out, bytes).unwrap_or_else(|e| {
+        println!("Can't write `{}`: {}", out, e);
+        std::process::exit(1);
+    });
+}
@@ -0,0 +1,310 @@
+use std::env::args;
+
+use vm::{deserialize, section_sizes, DebugInfo, IsIf, Type, Value};
+use vm_bytecode::{
+    asm::{escape, format_instruction},
+    Bytecode,
+};
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let asm = args.iter().any(|arg| arg == "--asm");
+    let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path.clone(),
+        None => {
+            println!("Usage: otdis [--asm] <bytecode file>");
+            std::process::exit(1);
+        }
+    };
+
+    let program = std::fs::read(&path).unwrap_or_else(|e| {
+        println!("Can't read `{}`: {}", path, e);
+        std::process::exit(1);
+    });
+
+    if !program.starts_with(b"SPSL") {
+        println!("`{}` is not OT bytecode, `SPSL` header is missing", path);
+        std::process::exit(1);
+    }
+
+    let sizes = section_sizes(&program).unwrap();
+    let (start, constants, functions, signatures, groups, debug) = deserialize(&program).unwrap();
+
+    if asm {
+        return assembly(
+            &path,
+            start,
+            &constants,
+            &functions,
+            &signatures,
+            &groups,
+            &debug,
+        );
+    }
+
+    let name = |index: u32| function_name(&debug, index);
+
+    println!("{}: {} bytes", path, program.len());
+    println!("start function: {}", name(start));
+
+    println!("\nsections:");
+    for (section, size) in sizes {
+        println!("  {:<12}{:>8} bytes", section, size);
+    }
+
+    println!("\nconstants ({}):", constants.len());
+    for (index, constant) in constants.iter().enumerate() {
+        println!("  {:<4}{}", index, describe_constant(constant));
+    }
+
+    println!("\nfunctions ({}):", functions.len());
+    for (index, (argc, localc, code)) in functions.iter().enumerate() {
+        let mut bytes = Vec::new();
+        code.iter().for_each(|bc| bc.encode(&mut bytes));
+
+        println!(
+            "\n  {}/{}, {} locals, {} bytes",
+            name(index as u32),
+            argc,
+            localc,
+            bytes.len()
+        );
+
+        let jumps = jump_targets(code);
+
+        let mut depth = 0;
+        for (pc, bc) in code.iter().enumerate() {
+            // Else closes the branch opened by If and opens another one
+            if let Bytecode::Else | Bytecode::End = bc {
+                depth = usize::saturating_sub(depth, 1);
+            }
+
+            let mut annotation = match *bc {
+                Bytecode::ConstantGet(i) => constants
+                    .get(i as usize)
+                    .map(describe_constant)
+                    .unwrap_or_else(|| String::from("unknown constant")),
+                Bytecode::Call(i) | Bytecode::CallRet(i) | Bytecode::NewLambda(i, _, _) => name(i),
+                Bytecode::CallDynamic(i) => signatures
+                    .get(i as usize)
+                    .map(|(name, _, _)| format!("!{}", name))
+                    .unwrap_or_else(|| String::from("unknown signature")),
+                Bytecode::Import(i) => groups
+                    .get(i as usize)
+                    .map(|group| {
+                        group
+                            .iter()
+                            .map(|&sig| signatures[sig as usize].0.clone())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .unwrap_or_else(|| String::from("unknown group")),
+                Bytecode::LocalArrayGet(0, i) => format!("context value {}", i),
+                _ => String::new(),
+            };
+
+            if let Some(target) = jumps.get(pc).copied().flatten() {
+                if !annotation.is_empty() {
+                    annotation += ", ";
+                }
+                let branch = if bc.is_if() { "else" } else { "end" };
+                annotation += &format!("{} at {}", branch, target);
+            }
+
+            if let Some(start) = debug.as_ref().and_then(|d| position_start(d, index, pc)) {
+                if !annotation.is_empty() {
+                    annotation += ", ";
+                }
+                annotation += &start;
+            }
+
+            let instruction = format!("{}{:?}", "  ".repeat(depth), bc);
+            if annotation.is_empty() {
+                println!("    {:<5}{}", pc, instruction);
+            } else {
+                println!("    {:<5}{:<32}; {}", pc, instruction, annotation);
+            }
+
+            if bc.is_if() || matches!(bc, Bytecode::Else) {
+                depth += 1;
+            }
+        }
+    }
+
+    println!("\nsignatures ({}):", signatures.len());
+    for (index, (name, args, ret)) in signatures.iter().enumerate() {
+        println!("  {:<4}{} {:?} -> {:?}", index, name, args, ret);
+    }
+
+    println!("\ngroups ({}):", groups.len());
+    for (index, group) in groups.iter().enumerate() {
+        println!("  {:<4}{:?}", index, group);
+    }
+}
+
+// Prints the program in the format accepted by `otas`
+fn assembly(
+    path: &str,
+    start: u32,
+    constants: &[Value],
+    functions: &[(u8, u8, Vec<Bytecode>)],
+    signatures: &[(String, Vec<Type>, Type)],
+    groups: &[Vec<u32>],
+    debug: &Option<DebugInfo>,
+) {
+    let labels = labels(functions, debug);
+    let label = |index: u32| {
+        labels
+            .get(index as usize)
+            .cloned()
+            .unwrap_or_else(|| index.to_string())
+    };
+
+    println!("; disassembled from {}", path);
+    println!(".start {}", label(start));
+
+    if !constants.is_empty() {
+        println!();
+    }
+    for constant in constants {
+        match constant {
+            Value::String(s) => println!(".const str {}", escape(&s.to_string_lossy())),
+            Value::Int(i) => println!(".const int {}", i),
+            value => println!("; unsupported constant {:?}", value),
+        }
+    }
+
+    if !signatures.is_empty() {
+        println!();
+    }
+    for (name, args, ret) in signatures {
+        let args: String = args.iter().map(|t| format!(" {:?}", t)).collect();
+        println!(".sig {}{} -> {:?}", name, args, ret);
+    }
+    for group in groups {
+        let group: String = group.iter().map(|i| format!(" {}", i)).collect();
+        println!(".group{}", group);
+    }
+
+    for (index, (argc, localc, code)) in functions.iter().enumerate() {
+        println!("\n.func {} {} {}", label(index as u32), argc, localc);
+
+        let f = debug.as_ref().and_then(|d| d.functions.get(index));
+        if let Some(f) = f {
+            println!(".name {}", escape(&f.name));
+        }
+
+        let mut depth = 0;
+        for (pc, bc) in code.iter().enumerate() {
+            if let Bytecode::Else | Bytecode::End = bc {
+                depth = usize::saturating_sub(depth, 1);
+            }
+
+            if let Some(f) = f {
+                f.positions
+                    .iter()
+                    .filter(|(i, _, _, _)| *i as usize == pc)
+                    .for_each(|&(_, file, line, col)| {
+                        let file = &debug.as_ref().unwrap().files[file as usize];
+                        println!(".pos {} {} {}", escape(file), line, col);
+                    });
+            }
+
+            println!(
+                "    {}{}",
+                "  ".repeat(depth),
+                format_instruction(*bc, label)
+            );
+
+            if bc.is_if() || matches!(bc, Bytecode::Else) {
+                depth += 1;
+            }
+        }
+    }
+}
+
+// Unique labels for functions, based on their names if debug info is present
+fn labels(functions: &[(u8, u8, Vec<Bytecode>)], debug: &Option<DebugInfo>) -> Vec<String> {
+    let names: Vec<Option<&str>> = (0..functions.len())
+        .map(|i| debug.as_ref().and_then(|d| d.function_name(i as u32)))
+        .map(|name| name.filter(|name| is_label(name)))
+        .collect();
+
+    let mut labels: Vec<String> = Vec::with_capacity(functions.len());
+    for (index, name) in names.iter().enumerate() {
+        let label = match name {
+            Some(name) if names.iter().filter(|n| *n == &Some(*name)).count() == 1 => {
+                name.to_string()
+            }
+            Some(name) => format!("{}/{}", name, functions[index].0),
+            None => format!("f{}", index),
+        };
+
+        // overloads with the same arity or clash with generated label
+        if labels.contains(&label) {
+            labels.push(format!("{}#{}", label, index));
+        } else {
+            labels.push(label);
+        }
+    }
+
+    return labels;
+}
+
+fn is_label(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '"')
+        && !name.contains(|c: char| c.is_whitespace() || "(),;".contains(c))
+}
+
+// For every If the index of its Else and for every Else the index of its End
+fn jump_targets(code: &[Bytecode]) -> Vec<Option<usize>> {
+    let mut targets = vec![None; code.len()];
+    let mut open = Vec::new();
+
+    for (pc, bc) in code.iter().enumerate() {
+        match bc {
+            bc if bc.is_if() => open.push(pc),
+            Bytecode::Else => {
+                if let Some(start) = open.pop() {
+                    targets[start] = Some(pc);
+                }
+                open.push(pc);
+            }
+            Bytecode::End => {
+                if let Some(start) = open.pop() {
+                    targets[start] = Some(pc);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    return targets;
+}
+
+fn function_name(debug: &Option<DebugInfo>, index: u32) -> String {
+    match debug.as_ref().and_then(|d| d.function_name(index)) {
+        Some(name) => format!("#{} {}", index, name),
+        None => format!("#{}", index),
+    }
+}
+
+fn describe_constant(constant: &Value) -> String {
+    match constant {
+        Value::String(s) => format!("str {:?}", s.to_string_lossy()),
+        Value::Int(i) => format!("int {}", i),
+        value => format!("{:?}", value),
+    }
+}
+
+// Source position, if instruction is the first one compiled from it
+fn position_start(debug: &DebugInfo, function: usize, pc: usize) -> Option<String> {
+    debug
+        .functions
+        .get(function)?
+        .positions
+        .iter()
+        .find(|(index, _, _, _)| *index as usize == pc)
+        .map(|&(_, file, line, col)| format!("{}:{}:{}", debug.files[file as usize], line, col))
+}
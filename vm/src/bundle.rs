@@ -0,0 +1,31 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use vm_bytecode::bundle::{trailer, TRAILER_LEN};
+
+/// Program appended to the running executable by `otc --bundle`, with flag whether to use JIT
+pub fn embedded_program() -> Option<(Vec<u8>, bool)> {
+    let mut exe = File::open(std::env::current_exe().ok()?).ok()?;
+
+    let mut end = [0; TRAILER_LEN];
+    let size = exe.seek(SeekFrom::End(0)).ok()?;
+    if size < TRAILER_LEN as u64 {
+        return None;
+    }
+    exe.seek(SeekFrom::End(-(TRAILER_LEN as i64))).ok()?;
+    exe.read_exact(&mut end).ok()?;
+
+    let (len, jit) = trailer(&end)?;
+    if len > size - TRAILER_LEN as u64 {
+        return None;
+    }
+
+    let mut program = vec![0; len as usize];
+    exe.seek(SeekFrom::Start(size - TRAILER_LEN as u64 - len))
+        .ok()?;
+    exe.read_exact(&mut program).ok()?;
+
+    return Some((program, jit));
+}
@@ -0,0 +1,32 @@
+/// Source positions of functions, loaded from the optional debug section
+#[derive(Debug, Default)]
+pub struct DebugInfo {
+    pub files: Vec<String>,
+    pub functions: Vec<FunctionDebug>,
+}
+
+#[derive(Debug)]
+pub struct FunctionDebug {
+    pub name: String,
+    // instruction index, file index, line, column
+    pub positions: Vec<(u32, u32, u32, u32)>,
+}
+
+impl DebugInfo {
+    pub fn function_name(&self, function: u32) -> Option<&str> {
+        self.functions
+            .get(function as usize)
+            .map(|f| f.name.as_str())
+    }
+
+    /// Finds source position of instruction, returns file, line and column
+    pub fn locate(&self, function: u32, pc: usize) -> Option<(&str, u32, u32)> {
+        let f = self.functions.get(function as usize)?;
+
+        f.positions
+            .iter()
+            .take_while(|(index, _, _, _)| *index as usize <= pc)
+            .last()
+            .map(|&(_, file, line, col)| (self.files[file as usize].as_str(), line, col))
+    }
+}
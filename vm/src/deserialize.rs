@@ -2,6 +2,7 @@ use std::ffi::CString;
 
 use vm_bytecode::Bytecode;
 
+use super::debug::{DebugInfo, FunctionDebug};
 use super::value::Value;
 
 pub fn deserialize(
@@ -13,6 +14,7 @@ pub fn deserialize(
         Vec<(u8, u8, Vec<Bytecode>)>,
         Vec<(String, Vec<Type>, Type)>,
         Vec<Vec<u32>>,
+        Option<DebugInfo>,
     ),
     &'static str,
 > {
@@ -34,9 +36,95 @@ pub fn deserialize(
     let (b, signatures) = decode_signatures(bytes);
     let bytes = b;
 
-    let (_, groups) = decode_groups(bytes);
+    let (b, groups) = decode_groups(bytes);
+    let bytes = b;
+
+    let debug = decode_debug(bytes);
 
-    Ok((start_index, constants, functions, signatures, groups))
+    Ok((start_index, constants, functions, signatures, groups, debug))
+}
+
+/// Sizes of sections in bytes, in the order they are stored
+pub fn section_sizes(bytes: &[u8]) -> Result<Vec<(&'static str, usize)>, &'static str> {
+    let total = bytes.len();
+
+    let (b, bool) = is_magic(bytes);
+    if !bool {
+        return Err("Magic bytes are missing");
+    }
+    let mut sizes = vec![("header", total - b.len())];
+    let bytes = b;
+
+    let (b, _) = decode_u32(bytes);
+    sizes.push(("start", bytes.len() - b.len()));
+    let bytes = b;
+
+    let (b, _) = decode_constans(bytes);
+    sizes.push(("constants", bytes.len() - b.len()));
+    let bytes = b;
+
+    let (b, _) = decode_functions(bytes);
+    sizes.push(("functions", bytes.len() - b.len()));
+    let bytes = b;
+
+    let (b, _) = decode_signatures(bytes);
+    sizes.push(("signatures", bytes.len() - b.len()));
+    let bytes = b;
+
+    let (b, _) = decode_groups(bytes);
+    sizes.push(("groups", bytes.len() - b.len()));
+
+    if !b.is_empty() {
+        sizes.push(("debug", b.len()));
+    }
+
+    Ok(sizes)
+}
+
+fn decode_debug(bytes: &[u8]) -> Option<DebugInfo> {
+    if !bytes.starts_with(b"DBUG") {
+        return None;
+    }
+    let bytes = &bytes[4..];
+
+    let (b, filec) = decode_u32(bytes);
+    let mut bytes = b;
+
+    let files = (0..filec)
+        .map(|_| {
+            let (b, file) = decode_string(bytes);
+            bytes = b;
+            file
+        })
+        .collect();
+
+    let (b, funcc) = decode_u32(bytes);
+    bytes = b;
+
+    let mut functions = Vec::with_capacity(funcc as usize);
+
+    for _ in 0..funcc {
+        let (b, name) = decode_string(bytes);
+        bytes = b;
+
+        let (b, posc) = decode_u32(bytes);
+        bytes = b;
+
+        let positions = (0..posc)
+            .map(|_| {
+                let (b, index) = decode_u32(bytes);
+                let (b, file) = decode_u32(b);
+                let (b, line) = decode_u32(b);
+                let (b, col) = decode_u32(b);
+                bytes = b;
+                (index, file, line, col)
+            })
+            .collect();
+
+        functions.push(FunctionDebug { name, positions });
+    }
+
+    Some(DebugInfo { files, functions })
 }
 
 fn decode_groups(bytes: &[u8]) -> (&[u8], Vec<Vec<u32>>) {
@@ -156,6 +244,10 @@ fn decode_constans(bytes: &[u8]) -> (&[u8], Vec<Value>) {
 }
 
 fn is_magic(bytes: &[u8]) -> (&[u8], bool) {
+    if bytes.len() < 4 {
+        return (bytes, false);
+    }
+
     (
         &bytes[4..],
         bytes[0] == 'S' as u8
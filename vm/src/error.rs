@@ -1,9 +1,92 @@
-#[macro_export]
-macro_rules! vm_runtime_error {
-    ($($t: tt)+) => {
-        {
-            println!("\x1b[31m\x1b[1mVM ERROR:\x1b[0m {}", format!($($t)+));
-            std::process::exit(0);
+use vm_bytecode::{
+    ATOM_ARITHMETIC_ERROR, ATOM_ARITY_ERROR, ATOM_ASSERTION_ERROR, ATOM_INDEX_ERROR,
+    ATOM_STACK_OVERFLOW, ATOM_TYPE_ERROR,
+};
+
+/// What went wrong, programs can tell errors apart by it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    /// Value of a wrong type was given to an instruction or builtin
+    Type,
+    /// Division by zero
+    Arithmetic,
+    /// Index out of bounds of a string or an array
+    Index,
+    /// Lambda was called with a number of arguments it doesn't accept
+    Arity,
+    /// `assert-eq` got values that aren't equal
+    Assertion,
+    /// More nested calls than the maximum depth of the VM
+    StackOverflow,
+    /// Value given to `throw` wasn't caught
+    Uncaught,
+    /// `exit` was called with the status, embedders decide whether the process ends
+    Exit(i32),
+}
+
+// atoms of kinds of errors that can be caught
+const ATOMS: [(ErrorKind, u32); 6] = [
+    (ErrorKind::Type, ATOM_TYPE_ERROR),
+    (ErrorKind::Arithmetic, ATOM_ARITHMETIC_ERROR),
+    (ErrorKind::Index, ATOM_INDEX_ERROR),
+    (ErrorKind::Arity, ATOM_ARITY_ERROR),
+    (ErrorKind::Assertion, ATOM_ASSERTION_ERROR),
+    (ErrorKind::StackOverflow, ATOM_STACK_OVERFLOW),
+];
+
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Type => "type-error",
+            ErrorKind::Arithmetic => "arithmetic-error",
+            ErrorKind::Index => "index-error",
+            ErrorKind::Arity => "arity-error",
+            ErrorKind::Assertion => "assertion-error",
+            ErrorKind::StackOverflow => "stack-overflow",
+            ErrorKind::Uncaught => "uncaught",
+            ErrorKind::Exit(_) => "exit",
+        }
+    }
+
+    /// Atom the error is tagged with when it is caught, `None` for uncaught thrown values and exits
+    pub fn atom(&self) -> Option<u32> {
+        ATOMS
+            .iter()
+            .find(|(kind, _)| kind == self)
+            .map(|(_, atom)| *atom)
+    }
+
+    pub fn from_atom(atom: u32) -> Option<ErrorKind> {
+        ATOMS
+            .iter()
+            .find(|(_, a)| *a == atom)
+            .map(|(kind, _)| *kind)
+    }
+}
+
+/// Error raised by the program with the call stack where it happened, it is displayed as the
+/// message followed by the trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    // function names and source positions, from the failed function to the outermost call
+    pub trace: Vec<String>,
+    // calls left out of the trace because the stack was too deep
+    pub omitted: usize,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for location in &self.trace {
+            write!(f, "\n    at {}", location)?;
+        }
+        if self.omitted > 0 {
+            write!(f, "\n    ... {} more", self.omitted)?;
         }
-    };
+        return Ok(());
+    }
 }
+
+impl std::error::Error for RuntimeError {}
@@ -13,3 +13,74 @@ impl IsIf for Bytecode {
         }
     }
 }
+
+/// Index of the matching `Else` for every if, of the matching `Catch` for every `Try` and of the
+/// matching `End` for every `Else` and `Catch`, other instructions don't jump
+pub fn jump_targets(code: &[Bytecode]) -> Vec<u32> {
+    let mut targets = vec![0; code.len()];
+    let mut ifs = Vec::new();
+    let mut tries = Vec::new();
+    let mut elses = Vec::new();
+
+    for (pc, bc) in code.iter().enumerate() {
+        match bc {
+            bc if bc.is_if() => ifs.push(pc),
+            Bytecode::Else => {
+                if let Some(start) = ifs.pop() {
+                    targets[start] = pc as u32;
+                }
+                elses.push(pc);
+            }
+            Bytecode::Try => tries.push(pc),
+            Bytecode::Catch => {
+                if let Some(start) = tries.pop() {
+                    targets[start] = pc as u32;
+                }
+                elses.push(pc);
+            }
+            Bytecode::End => {
+                if let Some(start) = elses.pop() {
+                    targets[start] = pc as u32;
+                }
+            }
+            _ => (),
+        }
+    }
+    return targets;
+}
+
+#[cfg(test)]
+mod tests {
+    use vm_bytecode::Bytecode::*;
+
+    use super::jump_targets;
+
+    #[test]
+    fn if_jumps_to_else_and_else_to_end() {
+        let code = [LocalGet(0), If, ConstantNil, Else, ConstantNil, End, Ret];
+        assert_eq!(jump_targets(&code), [0, 3, 0, 5, 0, 0, 0]);
+
+        let code = [IfLte, Nop, Else, Nop, End];
+        assert_eq!(jump_targets(&code), [2, 0, 4, 0, 0]);
+    }
+
+    #[test]
+    fn nested_branches_match_innermost() {
+        let code = [If, IfGt, Nop, Else, Nop, End, Else, Nop, End];
+        assert_eq!(jump_targets(&code), [6, 3, 0, 5, 0, 0, 8, 0, 0]);
+    }
+
+    #[test]
+    fn try_jumps_to_catch_and_catch_to_end() {
+        let code = [
+            Try, If, Nop, Else, Nop, End, Catch, IfEq, Nop, Else, Nop, End, End,
+        ];
+        assert_eq!(
+            jump_targets(&code),
+            [6, 3, 0, 5, 0, 0, 12, 9, 0, 11, 0, 0, 0]
+        );
+
+        let code = [Try, Try, Nop, Catch, End, Catch, End];
+        assert_eq!(jump_targets(&code), [5, 3, 0, 4, 0, 6, 0]);
+    }
+}
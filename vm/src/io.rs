@@ -0,0 +1,139 @@
+use std::ffi::CString;
+use std::io::{BufRead, Read, Write};
+
+use vm_bytecode::{ATOM_ERR, ATOM_OK};
+
+use crate::value::Value;
+
+// Failed operations return `(:err reason)` instead of stopping the program, only arguments of
+// wrong types are runtime errors
+
+fn ok(value: Value) -> Value {
+    Value::Pair((Value::Atom(ATOM_OK), value).into())
+}
+
+fn err(reason: String) -> Value {
+    // messages of errors never contain the null character
+    let reason = Value::String(CString::new(reason).unwrap());
+    Value::Pair((Value::Atom(ATOM_ERR), reason).into())
+}
+
+// the reason is the same as in the C runtime, without the code of the OS error
+fn io_err(e: std::io::Error) -> Value {
+    let reason = e.to_string();
+    let reason = match reason.find(" (os error") {
+        Some(end) => reason[..end].to_owned(),
+        None => reason,
+    };
+    err(reason)
+}
+
+fn contents(bytes: Vec<u8>) -> Value {
+    match CString::new(bytes) {
+        Ok(s) => ok(Value::String(s)),
+        Err(_) => err(String::from("contains the null character")),
+    }
+}
+
+fn text(s: &Value) -> Result<String, String> {
+    match s {
+        Value::String(s) => Ok(s.to_string_lossy().into_owned()),
+        s => Err(format!("{} is not a string", s)),
+    }
+}
+
+pub fn read_file(file: Value) -> Result<Value, String> {
+    return Ok(match std::fs::read(text(&file)?) {
+        Ok(bytes) => contents(bytes),
+        Err(e) => io_err(e),
+    });
+}
+
+/// Writes the string to the file, the file is created or its contents replaced unless append
+pub fn write_file(file: Value, s: Value, append: bool) -> Result<Value, String> {
+    let file = text(&file)?;
+    let s = match s {
+        Value::String(s) => s,
+        s => return Err(format!("{} is not a string", s)),
+    };
+
+    let written = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(file)
+        .and_then(|mut file| file.write_all(s.as_bytes()));
+
+    return Ok(match written {
+        Ok(()) => ok(Value::Nil),
+        Err(e) => io_err(e),
+    });
+}
+
+/// Line of the standard input without the line break, `(:ok !)` at the end of the input
+pub fn read_line() -> Value {
+    // prompts written by `print` have to be visible before waiting for the input
+    let _ = std::io::stdout().flush();
+
+    let mut line = Vec::new();
+    match std::io::stdin().lock().read_until(b'\n', &mut line) {
+        Ok(0) => ok(Value::Nil),
+        Ok(_) => {
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+            }
+            contents(line)
+        }
+        Err(e) => io_err(e),
+    }
+}
+
+pub fn read_all_stdin() -> Value {
+    let _ = std::io::stdout().flush();
+
+    let mut bytes = Vec::new();
+    match std::io::stdin().lock().read_to_end(&mut bytes) {
+        Ok(_) => contents(bytes),
+        Err(e) => io_err(e),
+    }
+}
+
+/// 1 when the file or directory exists, otherwise 0
+pub fn file_exists(file: Value) -> Result<Value, String> {
+    let exists = std::path::Path::new(&text(&file)?).exists();
+    return Ok(Value::Int(exists as i32));
+}
+
+/// List of arguments the program was started with
+pub fn args(args: &[String]) -> Value {
+    args.iter().rev().fold(Value::Nil, |list, arg| {
+        // arguments of processes can't contain the null character
+        let arg = Value::String(CString::new(arg.as_str()).unwrap());
+        Value::Pair((arg, list).into())
+    })
+}
+
+/// Value of the environment variable, nil when it isn't set
+pub fn env(name: Value) -> Result<Value, String> {
+    let name = text(&name)?;
+    if name.is_empty() || name.contains('=') {
+        return Ok(Value::Nil);
+    }
+
+    return Ok(match std::env::var(name) {
+        Ok(value) => Value::String(CString::new(value).unwrap()),
+        Err(_) => Value::Nil,
+    });
+}
+
+/// Status the program asked to exit with
+pub fn exit_status(status: Value) -> Result<i32, String> {
+    match status {
+        Value::Int(status) => Ok(status),
+        status => Err(format!("{} is not an integer", status)),
+    }
+}
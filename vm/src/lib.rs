@@ -1,24 +1,35 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod bundle;
+mod debug;
 mod deserialize;
 mod error;
+mod io;
 
 #[cfg(not(target_arch = "wasm32"))]
 mod dlibraries;
 #[cfg(not(target_arch = "wasm32"))]
 mod jit;
 
+mod strings;
 mod value;
 mod vm;
 
 mod helper;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use bundle::embedded_program;
+pub use debug::*;
 pub use deserialize::*;
+pub use error::{ErrorKind, RuntimeError};
+pub use helper::IsIf;
+pub use value::Value;
 pub use vm::*;
 
-pub fn run_bytecode(bc: &[u8], console: impl Fn(&str)) {
-    let (start, constants, functions, signatures, groups) = deserialize(bc).unwrap();
+pub fn run_bytecode(bc: &[u8], console: impl Fn(&str)) -> Result<Value, RuntimeError> {
+    let (start, constants, functions, signatures, groups, debug) = deserialize(bc).unwrap();
 
     VM::new(
-        constants, functions, signatures, groups, console, false, false,
+        constants, functions, signatures, groups, debug, console, false, false,
     )
-    .run(start, vec![]);
+    .run(start, vec![])
 }
@@ -1,43 +1,89 @@
 use std::env::args;
+use std::io::Write;
 
-use vm::deserialize;
-use vm::VM;
+use vm::{deserialize, embedded_program};
+use vm::{ErrorKind, RuntimeError, VM};
+
+const USAGE: &str = "Usage: otvm <bytecode file> [--jit] [--max-depth <n>] [-- <args>...]";
 
 fn main() {
+    // executable created by `otc --bundle` runs its own program and gets all arguments
+    if let Some((program, jit)) = embedded_program() {
+        return run(&program, jit, false, None, args().skip(1).collect());
+    }
+
     let mut args = args().skip(1);
-    let path = args.next().unwrap();
-    let program = std::fs::read(path).unwrap();
-
-    let should_jit = if let Some(str) = args.next() {
-        if str == "--jit" {
-            true
-        } else {
-            false
-        }
-    } else {
-        false
-    };
-
-    let debug_jit = if let Some(str) = args.next() {
-        if str == "--debug-jit" {
-            true
-        } else {
-            false
+    let path = args
+        .next()
+        .unwrap_or_else(|| usage_error("missing bytecode file"));
+
+    let mut should_jit = false;
+    let mut debug_jit = false;
+    let mut max_depth = None;
+
+    // arguments after `--` are passed to the program
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jit" => should_jit = true,
+            "--debug-jit" => debug_jit = true,
+            "--max-depth" => match args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => max_depth = Some(depth),
+                _ => usage_error("`--max-depth` expects a number"),
+            },
+            "--" => break,
+            arg => usage_error(&format!("unknown argument `{}`", arg)),
         }
-    } else {
-        false
-    };
+    }
+
+    let program = std::fs::read(&path).unwrap_or_else(|e| {
+        eprintln!("\x1b[31m\x1b[1mERROR:\x1b[0m can't read `{}`: {}", path, e);
+        std::process::exit(1);
+    });
 
-    let (start, constants, functions, signatures, groups) = deserialize(&program).unwrap();
+    run(&program, should_jit, debug_jit, max_depth, args.collect());
+}
+
+fn run(
+    program: &[u8],
+    should_jit: bool,
+    debug_jit: bool,
+    max_depth: Option<usize>,
+    program_args: Vec<String>,
+) {
+    let (start, constants, functions, signatures, groups, debug) = deserialize(program).unwrap();
 
-    VM::new(
+    let mut vm = VM::new(
         constants,
         functions,
         signatures,
         groups,
+        debug,
         |s| print!("{}", s),
         should_jit,
         debug_jit,
-    )
-    .run(start, vec![]);
+    );
+    if let Some(depth) = max_depth {
+        vm.max_depth(depth);
+    }
+    vm.program_args(program_args);
+    let result = vm.run(start, vec![]);
+
+    let _ = std::io::stdout().flush();
+    match result {
+        Err(RuntimeError {
+            kind: ErrorKind::Exit(code),
+            ..
+        }) => std::process::exit(code),
+        // the status is set only by `exit`, the result of the program isn't used
+        Ok(_) => (),
+        Err(error) => {
+            eprintln!("\x1b[31m\x1b[1mVM ERROR:\x1b[0m {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("\x1b[31m\x1b[1mERROR:\x1b[0m {}\n\n{}", msg, USAGE);
+    std::process::exit(1);
 }
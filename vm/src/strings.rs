@@ -0,0 +1,177 @@
+use std::cmp::Ordering;
+use std::ffi::CString;
+
+use crate::error::ErrorKind;
+use crate::value::Value;
+
+// Strings are indexed by characters of their UTF-8 encoding, not by bytes
+
+fn new(s: impl Into<Vec<u8>>) -> Value {
+    // parts of strings and formatted values never contain the null character
+    Value::String(CString::new(s).unwrap())
+}
+
+fn text(s: &Value) -> Result<String, String> {
+    match s {
+        Value::String(s) => Ok(s.to_string_lossy().into_owned()),
+        s => Err(format!("{} is not a string", s)),
+    }
+}
+
+fn index(i: &Value) -> Result<i32, String> {
+    match i {
+        Value::Int(i) => Ok(*i),
+        i => Err(format!("{} is not an integer", i)),
+    }
+}
+
+pub fn len(s: Value) -> Result<Value, String> {
+    text(&s).map(|s| Value::Int(s.chars().count() as i32))
+}
+
+pub fn concat(first: Value, second: Value) -> Result<Value, String> {
+    match (first, second) {
+        (Value::String(first), Value::String(second)) => {
+            Ok(new([first.as_bytes(), second.as_bytes()].concat()))
+        }
+        (first, second) => Err(format!("Can't concat {:?} and {:?}", first, second)),
+    }
+}
+
+/// Characters from start up to end without it, the error of bounds is told apart from wrong types
+pub fn substring(s: Value, start: Value, end: Value) -> Result<Value, (ErrorKind, String)> {
+    let type_error = |e| (ErrorKind::Type, e);
+    let chars = text(&s).map_err(type_error)?.chars().collect::<Vec<_>>();
+    let (start, end) = (
+        index(&start).map_err(type_error)?,
+        index(&end).map_err(type_error)?,
+    );
+
+    if start < 0 || end < start || end as usize > chars.len() {
+        return Err((
+            ErrorKind::Index,
+            format!(
+                "substring {}..{} out of bounds of string of length {}",
+                start,
+                end,
+                chars.len()
+            ),
+        ));
+    }
+    return Ok(new(chars[start as usize..end as usize]
+        .iter()
+        .collect::<String>()));
+}
+
+/// Code point of the character at the index like `'a'`, nil when it is out of bounds
+pub fn char_at(s: Value, i: Value) -> Result<Value, String> {
+    let s = text(&s)?;
+    let i = index(&i)?;
+
+    return Ok(match s.chars().nth(i as usize) {
+        Some(c) if i >= 0 => Value::Int(c as i32),
+        _ => Value::Nil,
+    });
+}
+
+/// String of one character with the code point, the null character can't be in strings
+pub fn from_char(c: Value) -> Result<Value, String> {
+    match c {
+        Value::Int(i) if i > 0 => match char::from_u32(i as u32) {
+            Some(c) => Ok(new(c.to_string())),
+            None => Err(format!("{} is not a character", i)),
+        },
+        c => Err(format!("{} is not a character", c)),
+    }
+}
+
+/// Decimal integer with an optional sign, nil when the string isn't one
+pub fn to_int(s: Value) -> Result<Value, String> {
+    let s = text(&s)?;
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(&s);
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(Value::Nil);
+    }
+    return Ok(s.parse().map(Value::Int).unwrap_or(Value::Nil));
+}
+
+pub fn from_int(i: Value) -> Result<Value, String> {
+    index(&i).map(|i| new(i.to_string()))
+}
+
+/// Same text as `print` writes
+pub fn to_string(value: Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(s),
+        value => new(value.to_string()),
+    }
+}
+
+/// List of parts between separators, empty separator splits the string into characters
+pub fn split(s: Value, separator: Value) -> Result<Value, String> {
+    let string = text(&s)?;
+    let separator = text(&separator)?;
+
+    let parts: Vec<Value> = match separator.is_empty() {
+        true => string.chars().map(|c| new(c.to_string())).collect(),
+        false => string.split(separator.as_str()).map(new).collect(),
+    };
+    return Ok(list(parts));
+}
+
+/// Items of the list converted to strings with the separator between them
+pub fn join(list: Value, separator: Value) -> Result<Value, String> {
+    let separator = text(&separator)?;
+
+    let mut parts = Vec::new();
+    let mut rest = list;
+    loop {
+        rest = match rest {
+            Value::Nil => break,
+            Value::Pair(pair) => {
+                parts.push(pair.0.to_string());
+                pair.1.clone()
+            }
+            rest => return Err(format!("{} is not a list", rest)),
+        }
+    }
+    return Ok(new(parts.join(&separator)));
+}
+
+/// Index of the first character of the first occurrence, nil when there is none
+pub fn index_of(s: Value, part: Value) -> Result<Value, String> {
+    let string = text(&s)?;
+    let part = text(&part)?;
+
+    return Ok(match string.find(part.as_str()) {
+        Some(byte) => Value::Int(string[..byte].chars().count() as i32),
+        None => Value::Nil,
+    });
+}
+
+/// Only ASCII letters are changed, the same as in the C runtime
+pub fn upper(s: Value) -> Result<Value, String> {
+    text(&s).map(|s| new(s.to_ascii_uppercase()))
+}
+
+pub fn lower(s: Value) -> Result<Value, String> {
+    text(&s).map(|s| new(s.to_ascii_lowercase()))
+}
+
+/// -1, 0 or 1 when the first value is less than, equal to or greater than the second one,
+/// strings are compared by bytes
+pub fn compare(first: Value, second: Value) -> Value {
+    match first.partial_cmp(&second) {
+        Some(Ordering::Less) => Value::Int(-1),
+        Some(Ordering::Greater) => Value::Int(1),
+        _ => Value::Int(0),
+    }
+}
+
+fn list(items: Vec<Value>) -> Value {
+    items
+        .into_iter()
+        .rev()
+        .fold(Value::Nil, |list, item| Value::Pair((item, list).into()))
+}
@@ -6,8 +6,6 @@ use std::{
     rc::Rc,
 };
 
-use crate::vm_runtime_error;
-
 #[cfg(not(target_arch = "wasm32"))]
 use super::jit::typecheck::Type;
 
@@ -21,7 +19,7 @@ pub enum Value {
     Array(Rc<RefCell<Vec<Value>>>),
     String(CString),
 
-    Lambda(Rc<(u32, Vec<Value>)>),
+    Lambda(Rc<(u32, u8, Vec<Value>)>), // index of first function, number of arity overloads, context
 }
 
 impl Display for Value {
@@ -84,41 +82,42 @@ impl Value {
 }
 
 impl Add for Value {
-    type Output = Value;
+    type Output = Result<Value, String>;
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(i), Value::Int(i2)) => Value::Int(i + i2),
-            (s, r) => vm_runtime_error!("Can't add {:?} and {:?}", s, r),
+            (Value::Int(i), Value::Int(i2)) => Ok(Value::Int(i + i2)),
+            (s, r) => Err(format!("Can't add {:?} and {:?}", s, r)),
         }
     }
 }
 
 impl Sub for Value {
-    type Output = Value;
+    type Output = Result<Value, String>;
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(i), Value::Int(i2)) => Value::Int(i - i2),
-            (s, r) => vm_runtime_error!("Can't sub {:?} and {:?}", s, r),
+            (Value::Int(i), Value::Int(i2)) => Ok(Value::Int(i - i2)),
+            (s, r) => Err(format!("Can't sub {:?} and {:?}", s, r)),
         }
     }
 }
 
 impl Mul for Value {
-    type Output = Value;
+    type Output = Result<Value, String>;
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(i), Value::Int(i2)) => Value::Int(i * i2),
-            (s, r) => vm_runtime_error!("Can't mul {:?} and {:?}", s, r),
+            (Value::Int(i), Value::Int(i2)) => Ok(Value::Int(i * i2)),
+            (s, r) => Err(format!("Can't mul {:?} and {:?}", s, r)),
         }
     }
 }
 
 impl Div for Value {
-    type Output = Value;
+    type Output = Result<Value, String>;
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Value::Int(i), Value::Int(i2)) => Value::Int(i / i2),
-            (s, r) => vm_runtime_error!("Can't mul {:?} and {:?}", s, r),
+            (Value::Int(_), Value::Int(0)) => Err(String::from("Division by zero")),
+            (Value::Int(i), Value::Int(i2)) => Ok(Value::Int(i / i2)),
+            (s, r) => Err(format!("Can't div {:?} and {:?}", s, r)),
         }
     }
 }
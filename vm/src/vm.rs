@@ -1,33 +1,62 @@
 use std::borrow::Borrow;
 use std::cell::RefCell;
+use std::ffi::CString;
 use std::rc::Rc;
 
+use crate::debug::DebugInfo;
 use crate::deserialize::Type as T;
+use crate::error::{ErrorKind, RuntimeError};
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::dlibraries::DynamicLibraries;
 
-use crate::helper::IsIf;
+use crate::helper::jump_targets;
+use crate::io;
+use crate::strings;
 use crate::value::Value;
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::jit::typecheck::Type;
 
-use crate::vm_runtime_error;
 use vm_bytecode::Bytecode;
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::jit::JITHandle;
 
 const DEFAULT_STACK_SIZE: usize = 256;
+const DEFAULT_MAX_DEPTH: usize = 100_000;
+// calls shown in stack traces of errors
+const TRACE_LIMIT: usize = 20;
+
+/// Handler of `try` installed in the function at the depth, values thrown in its body continue
+/// after the `Catch` instruction
+struct Handler {
+    depth: usize,
+    stack_pointer: usize,
+    catch: usize,
+}
+
+/// Caller of the running function, restored when it returns
+struct Frame {
+    function: u32,
+    pc: usize,
+    locals: Vec<Value>,
+    // types of arguments of the call, collected for the JIT
+    #[cfg(not(target_arch = "wasm32"))]
+    shape: Option<Vec<Type>>,
+}
 
 pub struct VM<F: Fn(&str)> {
     constants: Vec<Value>,
     functions: Vec<(u8, u8, Vec<Bytecode>)>,
+    // branch targets of every function, so ifs don't have to search for them
+    jumps: Vec<Vec<u32>>,
 
     signatures: Vec<(String, Vec<T>, T)>,
     groups: Vec<Vec<u32>>,
 
+    debug: Option<DebugInfo>,
+
     #[cfg(not(target_arch = "wasm32"))]
     jit: JITHandle,
     #[cfg(not(target_arch = "wasm32"))]
@@ -36,6 +65,9 @@ pub struct VM<F: Fn(&str)> {
     print_handle: F,
     should_jit: bool,
     debug_jit: bool,
+    max_depth: usize,
+
+    program_args: Vec<String>,
 }
 
 impl<F: Fn(&str)> VM<F> {
@@ -44,19 +76,28 @@ impl<F: Fn(&str)> VM<F> {
         functions: Vec<(u8, u8, Vec<Bytecode>)>,
         signatures: Vec<(String, Vec<T>, T)>,
         groups: Vec<Vec<u32>>,
+        debug: Option<DebugInfo>,
         print_handle: F,
         should_jit: bool,
         debug_jit: bool,
     ) -> VM<F> {
         let fc = functions.len();
+        let jumps = functions
+            .iter()
+            .map(|(_, _, code)| jump_targets(code))
+            .collect();
 
         VM {
             constants,
             functions,
+            jumps,
             signatures,
             groups,
+            debug,
             should_jit,
             debug_jit,
+            max_depth: DEFAULT_MAX_DEPTH,
+            program_args: Vec::new(),
 
             #[cfg(not(target_arch = "wasm32"))]
             jit: JITHandle::new(fc),
@@ -66,33 +107,270 @@ impl<F: Fn(&str)> VM<F> {
         }
     }
 
-    pub fn run(&mut self, start_function: u32, mut args: Vec<Value>) -> Value {
+    /// Replaces the program, loaded libraries and values returned by the previous one are kept
+    pub fn load(
+        &mut self,
+        constants: Vec<Value>,
+        functions: Vec<(u8, u8, Vec<Bytecode>)>,
+        signatures: Vec<(String, Vec<T>, T)>,
+        groups: Vec<Vec<u32>>,
+        debug: Option<DebugInfo>,
+    ) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.jit = JITHandle::new(functions.len());
+        }
+
+        self.constants = constants;
+        self.jumps = functions
+            .iter()
+            .map(|(_, _, code)| jump_targets(code))
+            .collect();
+        self.functions = functions;
+        self.signatures = signatures;
+        self.groups = groups;
+        self.debug = debug;
+    }
+
+    /// Number of nested calls after which the program fails with stack overflow
+    pub fn max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Arguments the program gets from `(args)`
+    pub fn program_args(&mut self, args: Vec<String>) {
+        self.program_args = args;
+    }
+
+    /// Runs the function until it returns, errors raised by the program stop it
+    pub fn run(
+        &mut self,
+        start_function: u32,
+        mut args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
         let (_, localc, code) = self.borrow_function(start_function);
         let mut code = code;
+        let mut function = start_function;
 
         args.extend(vec![Value::Int(0); *localc as usize]);
         let mut locals = args;
 
-        const V: Value = Value::Int(0);
-        let mut stack = [V; DEFAULT_STACK_SIZE];
+        let mut stack = Vec::with_capacity(DEFAULT_STACK_SIZE);
         let mut stack_pointer = 0;
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut handlers: Vec<Handler> = Vec::new();
 
         let mut pc = 0;
 
-        while pc < code.len() {
-            match code[pc] {
+        // the innermost handler gets the value, functions called inside of its body are left
+        macro_rules! raise {
+            ($value: expr) => {{
+                let value = $value;
+                let handler = handlers.pop().unwrap();
+                // handlers of returned frames are removed by `Ret`, the handler's frame is kept
+                let depth = handler.depth.min(frames.len());
+                if let Some(frame) = frames.drain(depth..).next() {
+                    function = frame.function;
+                    locals = frame.locals;
+                    code = &self.borrow_function(function).2;
+                }
+                stack.truncate(handler.stack_pointer);
+                stack_pointer = handler.stack_pointer;
+                Self::push_stack(&mut stack, &mut stack_pointer, value);
+                pc = handler.catch + 1;
+                continue;
+            }};
+        }
+
+        // the error is caught as `(pair kind message)`, without a handler it is returned with the
+        // call stack of the failed instruction
+        macro_rules! fail {
+            ($kind: expr, $message: expr) => {{
+                let (kind, message): (ErrorKind, String) = ($kind, $message);
+                if handlers.is_empty() {
+                    return Err(self.runtime_error(&frames, function, pc, kind, message));
+                }
+                raise!(error_value(kind, message))
+            }};
+        }
+
+        loop {
+            let instruction = match code.get(pc) {
+                Some(instruction) => *instruction,
+                // functions fall off the end when none of their clauses matches
+                None => {
+                    Self::push_stack(&mut stack, &mut stack_pointer, Value::Nil);
+                    Bytecode::Ret
+                }
+            };
+
+            match instruction {
                 Bytecode::StringLen => {
                     let s = Self::pop_stack(&mut stack, &mut stack_pointer);
-
-                    if let Value::String(s) = s {
-                        Self::push_stack(
-                            &mut stack,
-                            &mut stack_pointer,
-                            Value::Int(s.as_bytes().len() as i32),
+                    match strings::len(s) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringConcat => {
+                    let second = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let first = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::concat(first, second) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::Substring => {
+                    let end = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let start = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::substring(s, start, end) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err((kind, e)) => fail!(kind, e),
+                    }
+                }
+                Bytecode::CharAt => {
+                    let index = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::char_at(s, index) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::CharToString => {
+                    let c = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::from_char(c) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::ReadFile => {
+                    let file = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match io::read_file(file) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::WriteFile | Bytecode::AppendFile => {
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let file = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let append = matches!(instruction, Bytecode::AppendFile);
+                    match io::write_file(file, s, append) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::ReadLine => {
+                    Self::push_stack(&mut stack, &mut stack_pointer, io::read_line());
+                }
+                Bytecode::ReadAllStdin => {
+                    Self::push_stack(&mut stack, &mut stack_pointer, io::read_all_stdin());
+                }
+                Bytecode::FileExists => {
+                    let file = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match io::file_exists(file) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::ProgramArgs => {
+                    Self::push_stack(&mut stack, &mut stack_pointer, io::args(&self.program_args));
+                }
+                Bytecode::Env => {
+                    let name = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match io::env(name) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::Exit => {
+                    // the embedder ends the process, handlers of `try` don't catch it
+                    let status = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match io::exit_status(status) {
+                        Ok(status) => {
+                            let message = format!("exited with status {}", status);
+                            let kind = ErrorKind::Exit(status);
+                            return Err(self.runtime_error(&frames, function, pc, kind, message));
+                        }
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringToInt => {
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::to_int(s) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::IntToString => {
+                    let i = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::from_int(i) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::ToString => {
+                    let value = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    Self::push_stack(&mut stack, &mut stack_pointer, strings::to_string(value));
+                }
+                Bytecode::StringSplit => {
+                    let separator = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::split(s, separator) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringJoin => {
+                    let separator = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let list = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::join(list, separator) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringIndexOf => {
+                    let part = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::index_of(s, part) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringUpper => {
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::upper(s) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringLower => {
+                    let s = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    match strings::lower(s) {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
+                }
+                Bytecode::StringCompare => {
+                    let second = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let first = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    Self::push_stack(
+                        &mut stack,
+                        &mut stack_pointer,
+                        strings::compare(first, second),
+                    );
+                }
+                Bytecode::AssertEq => {
+                    let actual = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let expected = Self::pop_stack(&mut stack, &mut stack_pointer);
+
+                    if expected != actual {
+                        fail!(
+                            ErrorKind::Assertion,
+                            format!("assertion failed: expected {}, found {}", expected, actual)
                         );
-                    } else {
-                        unreachable!();
                     }
+                    Self::push_stack(&mut stack, &mut stack_pointer, Value::Nil);
                 }
                 Bytecode::Import(index) => {
                     let path = Self::pop_stack(&mut stack, &mut stack_pointer);
@@ -179,22 +457,37 @@ impl<F: Fn(&str)> VM<F> {
                 Bytecode::Add => {
                     let first = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let second = Self::pop_stack(&mut stack, &mut stack_pointer);
-                    Self::push_stack(&mut stack, &mut stack_pointer, first + second);
+                    match first + second {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
                 }
                 Bytecode::Sub => {
                     let first = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let second = Self::pop_stack(&mut stack, &mut stack_pointer);
-                    Self::push_stack(&mut stack, &mut stack_pointer, second - first);
+                    match second - first {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
                 }
                 Bytecode::Mul => {
                     let first = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let second = Self::pop_stack(&mut stack, &mut stack_pointer);
-                    Self::push_stack(&mut stack, &mut stack_pointer, first * second);
+                    match first * second {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
                 }
                 Bytecode::Div => {
                     let first = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let second = Self::pop_stack(&mut stack, &mut stack_pointer);
-                    Self::push_stack(&mut stack, &mut stack_pointer, second / first);
+                    if let (Value::Int(_), Value::Int(0)) = (&second, &first) {
+                        fail!(ErrorKind::Arithmetic, String::from("Division by zero"));
+                    }
+                    match second / first {
+                        Ok(val) => Self::push_stack(&mut stack, &mut stack_pointer, val),
+                        Err(e) => fail!(ErrorKind::Type, e),
+                    }
                 }
                 Bytecode::Eq => {
                     let first = Self::pop_stack(&mut stack, &mut stack_pointer);
@@ -244,38 +537,33 @@ impl<F: Fn(&str)> VM<F> {
                 Bytecode::If => {
                     let cond = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if cond == Value::Int(0) || cond == Value::Nil {
-                        let mut fc: u32 = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
-                Bytecode::Else => {
-                    let mut elsec: u32 = 0;
-
-                    loop {
-                        pc += 1;
-                        match code[pc] {
-                            Bytecode::Else => elsec += 1,
-                            Bytecode::End if elsec == 0 => break,
-                            Bytecode::End if elsec != 0 => elsec -= 1,
-                            _ => (),
-                        }
+                Bytecode::Else => pc = self.jumps[function as usize][pc] as usize,
+                Bytecode::End => (),
+                Bytecode::Try => handlers.push(Handler {
+                    depth: frames.len(),
+                    stack_pointer,
+                    catch: self.jumps[function as usize][pc] as usize,
+                }),
+                Bytecode::Catch => {
+                    handlers.pop();
+                    pc = self.jumps[function as usize][pc] as usize;
+                }
+                Bytecode::Throw => {
+                    let value = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    if handlers.is_empty() {
+                        let (kind, message) = uncaught(value);
+                        return Err(self.runtime_error(&frames, function, pc, kind, message));
                     }
+                    raise!(value)
                 }
-                Bytecode::End => (),
                 Bytecode::Call(i) => {
                     let i_usize = i as usize;
 
                     #[cfg(not(target_arch = "wasm32"))]
-                    let result = if self.jit.function_exist(i) {
+                    if self.jit.function_exist(i) {
                         println!("CALLING JIT");
                         let (argc, _, _) = &self.functions[i_usize];
 
@@ -287,59 +575,68 @@ impl<F: Fn(&str)> VM<F> {
                         args.reverse();
 
                         let shape = self.jit.get_shape(i_usize);
-                        Value::from((shape.ret, self.jit.call_function(i, &args).unwrap()))
-                    } else {
-                        if self.jit.is_function_hot(i_usize, 5) {
-                            let (_, _, bc) = &self.functions[i_usize];
-
-                            let shape = self.jit.get_shape(i_usize);
-                            self.jit.compile(
-                                i_usize,
-                                shape.args.unwrap(),
-                                0,
-                                shape.ret,
-                                bc,
-                                &self.constants,
-                                self.debug_jit,
-                            );
-                        }
+                        let result =
+                            Value::from((shape.ret, self.jit.call_function(i, &args).unwrap()));
+                        Self::push_stack(&mut stack, &mut stack_pointer, result);
+                        pc += 1;
+                        continue;
+                    }
 
-                        let (argc, _, _) = &self.functions[i_usize];
-                        let mut args = Vec::with_capacity(*argc as usize);
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if self.jit.is_function_hot(i_usize, 5) {
+                        let (_, _, bc) = &self.functions[i_usize];
 
-                        for _ in 0..*argc {
-                            args.push(Self::pop_stack(&mut stack, &mut stack_pointer));
-                        }
+                        let shape = self.jit.get_shape(i_usize);
+                        self.jit.compile(
+                            i_usize,
+                            shape.args.unwrap(),
+                            0,
+                            shape.ret,
+                            bc,
+                            &self.constants,
+                            self.debug_jit,
+                        );
+                    }
 
-                        args.reverse();
+                    let (argc, localc, new_code) = self.borrow_function(i);
+                    let mut args = Vec::with_capacity(*argc as usize + *localc as usize);
+
+                    for _ in 0..*argc {
+                        args.push(Self::pop_stack(&mut stack, &mut stack_pointer));
+                    }
 
-                        let args_shape: Vec<Type> = args.iter().map(|arg| arg.typ()).collect();
-                        let result = self.run(i, args);
+                    args.reverse();
 
-                        if self.should_jit {
-                            self.jit.write_stats(i_usize, args_shape, result.typ());
-                        }
-                        result
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let shape = match self.should_jit {
+                        true => Some(args.iter().map(|arg| arg.typ()).collect()),
+                        false => None,
                     };
-                    #[cfg(target_arch = "wasm32")]
-                    let result = {
-                        let (argc, _, _) = &self.functions[i_usize];
-                        let mut args = Vec::with_capacity(*argc as usize);
 
-                        for _ in 0..*argc {
-                            args.push(Self::pop_stack(&mut stack, &mut stack_pointer));
-                        }
-
-                        args.reverse();
+                    if frames.len() >= self.max_depth {
+                        fail!(ErrorKind::StackOverflow, String::from("stack overflow"));
+                    }
 
-                        let result = self.run(i, args);
+                    args.extend(vec![Value::Int(0); *localc as usize]);
+                    frames.push(Frame {
+                        function,
+                        pc,
+                        locals: std::mem::replace(&mut locals, args),
+                        #[cfg(not(target_arch = "wasm32"))]
+                        shape,
+                    });
 
-                        result
-                    };
-                    Self::push_stack(&mut stack, &mut stack_pointer, result);
+                    code = new_code;
+                    function = i;
+                    pc = 0;
+                    continue;
                 }
                 Bytecode::CallRet(i) => {
                     let (argc, localc, new_code) = self.borrow_function(i);
+                    // the frame is replaced, so are its handlers
+                    while handlers.last().map_or(false, |h| h.depth >= frames.len()) {
+                        handlers.pop();
+                    }
 
                     locals.clear();
                     for _ in 0..*argc {
@@ -352,10 +649,31 @@ impl<F: Fn(&str)> VM<F> {
                     //println!("{:?}", locals);
 
                     code = new_code;
+                    function = i;
                     pc = 0;
                     continue;
                 }
-                Bytecode::Ret => return Self::pop_stack(&mut stack, &mut stack_pointer),
+                Bytecode::Ret => {
+                    let result = Self::pop_stack(&mut stack, &mut stack_pointer);
+                    let frame = match frames.pop() {
+                        Some(frame) => frame,
+                        None => return Ok(result),
+                    };
+                    while handlers.last().map_or(false, |h| h.depth > frames.len()) {
+                        handlers.pop();
+                    }
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if let Some(shape) = frame.shape {
+                        self.jit.write_stats(function as usize, shape, result.typ());
+                    }
+
+                    code = &self.borrow_function(frame.function).2;
+                    function = frame.function;
+                    pc = frame.pc;
+                    locals = frame.locals;
+                    Self::push_stack(&mut stack, &mut stack_pointer, result);
+                }
                 Bytecode::Remove => {
                     Self::pop_stack(&mut stack, &mut stack_pointer);
                 }
@@ -406,13 +724,19 @@ impl<F: Fn(&str)> VM<F> {
                     let size = Self::pop_stack(&mut stack, &mut stack_pointer);
 
                     if let Value::Int(size) = size {
+                        if size < 0 {
+                            fail!(
+                                ErrorKind::Index,
+                                String::from("array size can't be negative")
+                            );
+                        }
                         Self::push_stack(
                             &mut stack,
                             &mut stack_pointer,
                             Value::Array(Rc::new(RefCell::new(vec![Value::Nil; size as usize]))),
                         );
                     } else {
-                        vm_runtime_error!("{} must be an integer", size);
+                        fail!(ErrorKind::Type, format!("{} must be an integer", size));
                     }
                 }
                 Bytecode::ArrayGet => {
@@ -429,10 +753,9 @@ impl<F: Fn(&str)> VM<F> {
                                 .clone(),
                         );
                     } else {
-                        vm_runtime_error!(
-                            "{} has to be array and {} has to be integer",
-                            val,
-                            index
+                        fail!(
+                            ErrorKind::Type,
+                            format!("{} has to be array and {} has to be integer", val, index)
                         );
                     }
                 }
@@ -442,13 +765,15 @@ impl<F: Fn(&str)> VM<F> {
                     let array = Self::pop_stack(&mut stack, &mut stack_pointer);
 
                     if let (Value::Array(arr), Value::Int(index)) = (&array, &index) {
-                        arr.borrow_mut()[*index as usize] = value;
+                        match arr.borrow_mut().get_mut(*index as usize) {
+                            Some(item) if *index >= 0 => *item = value,
+                            _ => fail!(ErrorKind::Index, String::from("index out of bounds")),
+                        }
                         Self::push_stack(&mut stack, &mut stack_pointer, array);
                     } else {
-                        vm_runtime_error!(
-                            "{} has to be array and {} has to be integer",
-                            array,
-                            index
+                        fail!(
+                            ErrorKind::Type,
+                            format!("{} has to be array and {} has to be integer", array, index)
                         );
                     }
                 }
@@ -462,11 +787,11 @@ impl<F: Fn(&str)> VM<F> {
                             Value::Int(arr.borrow_mut().len() as i32),
                         )
                     } else {
-                        vm_runtime_error!("{} is not array", array);
+                        fail!(ErrorKind::Type, format!("{} is not array", array));
                     }
                 }
 
-                Bytecode::NewLambda(findex, ctx) => {
+                Bytecode::NewLambda(findex, ctx, overloads) => {
                     let s = ctx as usize;
                     let mut v = vec![Value::Nil; s];
                     for i in (0..s).rev() {
@@ -476,20 +801,29 @@ impl<F: Fn(&str)> VM<F> {
                     Self::push_stack(
                         &mut stack,
                         &mut stack_pointer,
-                        Value::Lambda(Rc::new((findex, v))),
+                        Value::Lambda(Rc::new((findex, overloads, v))),
                     );
                 }
-                Bytecode::CallLambda => {
+                Bytecode::CallLambda(argc) => {
                     let lambda = Self::pop_stack(&mut stack, &mut stack_pointer);
 
                     if let Value::Lambda(rc) = lambda {
-                        let index = rc.0;
-                        let ctx = &rc.1;
+                        let (first, overloads, ctx) = (rc.0, rc.1, &rc.2);
+
+                        // Arity overloads are stored next to each other
+                        let index = match (first..first + overloads as u32)
+                            .find(|&i| self.functions[i as usize].0 == argc)
+                        {
+                            Some(index) => index,
+                            None => fail!(
+                                ErrorKind::Arity,
+                                format!("lambda does not accept {} arguments", argc)
+                            ),
+                        };
 
-                        let (argc, _, _) = &self.functions[index as usize];
-                        let mut args = Vec::with_capacity(*argc as usize);
+                        let mut args = Vec::with_capacity(argc as usize);
 
-                        for _ in 0..*argc {
+                        for _ in 0..argc {
                             args.push(Self::pop_stack(&mut stack, &mut stack_pointer));
                         }
                         args.push(Value::Array(Rc::new(RefCell::new(
@@ -498,9 +832,26 @@ impl<F: Fn(&str)> VM<F> {
 
                         args.reverse();
 
-                        Self::push_stack(&mut stack, &mut stack_pointer, self.run(index, args));
+                        if frames.len() >= self.max_depth {
+                            fail!(ErrorKind::StackOverflow, String::from("stack overflow"));
+                        }
+
+                        let (_, localc, new_code) = self.borrow_function(index);
+                        args.extend(vec![Value::Int(0); *localc as usize]);
+                        frames.push(Frame {
+                            function,
+                            pc,
+                            locals: std::mem::replace(&mut locals, args),
+                            #[cfg(not(target_arch = "wasm32"))]
+                            shape: None,
+                        });
+
+                        code = new_code;
+                        function = index;
+                        pc = 0;
+                        continue;
                     } else {
-                        vm_runtime_error!("{} is not callable", lambda);
+                        fail!(ErrorKind::Type, format!("{} is not callable", lambda));
                     }
                 }
                 Bytecode::LocalArrayGet(local, index) => {
@@ -511,7 +862,10 @@ impl<F: Fn(&str)> VM<F> {
                             array.borrow_mut()[index as usize].clone(),
                         )
                     } else {
-                        vm_runtime_error!("{} is not an array", &locals[local as usize]);
+                        fail!(
+                            ErrorKind::Type,
+                            format!("{} is not an array", &locals[local as usize])
+                        );
                     }
                 }
                 Bytecode::Print => {
@@ -528,98 +882,87 @@ impl<F: Fn(&str)> VM<F> {
                     let left = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let right = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if left != right {
-                        let mut fc = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
                 Bytecode::IfLt => {
                     let right = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let left = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if left < right {
-                        let mut fc = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
                 Bytecode::IfGt => {
                     let right = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let left = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if left > right {
-                        let mut fc = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
                 Bytecode::IfLte => {
                     let right = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let left = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if left <= right {
-                        let mut fc = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
                 Bytecode::IfGte => {
                     let right = Self::pop_stack(&mut stack, &mut stack_pointer);
                     let left = Self::pop_stack(&mut stack, &mut stack_pointer);
                     if left >= right {
-                        let mut fc = 0;
-
-                        loop {
-                            pc += 1;
-                            match code[pc] {
-                                bc if bc.is_if() => fc += 1,
-                                Bytecode::Else if fc == 0 => break,
-                                Bytecode::Else if fc != 0 => fc -= 1,
-                                _ => (),
-                            }
-                        }
+                        pc = self.jumps[function as usize][pc] as usize;
                     }
                 }
                 Bytecode::Nop => (),
             }
             pc += 1;
         }
+    }
+
+    fn runtime_error(
+        &self,
+        frames: &[Frame],
+        function: u32,
+        pc: usize,
+        kind: ErrorKind,
+        message: String,
+    ) -> RuntimeError {
+        // callers are traced at their call instructions, tail calls don't leave a frame
+        let calls = std::iter::once((function, pc))
+            .chain(frames.iter().rev().map(|frame| (frame.function, frame.pc)));
+        let trace = calls
+            .take(TRACE_LIMIT)
+            .map(|(function, pc)| self.location(function, pc))
+            .collect();
+
+        return RuntimeError {
+            kind,
+            message,
+            trace,
+            omitted: (frames.len() + 1).saturating_sub(TRACE_LIMIT),
+        };
+    }
 
-        Value::Nil
+    fn location(&self, function: u32, pc: usize) -> String {
+        match &self.debug {
+            Some(debug) => {
+                let name = debug.function_name(function).unwrap_or("?");
+                match debug.locate(function, pc) {
+                    Some((file, line, col)) => format!("{} ({}:{}:{})", name, file, line, col),
+                    None => name.to_owned(),
+                }
+            }
+            None => format!("function #{}", function),
+        }
     }
 
     #[inline(always)]
-    fn push_stack(stack: &mut [Value], stack_pointer: &mut usize, val: Value) {
-        stack[*stack_pointer] = val;
+    fn push_stack(stack: &mut Vec<Value>, stack_pointer: &mut usize, val: Value) {
+        if *stack_pointer == stack.len() {
+            stack.push(val);
+        } else {
+            stack[*stack_pointer] = val;
+        }
         *stack_pointer += 1;
     }
 
@@ -636,3 +979,469 @@ impl<F: Fn(&str)> VM<F> {
         unsafe { &*(&self.functions[index as usize] as *const _) }
     }
 }
+
+fn error_value(kind: ErrorKind, message: String) -> Value {
+    let kind = Value::Atom(kind.atom().expect("raised errors can be caught"));
+    // messages of errors never contain the null character
+    let message = Value::String(CString::new(message).unwrap());
+    return Value::Pair(Rc::new((kind, message)));
+}
+
+// Errors raised by the VM and thrown again keep their kind and message
+fn uncaught(value: Value) -> (ErrorKind, String) {
+    if let Value::Pair(pair) = &value {
+        if let (Value::Atom(atom), Value::String(message)) = &**pair {
+            if let Some(kind) = ErrorKind::from_atom(*atom) {
+                return (kind, message.to_string_lossy().into_owned());
+            }
+        }
+    }
+    return (ErrorKind::Uncaught, format!("uncaught {}", value));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use vm_bytecode::asm::{assemble, escape};
+    use vm_bytecode::{ATOM_ERR, ATOM_OK};
+
+    use super::TRACE_LIMIT;
+    use crate::{deserialize, ErrorKind, RuntimeError, Value, VM};
+
+    type Output = Rc<RefCell<String>>;
+
+    // VM running the program written in the assembly, with its start function and printed output
+    fn program(src: &str) -> (VM<impl Fn(&str)>, u32, Output) {
+        let bytes = assemble(src).unwrap();
+        let (start, constants, functions, signatures, groups, debug) = deserialize(&bytes).unwrap();
+
+        let output = Output::default();
+        let print = {
+            let output = output.clone();
+            move |s: &str| output.borrow_mut().push_str(s)
+        };
+        let vm = VM::new(
+            constants, functions, signatures, groups, debug, print, false, false,
+        );
+        return (vm, start, output);
+    }
+
+    fn run(src: &str) -> Result<Value, RuntimeError> {
+        let (mut vm, start, _) = program(src);
+        return vm.run(start, Vec::new());
+    }
+
+    // Runs the instructions in the start function with the constants, its result is returned
+    fn expression(constants: &[&str], code: &[&str]) -> Result<Value, RuntimeError> {
+        let constants = constants
+            .iter()
+            .map(|constant| format!(".const {}\n", constant))
+            .collect::<String>();
+        return run(&format!(
+            ".start main\n{}.func main 0 0\n{}\nRet\n",
+            constants,
+            code.join("\n")
+        ));
+    }
+
+    fn string(s: &str) -> Result<Value, RuntimeError> {
+        return Ok(Value::String(std::ffi::CString::new(s).unwrap()));
+    }
+
+    #[test]
+    fn throw_is_caught_across_frames() {
+        let result = run("
+            .start main
+            .const int 1
+            .const int 41
+            .func main 0 1
+                Try
+                Call(f)
+                Catch
+                LocalSet(0)
+                LocalGet(0)
+                ConstantGet(0)
+                Add
+                End
+                Ret
+            .func f 0 0
+                ConstantGet(0)
+                Call(g)
+                Add
+                Ret
+            .func g 0 0
+                ConstantGet(1)
+                Throw
+        ");
+
+        assert_eq!(result, Ok(Value::Int(42)));
+    }
+
+    #[test]
+    fn errors_are_caught_with_kind() {
+        let result = run("
+            .start main
+            .const int 1
+            .const int 0
+            .func main 0 0
+                Try
+                ConstantGet(0)
+                ConstantGet(1)
+                Div
+                Catch
+                PairLeft
+                End
+                Ret
+        ");
+
+        assert_eq!(
+            result,
+            Ok(Value::Atom(ErrorKind::Arithmetic.atom().unwrap()))
+        );
+    }
+
+    #[test]
+    fn handlers_end_with_their_frame() {
+        // `f` returns from the body of `try`, the later throw isn't caught by its handler
+        let result = run("
+            .start main
+            .func main 0 0
+                Call(f)
+                Remove
+                Atom(9)
+                Throw
+            .func f 0 0
+                Try
+                ConstantNil
+                Ret
+                Catch
+                End
+                Ret
+        ");
+
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Uncaught);
+        assert_eq!(error.message, "uncaught atom(9)");
+    }
+
+    #[test]
+    fn handlers_end_with_tail_call() {
+        // tail call replaces the frame of `try` in `f`
+        let result = run("
+            .start main
+            .func main 0 0
+                Call(f)
+                Remove
+                Atom(9)
+                Throw
+            .func f 0 0
+                Try
+                CallRet(h)
+                Catch
+                End
+                Ret
+            .func h 0 0
+                ConstantNil
+                Ret
+        ");
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Uncaught);
+    }
+
+    #[test]
+    fn string_builtins() {
+        let word = "str \"žluťoučký\"";
+        let get = |i: u8| format!("ConstantGet({})", i);
+        let (c0, c1, c2) = (get(0), get(1), get(2));
+
+        let substring = expression(&[word, "int 1", "int 4"], &[&c0, &c1, &c2, "Substring"]);
+        assert_eq!(substring, string("luť"));
+        assert_eq!(
+            expression(&[word, "int 3"], &[&c0, &c1, "CharAt"]),
+            Ok(Value::Int(357))
+        );
+        assert_eq!(
+            expression(&["int 357"], &[&c0, "CharToString"]),
+            string("ť")
+        );
+        assert_eq!(expression(&[word], &[&c0, "StringLen"]), Ok(Value::Int(9)));
+        assert_eq!(
+            expression(&[word, "str \"ou\""], &[&c0, &c1, "StringIndexOf"]),
+            Ok(Value::Int(4))
+        );
+
+        assert_eq!(
+            expression(&["str \"-42\""], &[&c0, "StringToInt"]),
+            Ok(Value::Int(-42))
+        );
+        assert_eq!(
+            expression(&["str \"4x\""], &[&c0, "StringToInt"]),
+            Ok(Value::Nil)
+        );
+        assert_eq!(
+            expression(
+                &["int 4", "int 2"],
+                &[
+                    &c0,
+                    "IntToString",
+                    &c1,
+                    "ConstantNil",
+                    "NewPair",
+                    "ToString",
+                    "StringConcat"
+                ]
+            ),
+            string("4{2;nil}")
+        );
+        assert_eq!(
+            expression(
+                &["str \"a,b,,c\"", "str \",\"", "str \"-\""],
+                &[&c0, &c1, "StringSplit", &c2, "StringJoin", "StringUpper"]
+            ),
+            string("A-B--C")
+        );
+        assert_eq!(
+            expression(
+                &["str \"ab\"", "str \"\"", "str \"+\""],
+                &[&c0, &c1, "StringSplit", &c2, "StringJoin"]
+            ),
+            string("a+b")
+        );
+        assert_eq!(
+            expression(&["str \"a\"", "str \"b\""], &[&c0, &c1, "StringCompare"]),
+            Ok(Value::Int(-1))
+        );
+
+        let error = expression(&[word, "int 5", "int 20"], &[&c0, &c1, &c2, "Substring"]);
+        assert_eq!(error.unwrap_err().kind, ErrorKind::Index);
+        let error = expression(&["int 1"], &[&c0, "StringUpper"]);
+        assert_eq!(error.unwrap_err().kind, ErrorKind::Type);
+    }
+
+    #[test]
+    fn file_builtins() {
+        let file = std::env::temp_dir().join(format!("ot-vm-{}.txt", std::process::id()));
+        let path = format!("str {}", escape(&file.to_string_lossy()));
+        let get = |i: u8| format!("ConstantGet({})", i);
+        let (c0, c1, c2) = (get(0), get(1), get(2));
+        let constants = [path.as_str(), "str \"a\"", "str \"b\""];
+
+        let contents = expression(
+            &constants,
+            &[
+                &c0,
+                &c1,
+                "WriteFile",
+                "Remove",
+                &c0,
+                &c2,
+                "AppendFile",
+                "Remove",
+                &c0,
+                "ReadFile",
+            ],
+        );
+        let ok = Value::Pair((Value::Atom(ATOM_OK), string("ab").unwrap()).into());
+        assert_eq!(contents, Ok(ok));
+        assert_eq!(
+            expression(&constants, &[&c0, "FileExists"]),
+            Ok(Value::Int(1))
+        );
+
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(
+            expression(&constants, &[&c0, "FileExists"]),
+            Ok(Value::Int(0))
+        );
+        assert_eq!(
+            expression(&constants, &[&c0, "ReadFile", "PairLeft"]),
+            Ok(Value::Atom(ATOM_ERR))
+        );
+
+        let error = expression(&["int 1"], &[&c0, "ReadFile"]);
+        assert_eq!(error.unwrap_err().kind, ErrorKind::Type);
+    }
+
+    #[test]
+    fn process_builtins() {
+        let (mut vm, start, _) = program(".start main\n.func main 0 0\nProgramArgs\nRet\n");
+        vm.program_args(vec![String::from("a"), String::from("b")]);
+        assert_eq!(
+            vm.run(start, Vec::new()).unwrap().to_string(),
+            "{a;{b;nil}}"
+        );
+
+        // variables are only read, tests run in parallel
+        assert_eq!(
+            expression(&["str \"PATH\""], &["ConstantGet(0)", "Env"]),
+            string(&std::env::var("PATH").unwrap())
+        );
+        for name in ["\"OT_VM_NOT_SET\"", "\"\"", "\"A=B\""] {
+            assert_eq!(
+                expression(&[&format!("str {}", name)], &["ConstantGet(0)", "Env"]),
+                Ok(Value::Nil)
+            );
+        }
+
+        // handlers of `try` don't catch it
+        let error = expression(
+            &["int 3"],
+            &["Try", "ConstantGet(0)", "Exit", "Catch", "End"],
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Exit(3));
+        assert_eq!(error.message, "exited with status 3");
+    }
+
+    // `sum` adds numbers up to its argument without tail calls, `main` calls it with 50000
+    const SUM: &str = "
+        .start main
+        .const int 0
+        .const int 1
+        .const int 50000
+        .func main 0 0
+            ConstantGet(2)
+            Call(sum)
+            Ret
+        .func sum 1 0
+            LocalGet(0)
+            ConstantGet(0)
+            Eq
+            If
+            ConstantGet(0)
+            Else
+            LocalGet(0)
+            LocalGet(0)
+            ConstantGet(1)
+            Sub
+            Call(sum)
+            Add
+            End
+            Ret
+    ";
+
+    #[test]
+    fn deep_recursion() {
+        assert_eq!(run(SUM), Ok(Value::Int(1250025000)));
+    }
+
+    #[test]
+    fn max_depth() {
+        let (mut vm, start, _) = program(SUM);
+        vm.max_depth(100);
+
+        let error = vm.run(start, Vec::new()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::StackOverflow);
+        assert_eq!(error.message, "stack overflow");
+        assert_eq!(error.trace.len(), TRACE_LIMIT);
+        assert_eq!(error.omitted, 101 - TRACE_LIMIT);
+
+        // the overflow can be caught and the stack is usable again
+        let caught = SUM.replace(
+            "Call(sum)\n            Ret",
+            "Try\n            Call(sum)\n            Catch\n            PairLeft\n            End\n            Ret",
+        );
+        let (mut vm, start, _) = program(&caught);
+        vm.max_depth(100);
+        assert_eq!(
+            vm.run(start, Vec::new()),
+            Ok(Value::Atom(ErrorKind::StackOverflow.atom().unwrap()))
+        );
+        vm.max_depth(50001);
+        assert_eq!(vm.run(start, Vec::new()), Ok(Value::Int(1250025000)));
+    }
+
+    #[test]
+    fn errors_are_traced() {
+        let src = "
+            .start main
+            .const int 1
+            .const int 0
+            .func main 0 0
+            .name \"@main\"
+            .pos \"./main.ot\" 5 1
+                Call(f)
+                Ret
+            .func f 0 0
+            .name \"f\"
+            .pos \"./main.ot\" 2 12
+                ConstantGet(0)
+            .pos \"./main.ot\" 2 15
+                Call(g)
+                Add
+                Ret
+            .func g 0 0
+            .name \"g\"
+            .pos \"./main.ot\" 1 12
+                ConstantGet(0)
+                ConstantGet(1)
+            .pos \"./main.ot\" 1 13
+                Div
+                Ret
+        ";
+
+        let error = run(src).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Arithmetic);
+        assert_eq!(
+            error.to_string(),
+            "Division by zero\n    at g (./main.ot:1:13)\n    at f (./main.ot:2:15)\n    at @main (./main.ot:5:1)"
+        );
+        assert_eq!(error.omitted, 0);
+
+        // functions are numbered without debug information
+        let stripped = src
+            .lines()
+            .filter(|line| {
+                !line.trim_start().starts_with(".name") && !line.trim_start().starts_with(".pos")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let error = run(&stripped).unwrap_err();
+        assert_eq!(
+            error.trace,
+            vec!["function #2", "function #1", "function #0"]
+        );
+    }
+
+    #[test]
+    fn nested_handlers() {
+        // the inner handler gets the index error and throws the kind to the outer one
+        let result = expression(
+            &["int 1", "int 3"],
+            &[
+                "Try",
+                "Try",
+                "ConstantGet(0)",
+                "NewArrayDyn",
+                "ConstantGet(1)",
+                "ConstantNil",
+                "ArraySet",
+                "Catch",
+                "PairLeft",
+                "Throw",
+                "End",
+                "Catch",
+                "End",
+            ],
+        );
+        assert_eq!(result, Ok(Value::Atom(ErrorKind::Index.atom().unwrap())));
+
+        // values thrown out of the handler aren't caught by it
+        let error = expression(
+            &["int 5"],
+            &[
+                "Try",
+                "Atom(3)",
+                "Throw",
+                "Catch",
+                "ConstantGet(0)",
+                "Add",
+                "Throw",
+                "End",
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Type);
+    }
+}
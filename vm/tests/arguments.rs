@@ -0,0 +1,31 @@
+use std::process::Command;
+
+// Exit status and error output of `otvm` started with the arguments
+fn otvm(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_otvm"))
+        .args(args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    return (output.status.code().unwrap(), stderr);
+}
+
+#[test]
+fn invalid_arguments_are_reported() {
+    let (status, stderr) = otvm(&[]);
+    assert_eq!(status, 1);
+    assert!(stderr.contains("missing bytecode file"));
+
+    let (status, stderr) = otvm(&["prog.out", "--jti"]);
+    assert_eq!(status, 1);
+    assert!(stderr.contains("unknown argument `--jti`"));
+
+    for args in [
+        &["prog.out", "--max-depth", "deep"][..],
+        &["prog.out", "--max-depth"],
+    ] {
+        let (status, stderr) = otvm(args);
+        assert_eq!(status, 1);
+        assert!(stderr.contains("`--max-depth` expects a number"));
+    }
+}